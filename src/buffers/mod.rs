@@ -3,33 +3,124 @@
 //! implementation. Buffers offer two main functionalities:
 //! - They are ordered and can be peeked.
 //! - They can drop data or block if full depending on configuration.
+pub mod shared_buffer;
 pub mod single_buffers;
 pub mod synchronizers;
+pub mod wal;
 
 use crate::channels::Packet;
 use crate::packet::ChannelID;
 use crate::packet::DataVersion;
 use crate::packet::UntypedPacket;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type PacketBufferAddress = (ChannelID, DataVersion);
+
+/// Formats the address of a packet as `channel@timestamp`, e.g. `camera@1.5s`.
+pub fn format_address((channel, version): &PacketBufferAddress) -> String {
+    format!("{channel}@{version}")
+}
 pub type PacketWithAddress = (PacketBufferAddress, UntypedPacket);
 pub type TypedPacketWithAddress<T> = (PacketBufferAddress, Packet<T>);
 
 #[derive(Debug, Error, PartialEq, Clone)]
 pub enum BufferError {
-    #[error("Data was received in channel {0:?} with an already existing version.")]
+    #[error("Data was received at {} with an already existing version.", format_address(.0))]
     DuplicateDataVersionError(PacketBufferAddress),
-    #[error("Trying to create a channel which already exists {0:?}.")]
+    #[error("Trying to create a channel which already exists {0}.")]
     DuplicateChannelError(ChannelID),
     #[error("Problem while processing data: {0:?}.")]
     InternalError(String),
     #[error("Buffer is full")]
     BufferFull,
-    #[error(
-        "Trying to insert data returned out of order. Min version {0:?}, trying to insert {1:?}"
-    )]
-    OutOfOrder(u128, u128),
+    #[error("Trying to insert data returned out of order. Min version {1}, trying to insert {0}")]
+    OutOfOrder(DataVersion, DataVersion),
+    #[error("Trying to insert version {0} older than the latest consumed version {1}")]
+    VersionRegression(DataVersion, DataVersion),
+    #[error("Error while accessing the write-ahead log: {0}")]
+    WalError(String),
+    #[error("Buffer capacity must be at least 1, a buffer of size 0 drops every packet.")]
+    InvalidCapacity,
+}
+
+/// Counters of the packets going through a buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Packets inserted into the buffer.
+    pub inserted: u64,
+    /// Packets dropped without being consumed, because the buffer was full
+    /// or because they were skipped during synchronization.
+    pub evicted: u64,
+    /// Packets returned to consumers.
+    pub consumed: u64,
+}
+
+/// Statistics of the time between the versions of consecutive packets inserted in
+/// a buffer, e.g. to check that a sensor produces at a steady rate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ArrivalStats {
+    /// Number of deltas measured, one less than the packets inserted in version order.
+    pub count: u64,
+    /// Mean of the deltas between consecutive versions.
+    pub mean: Duration,
+    /// Standard deviation of the deltas.
+    pub stddev: Duration,
+    /// Mean absolute difference between consecutive deltas.
+    pub jitter: Duration,
+}
+
+/// Accumulates the `ArrivalStats` of a buffer one version at a time.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ArrivalTracker {
+    last_timestamp: Option<u128>,
+    last_delta: Option<f64>,
+    count: u64,
+    mean: f64,
+    /// Sum of the squared differences from the mean, see Welford's algorithm.
+    squared_diffs: f64,
+    jitter_sum: f64,
+}
+
+impl ArrivalTracker {
+    /// Measures the delta from the previous version. Versions older than the
+    /// previous one are ignored.
+    pub(crate) fn record(&mut self, version: &DataVersion) {
+        let timestamp = version.timestamp_ns;
+        if let Some(last_timestamp) = self.last_timestamp {
+            if timestamp < last_timestamp {
+                return;
+            }
+            let delta = (timestamp - last_timestamp) as f64;
+            self.count += 1;
+            let diff = delta - self.mean;
+            self.mean += diff / self.count as f64;
+            self.squared_diffs += diff * (delta - self.mean);
+            if let Some(last_delta) = self.last_delta {
+                self.jitter_sum += (delta - last_delta).abs();
+            }
+            self.last_delta = Some(delta);
+        }
+        self.last_timestamp = Some(timestamp);
+    }
+
+    pub(crate) fn stats(&self) -> ArrivalStats {
+        if self.count == 0 {
+            return ArrivalStats::default();
+        }
+        let nanos = |value: f64| Duration::from_nanos(value.round() as u64);
+        let jitter = if self.count > 1 {
+            self.jitter_sum / (self.count - 1) as f64
+        } else {
+            0.0
+        };
+        ArrivalStats {
+            count: self.count,
+            mean: nanos(self.mean),
+            stddev: nanos((self.squared_diffs / self.count as f64).sqrt()),
+            jitter: nanos(jitter),
+        }
+    }
 }
 
 /// An iterator over the buffer data.
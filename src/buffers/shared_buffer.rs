@@ -0,0 +1,117 @@
+//! A buffer that can be shared between threads, for instance a producer
+//! inserting data and a consumer reading it.
+use std::sync::{Arc, PoisonError, RwLock};
+
+use super::{single_buffers::FixedSizeBuffer, BufferError};
+use crate::{channels::Packet, DataVersion};
+
+/// Wraps a `FixedSizeBuffer` behind a lock. Cloning the wrapper shares the same buffer.
+/// Reads take a read lock so that multiple consumers can access the buffer concurrently,
+/// while writes take the write lock for the duration of the operation only.
+pub struct SharedBuffer<B: FixedSizeBuffer> {
+    buffer: Arc<RwLock<B>>,
+}
+
+impl<B: FixedSizeBuffer> Clone for SharedBuffer<B> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+        }
+    }
+}
+
+impl<B: FixedSizeBuffer> SharedBuffer<B> {
+    pub fn new(buffer: B) -> Self {
+        Self {
+            buffer: Arc::new(RwLock::new(buffer)),
+        }
+    }
+
+    /// Inserts a packet into the buffer.
+    pub fn insert(&self, packet: Packet<B::Data>) -> Result<(), BufferError> {
+        self.buffer
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(packet)
+    }
+
+    /// Gets a copy of the packet with the given version.
+    pub fn get(&self, version: &DataVersion) -> Option<Packet<B::Data>>
+    where
+        B::Data: Clone,
+    {
+        self.buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(version)
+            .cloned()
+    }
+
+    /// Removes and returns the packet with the given version. Older packets
+    /// are dropped as they can't be consumed anymore.
+    pub fn consume(&self, version: &DataVersion) -> Option<Packet<B::Data>> {
+        let mut buffer = self.buffer.write().unwrap_or_else(PoisonError::into_inner);
+        if !buffer.contains_key(version) {
+            return None;
+        }
+        while let Some(packet) = buffer.pop() {
+            if packet.version == *version {
+                return Some(packet);
+            }
+            buffer.discard(packet);
+        }
+        None
+    }
+
+    /// The oldest version in the buffer.
+    pub fn peek(&self) -> Option<DataVersion> {
+        self.buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .peek()
+            .cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::single_buffers::FixedSizeBTree;
+    use crate::graph::metrics::BufferMonitor;
+    use std::thread;
+
+    #[test]
+    fn test_shared_buffer_is_shared_across_threads() {
+        let buffer = SharedBuffer::new(FixedSizeBTree::<String>::new(
+            10,
+            false,
+            BufferMonitor::default(),
+        ));
+        let producer_buffer = buffer.clone();
+        thread::spawn(move || {
+            for i in 0..3 {
+                let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+                producer_buffer.insert(packet).unwrap();
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.get(&DataVersion::new(1)).unwrap().data, "data 1");
+        assert_eq!(buffer.consume(&DataVersion::new(1)).unwrap().data, "data 1");
+        assert_eq!(buffer.peek(), Some(DataVersion::new(2)));
+        assert!(buffer.consume(&DataVersion::new(1)).is_none());
+    }
+}
@@ -1,12 +1,35 @@
-use crate::{channels::Packet, DataVersion, graph::metrics::BufferMonitor};
+use crate::{channels::{ChannelID, Packet}, DataVersion, graph::metrics::BufferMonitor};
 use ringbuffer::{AllocRingBuffer, RingBuffer, RingBufferExt, RingBufferRead, RingBufferWrite};
 
-use std::{collections::BTreeMap};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
 use super::{BufferError, BufferIterator};
 
 
 type _RingBuffer<T> = AllocRingBuffer<Packet<T>>;
 
+/// Callback receiving the packets dropped by a buffer before being consumed.
+pub type EvictionCallback<T> = Box<dyn Fn(Packet<T>) + Send + Sync>;
+
+/// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
+fn check_capacity(max_size: usize) {
+    if max_size == 0 {
+        panic!("{}", BufferError::InvalidCapacity);
+    }
+}
+
+/// Counts the packet as evicted and passes it to the eviction callback, if any.
+fn notify_evicted<T>(
+    on_evict: &Option<EvictionCallback<T>>,
+    monitor: &BufferMonitor,
+    packet: Packet<T>,
+) {
+    monitor.evicted();
+    if let Some(on_evict) = on_evict {
+        on_evict(packet);
+    }
+}
+
 pub trait LenTrait {
     /// Current length of the buffer.
     fn len(&self) -> usize;
@@ -22,6 +45,9 @@ pub trait FixedSizeBuffer: LenTrait {
     /// The data type handled.
     type Data;
 
+    /// Max number of packets the buffer can hold.
+    fn capacity(&self) -> usize;
+
     /// True if it contains a query data version.
     ///
     /// * Arguments
@@ -53,35 +79,59 @@ pub trait FixedSizeBuffer: LenTrait {
     fn insert(&mut self, packet: Packet<Self::Data>) -> Result<(), BufferError>;
     /// Peek the head of the buffer, oldest entry in the buffer.
     fn back(&self) -> Option<&DataVersion>;
+    /// Get a reference to the most recent packet in the buffer.
+    fn get_latest(&self) -> Option<&Packet<Self::Data>> {
+        self.back().and_then(|version| self.get(version))
+    }
     /// Peek the head of the buffer, oldest entry in the buffer.
     fn peek(&self) -> Option<&DataVersion>;
     /// Gets an iterator to the data starting from the end.
     fn iter(&self) -> Box<BufferIterator>;
     /// Removes the head of the buffer, oldest entry in the buffer.
     fn pop(&mut self) -> Option<Packet<Self::Data>>;
-    /// Checks if a timestamp would violate the data ordering.
+    /// Registers a callback called for each packet dropped by the buffer,
+    /// either when full or when skipped during synchronization.
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<Self::Data>);
+    /// Reports a packet removed from the buffer without being consumed.
+    fn discard(&self, packet: Packet<Self::Data>);
+    /// Checks if a version would violate the data ordering.
     /// * Arguments
     ///
-    /// `timestamp_ns` - Timestamp to test.
+    /// `version` - Version to test.
     ///
     /// * Returns
     /// Ok if the data could be inserted or error in the opposite case.
-    fn check_order(&self, timestamp_ns: u128) -> Result<(), BufferError> {
+    fn check_order(&self, version: &DataVersion) -> Result<(), BufferError> {
         if let Some(p) = self.peek() {
-            if timestamp_ns <= p.timestamp_ns {
-                return Err(BufferError::OutOfOrder(timestamp_ns, p.timestamp_ns));
+            if version <= p {
+                return Err(BufferError::OutOfOrder(*version, *p));
             }
         }
         Ok(())
     }
 }
 
+/// A buffer a ReadChannel can create for each of its channels, from the settings
+/// of the node, see `InputGenerator::create_channels`.
+pub trait BufferFactory: FixedSizeBuffer + Sized {
+    /// Creates an empty buffer.
+    ///
+    /// * Arguments
+    ///
+    /// `max_size` -  The max allowed size in the buffer.
+    /// `block_full` -  Return an error when inserting in a full buffer instead of
+    /// dropping data, for the buffers which support it.
+    /// `monitor` - True if the buffer should collect metrics.
+    fn create(max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self;
+}
+
 /// An implementation of 'FixedSizeBuffer' using a ring buffer.
 #[derive(Default)]
 pub struct RtRingBuffer<T> {
     buffer: _RingBuffer<T>,
     block_full: bool,
-    monitor: BufferMonitor
+    monitor: BufferMonitor,
+    on_evict: Option<EvictionCallback<T>>,
 }
 
 impl<T> RtRingBuffer<T> {
@@ -93,14 +143,18 @@ impl<T> RtRingBuffer<T> {
     /// `block_full` -  Block if full, it would return an error when inserting, if false,
     /// it will drop oldest data.
     /// `monitor` - True if the buffer should collect metrics.
+    ///
+    /// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
     pub fn new(mut max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self {
+        check_capacity(max_size);
         if !max_size.is_power_of_two() {
             max_size = 2_usize.pow(max_size.ilog2() / 2_usize.ilog2() + 1);
         }
         RtRingBuffer {
             buffer: _RingBuffer::with_capacity(max_size),
             block_full,
-            monitor
+            monitor,
+            on_evict: None,
         }
     }
 
@@ -109,6 +163,12 @@ impl<T> RtRingBuffer<T> {
     }
 }
 
+impl<T> BufferFactory for RtRingBuffer<T> {
+    fn create(max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self {
+        RtRingBuffer::new(max_size, block_full, monitor)
+    }
+}
+
 impl<T> LenTrait for RtRingBuffer<T> {
     fn len(&self) -> usize {
         self.buffer.len()
@@ -118,6 +178,10 @@ impl<T> LenTrait for RtRingBuffer<T> {
 impl<T> FixedSizeBuffer for RtRingBuffer<T> {
     type Data = T;
 
+    fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
     fn contains_key(&self, version: &DataVersion) -> bool {
         self.find_version(version).is_some()
     }
@@ -127,12 +191,13 @@ impl<T> FixedSizeBuffer for RtRingBuffer<T> {
     }
 
     fn insert(&mut self, packet: Packet<T>) -> Result<(), BufferError> {
-        self.check_order(packet.version.timestamp_ns)?;
+        self.check_order(&packet.version)?;
         if self.buffer.is_full() {
             if self.block_full {
                 return Err(BufferError::BufferFull);
-            } else {
+            } else if let Some(evicted) = self.buffer.dequeue() {
                 self.monitor.dec();
+                notify_evicted(&self.on_evict, &self.monitor, evicted);
             }
         }
         self.monitor.inc();
@@ -162,19 +227,435 @@ impl<T> FixedSizeBuffer for RtRingBuffer<T> {
         packet
     }
 
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<T>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn discard(&self, packet: Packet<T>) {
+        notify_evicted(&self.on_evict, &self.monitor, packet);
+    }
+
     fn iter(&self) -> Box<BufferIterator> {
         Box::new(self.buffer.iter().rev().map(|p| &p.version)) as Box<BufferIterator>
     }
 }
 
+/// An implementation of 'FixedSizeBuffer' keeping only the most recent packets.
+/// Versions are not required to be ordered and, once full, inserting a packet
+/// overwrites the oldest one. Useful for latest-value channels which are sampled
+/// irregularly.
+pub struct FixedSizeRingBuffer<T> {
+    buffer: VecDeque<Packet<T>>,
+    max_size: usize,
+    monitor: BufferMonitor,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T> FixedSizeRingBuffer<T> {
+    /// Creates a new instance.
+    ///
+    /// * Arguments
+    ///
+    /// `max_size` -  The max allowed size in the buffer.
+    /// `monitor` - True if the buffer should collect metrics.
+    ///
+    /// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
+    pub fn new(max_size: usize, monitor: BufferMonitor) -> Self {
+        check_capacity(max_size);
+        FixedSizeRingBuffer {
+            buffer: VecDeque::with_capacity(max_size),
+            max_size,
+            monitor,
+            on_evict: None,
+        }
+    }
+
+    fn find_version(&self, version: &DataVersion) -> Option<&Packet<T>> {
+        self.buffer.iter().rev().find(|packet| packet.version == *version)
+    }
+}
+
+/// The oldest packet is always overwritten, `block_full` is ignored.
+impl<T> BufferFactory for FixedSizeRingBuffer<T> {
+    fn create(max_size: usize, _block_full: bool, monitor: BufferMonitor) -> Self {
+        FixedSizeRingBuffer::new(max_size, monitor)
+    }
+}
+
+impl<T> LenTrait for FixedSizeRingBuffer<T> {
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+impl<T> FixedSizeBuffer for FixedSizeRingBuffer<T> {
+    type Data = T;
+
+    fn capacity(&self) -> usize {
+        self.max_size
+    }
+
+    fn contains_key(&self, version: &DataVersion) -> bool {
+        self.find_version(version).is_some()
+    }
+
+    fn get(&self, version: &DataVersion) -> Option<&Packet<T>> {
+        self.find_version(version)
+    }
+
+    fn insert(&mut self, packet: Packet<T>) -> Result<(), BufferError> {
+        if self.buffer.len() >= self.max_size {
+            if let Some(evicted) = self.buffer.pop_front() {
+                self.monitor.dec();
+                notify_evicted(&self.on_evict, &self.monitor, evicted);
+            }
+        }
+        self.buffer.push_back(packet);
+        self.monitor.inc();
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&DataVersion> {
+        self.buffer.front().map(|packet| &packet.version)
+    }
+
+    fn back(&self) -> Option<&DataVersion> {
+        self.buffer.back().map(|packet| &packet.version)
+    }
+
+    fn pop(&mut self) -> Option<Packet<T>> {
+        let packet = self.buffer.pop_front();
+        if packet.is_some() {
+            self.monitor.dec();
+        }
+        packet
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<T>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn discard(&self, packet: Packet<T>) {
+        notify_evicted(&self.on_evict, &self.monitor, packet);
+    }
+
+    fn iter(&self) -> Box<BufferIterator<'_>> {
+        Box::new(self.buffer.iter().rev().map(|p| &p.version)) as Box<BufferIterator>
+    }
+}
+
+/// An implementation of 'FixedSizeBuffer' evicting by age instead of count.
+/// Each insert drops the packets older than the newest version minus the window.
+/// The number of packets kept can also be capped with `with_max_size`.
+pub struct TimeWindowBuffer<T> {
+    data: BTreeMap<DataVersion, Packet<T>>,
+    window: u64,
+    max_size: usize,
+    block_full: bool,
+    monitor: BufferMonitor,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T> TimeWindowBuffer<T> {
+    /// Creates a new instance.
+    ///
+    /// * Arguments
+    ///
+    /// `window` - Max age in nanoseconds of the data kept, relative to the newest version.
+    /// `monitor` - True if the buffer should collect metrics.
+    pub fn new(window: u64, monitor: BufferMonitor) -> Self {
+        TimeWindowBuffer {
+            data: Default::default(),
+            window,
+            max_size: usize::MAX,
+            block_full: false,
+            monitor,
+            on_evict: None,
+        }
+    }
+
+    /// Caps the number of packets kept, on top of the window.
+    ///
+    /// * Arguments
+    ///
+    /// `max_size` -  The max allowed size in the buffer.
+    /// `block_full` -  Return an error when inserting in a full buffer instead of
+    /// dropping the oldest packet.
+    ///
+    /// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
+    pub fn with_max_size(mut self, max_size: usize, block_full: bool) -> Self {
+        check_capacity(max_size);
+        self.max_size = max_size;
+        self.block_full = block_full;
+        self
+    }
+
+    /// Changes the window and drops the packets falling out of it.
+    ///
+    /// * Arguments
+    ///
+    /// `window` - Max age in nanoseconds of the data kept, relative to the newest version.
+    pub fn set_window(&mut self, window: u64) {
+        self.window = window;
+        if let Some(newest) = self.data.last_key_value().map(|(v, _)| v.timestamp_ns) {
+            self.prune(newest);
+        }
+    }
+
+    fn prune(&mut self, newest: u128) {
+        let oldest_allowed = newest.saturating_sub(self.window as u128);
+        while let Some(entry) = self.data.first_entry() {
+            if entry.key().timestamp_ns >= oldest_allowed {
+                break;
+            }
+            let evicted = entry.remove();
+            self.monitor.dec();
+            notify_evicted(&self.on_evict, &self.monitor, evicted);
+        }
+    }
+}
+
+/// The buffer has no window until one is set, e.g. with
+/// `channels.c1().buffer.set_window(window)` on the read channel.
+impl<T> BufferFactory for TimeWindowBuffer<T> {
+    fn create(max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self {
+        TimeWindowBuffer::new(u64::MAX, monitor).with_max_size(max_size, block_full)
+    }
+}
+
+impl<T> LenTrait for TimeWindowBuffer<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<T> FixedSizeBuffer for TimeWindowBuffer<T> {
+    type Data = T;
+
+    /// Unless capped with `with_max_size` the buffer is only bounded by age.
+    fn capacity(&self) -> usize {
+        self.max_size
+    }
+
+    fn contains_key(&self, version: &DataVersion) -> bool {
+        self.data.contains_key(version)
+    }
+
+    fn get(&self, version: &DataVersion) -> Option<&Packet<T>> {
+        self.data.get(version)
+    }
+
+    fn insert(&mut self, packet: Packet<T>) -> Result<(), BufferError> {
+        self.check_order(&packet.version)?;
+        if self.data.len() >= self.max_size {
+            if self.block_full {
+                return Err(BufferError::BufferFull);
+            } else if let Some((_, evicted)) = self.data.pop_first() {
+                self.monitor.dec();
+                notify_evicted(&self.on_evict, &self.monitor, evicted);
+            }
+        }
+        let newest = packet.version.timestamp_ns;
+        self.data.insert(packet.version, packet);
+        self.monitor.inc();
+        self.prune(newest);
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&DataVersion> {
+        self.data.first_key_value().map(|(version, _)| version)
+    }
+
+    fn back(&self) -> Option<&DataVersion> {
+        self.data.last_key_value().map(|(version, _)| version)
+    }
+
+    fn pop(&mut self) -> Option<Packet<T>> {
+        let packet = self.data.pop_first().map(|(_, packet)| packet);
+        if packet.is_some() {
+            self.monitor.dec();
+        }
+        packet
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<T>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn discard(&self, packet: Packet<T>) {
+        notify_evicted(&self.on_evict, &self.monitor, packet);
+    }
+
+    fn iter(&self) -> Box<BufferIterator<'_>> {
+        Box::new(self.data.values().rev().map(|p| &p.version)) as Box<BufferIterator>
+    }
+}
+
+/// An implementation of 'FixedSizeBuffer' working as a cache. Data is indexed by
+/// version but, once full, the least recently inserted or read entry is evicted
+/// instead of the oldest version. Versions are not required to be ordered.
+pub struct LruBuffer<T> {
+    data: BTreeMap<DataVersion, Packet<T>>,
+    max_size: usize,
+    access: Mutex<AccessIndex>,
+    monitor: BufferMonitor,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+/// Orders the versions in a `LruBuffer` by their last access. Reads go through a
+/// shared reference, so the index sits behind a lock to keep the buffer `Sync`.
+#[derive(Default)]
+struct AccessIndex {
+    clock: u64,
+    last_access: BTreeMap<DataVersion, u64>,
+    by_access: BTreeMap<u64, DataVersion>,
+}
+
+impl AccessIndex {
+    fn touch(&mut self, version: DataVersion) {
+        self.clock += 1;
+        if let Some(previous) = self.last_access.insert(version, self.clock) {
+            self.by_access.remove(&previous);
+        }
+        self.by_access.insert(self.clock, version);
+    }
+
+    fn remove(&mut self, version: &DataVersion) {
+        if let Some(accessed) = self.last_access.remove(version) {
+            self.by_access.remove(&accessed);
+        }
+    }
+
+    fn pop_least_recent(&mut self) -> Option<DataVersion> {
+        let (_, version) = self.by_access.pop_first()?;
+        self.last_access.remove(&version);
+        Some(version)
+    }
+}
+
+impl<T> LruBuffer<T> {
+    /// Creates a new instance.
+    ///
+    /// * Arguments
+    ///
+    /// `max_size` -  The max allowed size in the buffer.
+    /// `monitor` - True if the buffer should collect metrics.
+    ///
+    /// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
+    pub fn new(max_size: usize, monitor: BufferMonitor) -> Self {
+        check_capacity(max_size);
+        LruBuffer {
+            data: Default::default(),
+            max_size,
+            access: Default::default(),
+            monitor,
+            on_evict: None,
+        }
+    }
+}
+
+/// Entries are always evicted by last access, `block_full` is ignored.
+impl<T> BufferFactory for LruBuffer<T> {
+    fn create(max_size: usize, _block_full: bool, monitor: BufferMonitor) -> Self {
+        LruBuffer::new(max_size, monitor)
+    }
+}
+
+impl<T> LenTrait for LruBuffer<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<T> FixedSizeBuffer for LruBuffer<T> {
+    type Data = T;
+
+    fn capacity(&self) -> usize {
+        self.max_size
+    }
+
+    fn contains_key(&self, version: &DataVersion) -> bool {
+        self.data.contains_key(version)
+    }
+
+    fn get(&self, version: &DataVersion) -> Option<&Packet<T>> {
+        let packet = self.data.get(version)?;
+        self.access.lock().unwrap().touch(*version);
+        Some(packet)
+    }
+
+    fn insert(&mut self, packet: Packet<T>) -> Result<(), BufferError> {
+        let access = self.access.get_mut().unwrap();
+        if !self.data.contains_key(&packet.version) && self.data.len() >= self.max_size {
+            if let Some(version) = access.pop_least_recent() {
+                if let Some(evicted) = self.data.remove(&version) {
+                    self.monitor.dec();
+                    notify_evicted(&self.on_evict, &self.monitor, evicted);
+                }
+            }
+        }
+        access.touch(packet.version);
+        if let Some(replaced) = self.data.insert(packet.version, packet) {
+            notify_evicted(&self.on_evict, &self.monitor, replaced);
+        } else {
+            self.monitor.inc();
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&DataVersion> {
+        self.data.first_key_value().map(|(version, _)| version)
+    }
+
+    fn back(&self) -> Option<&DataVersion> {
+        self.data.last_key_value().map(|(version, _)| version)
+    }
+
+    fn pop(&mut self) -> Option<Packet<T>> {
+        let (version, packet) = self.data.pop_first()?;
+        self.access.get_mut().unwrap().remove(&version);
+        self.monitor.dec();
+        Some(packet)
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<T>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn discard(&self, packet: Packet<T>) {
+        notify_evicted(&self.on_evict, &self.monitor, packet);
+    }
+
+    fn iter(&self) -> Box<BufferIterator<'_>> {
+        Box::new(self.data.keys().rev()) as Box<BufferIterator>
+    }
+}
+
+/// What a `FixedSizeBTree` does with a packet whose version is already buffered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Returns `BufferError::DuplicateDataVersionError` and keeps the stored packet.
+    /// The buffer doesn't know its channel, so the error carries an empty channel id
+    /// which `BufferReceiver::insert` fills in.
+    #[default]
+    Reject,
+    /// Overwrites the stored packet, e.g. with a corrected value.
+    Replace,
+    /// Keeps the stored packet and drops the new one.
+    KeepFirst,
+}
 
 /// An implementation of 'FixedSizeBuffer' using a BTree. The buffer
 /// is indexed by data version and it's ordered.
+/// A multi version packet is indexed by its first version and matches any version
+/// in its range, unless a more recent packet starts before that version.
 pub struct FixedSizeBTree<T> {
     data: BTreeMap<DataVersion, Packet<T>>,
     max_size: usize,
     block_full: bool,
-    monitor: BufferMonitor
+    monitor: BufferMonitor,
+    on_evict: Option<EvictionCallback<T>>,
+    on_duplicate: OnDuplicate,
 }
 
 impl<T> Default for FixedSizeBTree<T> {
@@ -185,7 +666,9 @@ impl<T> Default for FixedSizeBTree<T> {
             data: Default::default(),
             max_size: 1000,
             block_full: false,
-            monitor: BufferMonitor::default()
+            monitor: BufferMonitor::default(),
+            on_evict: None,
+            on_duplicate: OnDuplicate::default(),
         }
     }
 }
@@ -198,17 +681,80 @@ impl<T> FixedSizeBTree<T> {
     /// `max_size` -  The max allowed size in the buffer.
     /// `block_full` -  Block if full, it would return an error when inserting, if false,
     /// it will drop oldest data.
+    ///
+    /// Panics with `BufferError::InvalidCapacity` if `max_size` is 0.
     pub fn new(max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self {
+        check_capacity(max_size);
         FixedSizeBTree {
             data: Default::default(),
             max_size,
             block_full,
-            monitor
+            monitor,
+            on_evict: None,
+            on_duplicate: OnDuplicate::default(),
         }
     }
+
+    /// Replaces the packets in the buffer with the ones of `snapshot`, a clone
+    /// taken earlier. The settings and callbacks of the buffer are kept.
+    pub fn restore(&mut self, snapshot: Self) {
+        self.data = snapshot.data;
+        self.monitor.observe(self.data.len() as i64);
+    }
+
+    /// Removes the packets older than `version`, e.g. to skip data that can no
+    /// longer be synchronized. Multi version packets are kept while their range
+    /// reaches `version`. The removed packets are counted as evicted but are not
+    /// passed to the eviction callback.
+    ///
+    /// * Returns
+    /// The removed packets, from the oldest.
+    pub fn cleanup_before(&mut self, version: &DataVersion) -> Vec<Packet<T>> {
+        let kept = self.data.split_off(version);
+        let older = std::mem::replace(&mut self.data, kept);
+        let mut removed = vec![];
+        for (start, packet) in older {
+            if packet.end.is_some_and(|end| end >= *version) {
+                self.data.insert(start, packet);
+            } else {
+                self.monitor.evicted();
+                removed.push(packet);
+            }
+        }
+        self.monitor.observe(self.data.len() as i64);
+        removed
+    }
+
+    /// Sets what to do with a packet whose version is already in the buffer.
+    /// Defaults to `OnDuplicate::Reject`.
+    pub fn with_on_duplicate(mut self, on_duplicate: OnDuplicate) -> Self {
+        self.on_duplicate = on_duplicate;
+        self
+    }
 }
 
 
+/// Deep copies the packets, to snapshot the state of the buffer. The copy is
+/// not monitored and has no eviction callback.
+impl<T: Clone> Clone for FixedSizeBTree<T> {
+    fn clone(&self) -> Self {
+        FixedSizeBTree {
+            data: self.data.clone(),
+            max_size: self.max_size,
+            block_full: self.block_full,
+            monitor: BufferMonitor::default(),
+            on_evict: None,
+            on_duplicate: self.on_duplicate,
+        }
+    }
+}
+
+impl<T> BufferFactory for FixedSizeBTree<T> {
+    fn create(max_size: usize, block_full: bool, monitor: BufferMonitor) -> Self {
+        FixedSizeBTree::new(max_size, block_full, monitor)
+    }
+}
+
 impl<T> LenTrait for FixedSizeBTree<T> {
 
     fn len(&self) -> usize {
@@ -220,22 +766,50 @@ impl<T> LenTrait for FixedSizeBTree<T> {
 impl<T: Clone> FixedSizeBuffer for FixedSizeBTree<T> {
     type Data = T;
 
+    fn capacity(&self) -> usize {
+        self.max_size
+    }
+
     fn contains_key(&self, version: &DataVersion) -> bool {
-        self.data.contains_key(version)
+        self.get(version).is_some()
     }
 
     fn get(&self, version: &DataVersion) -> Option<&Packet<T>> {
-        self.data.get(version)
+        self.data.get(version).or_else(|| {
+            self.data
+                .range(..=*version)
+                .next_back()
+                .map(|(_, packet)| packet)
+                .filter(|packet| packet.covers(version))
+        })
     }
 
     fn insert(&mut self, packet: Packet<T>) -> Result<(), BufferError> {
-        self.check_order(packet.version.timestamp_ns)?;
+        if let Some(stored) = self.data.get_mut(&packet.version) {
+            match self.on_duplicate {
+                OnDuplicate::Reject => {
+                    return Err(BufferError::DuplicateDataVersionError((
+                        ChannelID::new(String::new()),
+                        packet.version,
+                    )))
+                }
+                OnDuplicate::Replace => {
+                    let replaced = std::mem::replace(stored, packet);
+                    notify_evicted(&self.on_evict, &self.monitor, replaced);
+                }
+                OnDuplicate::KeepFirst => notify_evicted(&self.on_evict, &self.monitor, packet),
+            }
+            return Ok(());
+        }
+        self.check_order(&packet.version)?;
         while self.data.len() >= self.max_size {
             if self.block_full {
                 return Err(BufferError::BufferFull);
             }
-            self.data.pop_first();
-            self.monitor.dec();
+            if let Some((_, evicted)) = self.data.pop_first() {
+                self.monitor.dec();
+                notify_evicted(&self.on_evict, &self.monitor, evicted);
+            }
         }
         self.data.insert(packet.version, packet);
         self.monitor.inc();
@@ -264,6 +838,14 @@ impl<T: Clone> FixedSizeBuffer for FixedSizeBTree<T> {
         None
     }
 
+    fn set_on_evict(&mut self, on_evict: EvictionCallback<T>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn discard(&self, packet: Packet<T>) {
+        notify_evicted(&self.on_evict, &self.monitor, packet);
+    }
+
     fn iter(&self) -> Box<BufferIterator> {
         Box::new(self.data.values().rev().map(|p| &p.version)) as Box<BufferIterator>
     }
@@ -272,6 +854,7 @@ impl<T: Clone> FixedSizeBuffer for FixedSizeBTree<T> {
 #[cfg(test)]
 mod fixed_size_buffer_tests {
     use super::*;
+    use crate::channels::read_channel::get_data;
     use crate::channels::Packet;
 
     macro_rules! param_test {
@@ -318,7 +901,7 @@ mod fixed_size_buffer_tests {
     ) {
         let max_size = 32;
         for i in 0..(max_size + 10) as u128 {
-            let version = DataVersion { timestamp_ns: i };
+            let version = DataVersion::new(i);
             let packet = Packet::<String>::new("test".to_string(), version);
             buffer.insert(packet).unwrap();
             if i >= max_size as u128 {
@@ -334,42 +917,56 @@ mod fixed_size_buffer_tests {
 
     fn test_buffer_contains_key_returns_expected<T: FixedSizeBuffer<Data = String>>(mut buffer: T) {
         for i in 0..3 {
-            let version = DataVersion { timestamp_ns: i };
+            let version = DataVersion::new(i);
             let packet = Packet::<String>::new("test".to_string(), version);
             buffer.insert(packet).unwrap();
-            assert!(buffer.contains_key(&DataVersion { timestamp_ns: i }));
+            assert!(buffer.contains_key(&DataVersion::new(i)));
         }
-        assert!(!buffer.contains_key(&DataVersion { timestamp_ns: 0 }));
+        assert!(!buffer.contains_key(&DataVersion::new(0)));
     }
 
     fn test_buffer_returns_error_if_data_out_of_order<T: FixedSizeBuffer<Data = String>>(
         mut buffer: T,
     ) {
-        let version = DataVersion { timestamp_ns: 1 };
+        let version = DataVersion::new(1);
         let packet = Packet::<String>::new("test".to_string(), version);
         buffer.insert(packet).unwrap();
-        assert!(buffer.contains_key(&DataVersion { timestamp_ns: 1 }));
+        assert!(buffer.contains_key(&DataVersion::new(1)));
 
-        let version = DataVersion { timestamp_ns: 0 };
+        let version = DataVersion::new(0);
         let packet = Packet::<String>::new("test".to_string(), version);
         assert!(buffer.insert(packet).is_err());
+
+        let version = DataVersion::new(0).with_sequence(2);
+        let packet = Packet::<String>::new("test".to_string(), version);
+        assert_eq!(
+            buffer.insert(packet).unwrap_err().to_string(),
+            "Trying to insert data returned out of order. Min version 1ns, trying to insert 0ns#2"
+        );
+
+        let version = DataVersion::new(1).with_sequence(1);
+        let packet = Packet::<String>::new("test".to_string(), version);
+        buffer.insert(packet).unwrap();
     }
 
     fn test_buffer_get_returns_expected_data<T: FixedSizeBuffer<Data = String>>(mut buffer: T) {
+        assert!(buffer.get_latest().is_none());
         for i in 0..3 {
-            let version = DataVersion { timestamp_ns: i };
+            let version = DataVersion::new(i);
             let packet = Packet::<String>::new(format!("test {}", i).to_string(), version);
             buffer.insert(packet).unwrap();
-            let data = buffer.get(&DataVersion { timestamp_ns: i }).unwrap();
+            let data = buffer.get(&DataVersion::new(i)).unwrap();
             assert_eq!(*data.data, format!("test {}", i).to_string());
+            assert_eq!(buffer.get_latest().unwrap().version, DataVersion::new(i));
         }
     }
 
     fn test_buffer_insert_returns_errr_if_full_and_block<T: FixedSizeBuffer<Data = String>>(
         mut buffer: T,
     ) {
+        assert_eq!(buffer.capacity(), 2);
         for i in 0..3 {
-            let version = DataVersion { timestamp_ns: i };
+            let version = DataVersion::new(i);
             let packet = Packet::<String>::new(format!("test {}", i).to_string(), version);
             if i == 2 {
                 assert_eq!(buffer.insert(packet).unwrap_err(), BufferError::BufferFull);
@@ -381,4 +978,251 @@ mod fixed_size_buffer_tests {
 
     param_test!(FixedSizeBTree);
     param_test!(RtRingBuffer);
+
+    #[test]
+    fn test_buffer_reports_evicted_packets() {
+        let evicted = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut buffer = FixedSizeBTree::<String>::new(2, false, BufferMonitor::default());
+        let evicted_clone = evicted.clone();
+        buffer.set_on_evict(Box::new(move |packet| {
+            evicted_clone.lock().unwrap().push(packet.version.timestamp_ns)
+        }));
+        for i in 0..4 {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(*evicted.lock().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_btree_matches_versions_within_multi_version_packet() {
+        let mut buffer = FixedSizeBTree::<String>::new(10, false, BufferMonitor::default());
+        buffer
+            .insert(Packet::new("single".to_string(), DataVersion::new(5)))
+            .unwrap();
+        buffer
+            .insert(Packet::multi_version(
+                "range".to_string(),
+                DataVersion::new(10),
+                DataVersion::new(20),
+            ))
+            .unwrap();
+        assert!(!buffer.contains_key(&DataVersion::new(7)));
+        assert_eq!(buffer.get(&DataVersion::new(10)).unwrap().data, "range");
+        assert_eq!(buffer.get(&DataVersion::new(15)).unwrap().data, "range");
+        assert!(buffer.get(&DataVersion::new(21)).is_none());
+
+        let packet = get_data(&mut buffer, &Some(DataVersion::new(15)), false).unwrap();
+        assert_eq!(packet.data, "range");
+        assert_eq!(packet.version, DataVersion::new(10));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_btree_handles_duplicate_versions_with_policy() {
+        let insert_twice = |policy| {
+            let mut buffer = FixedSizeBTree::<String>::new(10, false, BufferMonitor::default())
+                .with_on_duplicate(policy);
+            buffer
+                .insert(Packet::new("first".to_string(), DataVersion::new(1)))
+                .unwrap();
+            let result = buffer.insert(Packet::new("second".to_string(), DataVersion::new(1)));
+            assert_eq!(buffer.len(), 1);
+            (
+                result,
+                buffer.get(&DataVersion::new(1)).unwrap().data.clone(),
+            )
+        };
+        assert_eq!(
+            insert_twice(OnDuplicate::Reject),
+            (
+                Err(BufferError::DuplicateDataVersionError((
+                    ChannelID::new(String::new()),
+                    DataVersion::new(1)
+                ))),
+                "first".to_string()
+            )
+        );
+        assert_eq!(
+            insert_twice(OnDuplicate::Replace),
+            (Ok(()), "second".to_string())
+        );
+        assert_eq!(
+            insert_twice(OnDuplicate::KeepFirst),
+            (Ok(()), "first".to_string())
+        );
+    }
+
+    #[test]
+    fn test_btree_is_restored_from_snapshot() {
+        let mut buffer = FixedSizeBTree::<String>::new(10, false, BufferMonitor::default());
+        for i in 0..3 {
+            buffer
+                .insert(Packet::new(format!("test {i}"), DataVersion::new(i)))
+                .unwrap();
+        }
+        let snapshot = buffer.clone();
+        buffer.pop();
+        buffer
+            .insert(Packet::new("test 3".to_string(), DataVersion::new(3)))
+            .unwrap();
+
+        buffer.restore(snapshot);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(0)));
+        assert_eq!(buffer.get(&DataVersion::new(2)).unwrap().data, "test 2");
+        assert!(!buffer.contains_key(&DataVersion::new(3)));
+    }
+
+    #[test]
+    fn test_btree_cleanup_before_returns_removed_packets() {
+        let mut buffer = FixedSizeBTree::<String>::new(10, false, BufferMonitor::default());
+        for i in 0..4 {
+            buffer
+                .insert(Packet::new(format!("test {i}"), DataVersion::new(i)))
+                .unwrap();
+        }
+
+        let removed = buffer.cleanup_before(&DataVersion::new(2));
+        assert_eq!(
+            removed.iter().map(|packet| packet.version).collect::<Vec<_>>(),
+            vec![DataVersion::new(0), DataVersion::new(1)]
+        );
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(2)));
+        assert!(buffer.cleanup_before(&DataVersion::new(2)).is_empty());
+    }
+
+    #[test]
+    fn test_btree_cleanup_before_keeps_ranges_reaching_version() {
+        let mut buffer = FixedSizeBTree::<String>::new(10, false, BufferMonitor::default());
+        buffer
+            .insert(Packet::new("test 0".to_string(), DataVersion::new(0)))
+            .unwrap();
+        buffer
+            .insert(Packet::multi_version(
+                "range".to_string(),
+                DataVersion::new(1),
+                DataVersion::new(5),
+            ))
+            .unwrap();
+
+        let removed = buffer.cleanup_before(&DataVersion::new(3));
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].version, DataVersion::new(0));
+        assert_eq!(buffer.get(&DataVersion::new(3)).unwrap().data, "range");
+    }
+
+    #[test]
+    #[should_panic(expected = "Buffer capacity must be at least 1")]
+    fn test_buffer_with_zero_capacity_panics() {
+        FixedSizeBTree::<String>::new(0, false, BufferMonitor::default());
+    }
+
+    #[test]
+    fn test_lru_buffer_evicts_least_recently_used() {
+        let mut buffer = LruBuffer::<String>::new(2, BufferMonitor::default());
+        for i in [5, 1] {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert!(buffer.get(&DataVersion::new(5)).is_some());
+
+        let packet = Packet::<String>::new("test 3".to_string(), DataVersion::new(3));
+        buffer.insert(packet).unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert!(!buffer.contains_key(&DataVersion::new(1)));
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(3)));
+        assert_eq!(buffer.back(), Some(&DataVersion::new(5)));
+    }
+
+    #[test]
+    fn test_lru_buffer_is_sync_and_forgets_popped_versions() {
+        fn assert_sync<B: Sync>(_: &B) {}
+
+        let mut buffer = LruBuffer::<String>::create(2, false, BufferMonitor::default());
+        assert_sync(&buffer);
+        for i in [1, 2] {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(buffer.pop().unwrap().version, DataVersion::new(1));
+
+        // Version 1 is gone from the access index, so 2 is the one evicted.
+        for i in [3, 4] {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(buffer.len(), 2);
+        assert!(!buffer.contains_key(&DataVersion::new(2)));
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(3)));
+    }
+
+    #[test]
+    fn test_time_window_buffer_drops_stale_data() {
+        let mut buffer = TimeWindowBuffer::<String>::new(100, BufferMonitor::default());
+        for i in [0, 50, 100, 120] {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(50)));
+
+        let packet = Packet::<String>::new("test 230".to_string(), DataVersion::new(230));
+        buffer.insert(packet).unwrap();
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(230)));
+        assert!(buffer.insert(Packet::new("late".to_string(), DataVersion::new(200))).is_err());
+    }
+
+    #[test]
+    fn test_time_window_buffer_caps_size_and_updates_window() {
+        let mut buffer = TimeWindowBuffer::<String>::create(2, false, BufferMonitor::default());
+        for i in [0, 50, 100] {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(buffer.capacity(), 2);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(50)));
+
+        buffer.set_window(10);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(100)));
+
+        let mut blocking = TimeWindowBuffer::<String>::new(100, BufferMonitor::default())
+            .with_max_size(1, true);
+        blocking.insert(Packet::new("first".to_string(), DataVersion::new(1))).unwrap();
+        assert_eq!(
+            blocking.insert(Packet::new("second".to_string(), DataVersion::new(2))),
+            Err(BufferError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer_overwrites_oldest_when_full() {
+        let mut buffer = FixedSizeRingBuffer::<String>::new(3, BufferMonitor::default());
+        for i in 0..5 {
+            let packet = Packet::<String>::new(format!("test {i}"), DataVersion::new(i));
+            buffer.insert(packet).unwrap();
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.peek(), Some(&DataVersion::new(2)));
+        assert_eq!(buffer.back(), Some(&DataVersion::new(4)));
+        assert!(!buffer.contains_key(&DataVersion::new(1)));
+        assert_eq!(buffer.get(&DataVersion::new(3)).unwrap().data, "test 3");
+    }
+
+    #[test]
+    fn test_ring_buffer_accepts_data_out_of_order() {
+        let mut buffer = FixedSizeRingBuffer::<String>::new(2, BufferMonitor::default());
+        buffer
+            .insert(Packet::new("test".to_string(), DataVersion::new(2)))
+            .unwrap();
+        buffer
+            .insert(Packet::new("test".to_string(), DataVersion::new(1)))
+            .unwrap();
+        assert_eq!(buffer.pop().unwrap().version, DataVersion::new(2));
+        assert_eq!(buffer.pop().unwrap().version, DataVersion::new(1));
+        assert!(buffer.pop().is_none());
+    }
 }
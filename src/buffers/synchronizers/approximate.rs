@@ -0,0 +1,175 @@
+use crate::{
+    channels::{read_channel::ChannelBuffer, ChannelID},
+    DataVersion,
+};
+
+use super::{PacketSynchronizer, SyncDropReason};
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// A synchronizer matching packets whose timestamps are close but not identical,
+/// for instance a camera and an IMU sampling at different rates.
+///
+/// The candidate timestamp is the newest among the oldest packets of each channel.
+/// For each channel the packet nearest to the candidate is picked. If all of them
+/// fall within the tolerance the set is returned, otherwise the next timestamp in the
+/// buffers is tried. Packets older than a returned set are dropped when the set is read,
+/// so packets that cannot be matched within the window are discarded.
+#[derive(Debug, Default, Clone)]
+pub struct ApproximateTimeSynchronizer {
+    tolerance_ns: u64,
+    /// The candidates skipped since the last `take_dropped`.
+    dropped: Vec<SyncDropReason>,
+}
+
+impl ApproximateTimeSynchronizer {
+    /// Sets the max distance in nanoseconds between a packet and the candidate timestamp.
+    pub fn with_tolerance(mut self, tolerance_ns: u64) -> Self {
+        self.tolerance_ns = tolerance_ns;
+        self
+    }
+
+    pub fn tolerance_ns(&self) -> u64 {
+        self.tolerance_ns
+    }
+
+    /// Finds the version nearest to `target` if it is within tolerance.
+    fn nearest(&self, versions: &[DataVersion], target: u128) -> Option<DataVersion> {
+        versions
+            .iter()
+            .min_by_key(|version| version.timestamp_ns.abs_diff(target))
+            .filter(|version| version.timestamp_ns.abs_diff(target) <= self.tolerance_ns as u128)
+            .cloned()
+    }
+}
+
+impl PacketSynchronizer for ApproximateTimeSynchronizer {
+    fn synchronize(
+        &mut self,
+        ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
+    ) -> Option<HashMap<ChannelID, Option<DataVersion>>> {
+        let buffer = ordered_buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut channels = HashMap::<ChannelID, Vec<DataVersion>>::new();
+        for channel in buffer.available_channels() {
+            let versions: Vec<DataVersion> = buffer.iterator(channel)?.cloned().collect();
+            if versions.is_empty() {
+                return None;
+            }
+            channels.insert(channel.clone(), versions);
+        }
+
+        let first_candidate = channels
+            .values()
+            .filter_map(|versions| versions.last())
+            .map(|version| version.timestamp_ns)
+            .max()?;
+        let mut candidates: Vec<u128> = channels
+            .values()
+            .flatten()
+            .map(|version| version.timestamp_ns)
+            .filter(|timestamp| *timestamp >= first_candidate)
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for (skipped, target) in candidates.into_iter().enumerate() {
+            let matches: Option<HashMap<ChannelID, Option<DataVersion>>> = channels
+                .iter()
+                .map(|(channel, versions)| {
+                    self.nearest(versions, target)
+                        .map(|version| (channel.clone(), Some(version)))
+                })
+                .collect();
+            if matches.is_some() {
+                self.dropped
+                    .extend(std::iter::repeat(SyncDropReason::Timeout).take(skipped));
+                return matches;
+            }
+        }
+        None
+    }
+
+    fn take_dropped(&mut self) -> Vec<SyncDropReason> {
+        std::mem::take(&mut self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffers::synchronizers::tests::{
+            add_data, check_packet_set_contains_versions, create_test_buffer,
+        },
+        channels::read_channel::InputGenerator,
+    };
+
+    #[test]
+    fn test_approximate_synchronize_matches_nearest_within_tolerance() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = ApproximateTimeSynchronizer::default().with_tolerance(5);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 100);
+        add_data(safe_buffer.clone(), "c1".to_string(), 110);
+        add_data(safe_buffer.clone(), "c2".to_string(), 103);
+        add_data(safe_buffer.clone(), "c2".to_string(), 108);
+        assert!(test_synch.synchronize(safe_buffer.clone()).is_none());
+
+        add_data(safe_buffer.clone(), "c3".to_string(), 111);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(
+            synch.as_ref().unwrap(),
+            vec![Some(110), Some(108), Some(111)],
+        );
+    }
+
+    #[test]
+    fn test_approximate_synchronize_drops_unmatched_data() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = ApproximateTimeSynchronizer::default().with_tolerance(2);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 10);
+        add_data(safe_buffer.clone(), "c1".to_string(), 50);
+        add_data(safe_buffer.clone(), "c2".to_string(), 30);
+        add_data(safe_buffer.clone(), "c2".to_string(), 51);
+        add_data(safe_buffer.clone(), "c3".to_string(), 49);
+
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(
+            synch.as_ref().unwrap(),
+            vec![Some(50), Some(51), Some(49)],
+        );
+
+        safe_buffer
+            .write()
+            .unwrap()
+            .get_packets_for_version(&synch.unwrap(), false);
+        assert!(safe_buffer.read().unwrap().are_buffers_empty());
+    }
+
+    #[test]
+    fn test_approximate_synchronize_reports_skipped_candidates() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = ApproximateTimeSynchronizer::default().with_tolerance(2);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 10);
+        add_data(safe_buffer.clone(), "c1".to_string(), 30);
+        add_data(safe_buffer.clone(), "c2".to_string(), 20);
+        add_data(safe_buffer.clone(), "c2".to_string(), 30);
+        add_data(safe_buffer.clone(), "c3".to_string(), 20);
+        add_data(safe_buffer.clone(), "c3".to_string(), 31);
+
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(
+            synch.as_ref().unwrap(),
+            vec![Some(30), Some(30), Some(31)],
+        );
+        assert_eq!(test_synch.take_dropped(), vec![SyncDropReason::Timeout]);
+        assert!(test_synch.take_dropped().is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    channels::{read_channel::ChannelBuffer, ChannelID},
+    DataVersion,
+};
+
+use super::PacketSynchronizer;
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// A synchronizer for sample-and-hold sets, usually selected with
+/// `ReadChannel::with_sync_policy(SyncPolicy::SampleAndHold)`.
+///
+/// As soon as any channel has data, it returns the most recent version of each
+/// channel and None for the channels without data. Older packets are dropped when
+/// the set is read, versions are never required to match.
+#[derive(Debug, Default, Clone)]
+pub struct LatestSynchronizer {}
+
+impl PacketSynchronizer for LatestSynchronizer {
+    fn synchronize(
+        &mut self,
+        ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
+    ) -> Option<HashMap<ChannelID, Option<DataVersion>>> {
+        let buffer = ordered_buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if buffer.are_buffers_empty() {
+            return None;
+        }
+        Some(
+            buffer
+                .available_channels()
+                .into_iter()
+                .map(|channel| (channel.clone(), buffer.latest_version(channel).cloned()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::synchronizers::tests::{
+        add_data, check_packet_set_contains_versions, create_test_buffer,
+    };
+
+    #[test]
+    fn test_latest_synchronize_returns_newest_version_of_each_channel() {
+        let buffer = create_test_buffer();
+        let safe_buffer = Arc::new(RwLock::new(buffer));
+        let mut test_synch = LatestSynchronizer::default();
+        assert!(test_synch.synchronize(safe_buffer.clone()).is_none());
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c1".to_string(), 5);
+        add_data(safe_buffer.clone(), "c3".to_string(), 3);
+
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(5), None, Some(3)]);
+    }
+}
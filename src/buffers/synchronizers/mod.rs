@@ -6,6 +6,9 @@
 //! synchronizers can generate packet set with empty data but the processor must be ready to handle the lack of data.
 //! It's up to the user to create a pipeline with the right synchorization.
 
+pub mod approximate;
+pub mod latest;
+pub mod quorum;
 pub mod real_time;
 pub mod timestamp;
 
@@ -29,6 +32,43 @@ pub trait PacketSynchronizer: Send {
         &mut self,
         ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
     ) -> Option<HashMap<ChannelID, Option<DataVersion>>>;
+
+    /// The sets given up since the previous call, with the reason, e.g. for metrics.
+    /// Synchronizers that never give up a set return none.
+    fn take_dropped(&mut self) -> Vec<SyncDropReason> {
+        vec![]
+    }
+
+    /// Checks that the synchronizer can assemble sets from `channels`, the channels of
+    /// the ReadChannel it is attached to. Called when the node is started.
+    ///
+    /// * Returns
+    /// The reason why the channels are not supported, if any.
+    fn check_channels(&self, _channels: &[&ChannelID]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Why a set of packets never reached the processor of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncDropReason {
+    /// The set was dropped from the full work queue of the node.
+    Evicted,
+    /// The other channels had no packet within the time tolerance of the synchronizer.
+    Timeout,
+    /// Fewer channels than the quorum of the synchronizer had a packet at its version.
+    QuorumNotMet,
+}
+
+impl SyncDropReason {
+    /// The label of the reason in the metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncDropReason::Evicted => "evicted",
+            SyncDropReason::Timeout => "timeout",
+            SyncDropReason::QuorumNotMet => "quorum_not_met",
+        }
+    }
 }
 
 /// Synchronize a read channel if the minimum entry has an exact match in each channel.
@@ -109,12 +149,7 @@ pub mod tests {
         channel_id: String,
         version_timestamp: u128,
     ) {
-        let packet = Packet::<String> {
-            data: "data".to_string(),
-            version: DataVersion {
-                timestamp_ns: version_timestamp,
-            },
-        };
+        let packet = Packet::<String>::new("data".to_string(), DataVersion::new(version_timestamp));
         if channel_id == "c1" {
             buffer
                 .write()
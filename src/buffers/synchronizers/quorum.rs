@@ -0,0 +1,198 @@
+use crate::{
+    channels::{read_channel::ChannelBuffer, ChannelID},
+    DataVersion,
+};
+
+use super::{PacketSynchronizer, SyncDropReason};
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+/// A synchronizer for nodes that can work with some of their inputs missing, e.g.
+/// a fusion of intermittently available sensors. Usually selected with
+/// `ReadChannel::with_quorum`.
+///
+/// The candidate version is the oldest version that at least `quorum` channels hold.
+/// Once it is reached the set is returned as soon as every channel has the version or
+/// cannot receive it anymore, having newer data. Otherwise the missing channels are
+/// waited for up to the grace period and are None in the set. Older packets are
+/// dropped when the set is read.
+#[derive(Debug, Clone)]
+pub struct QuorumSynchronizer {
+    quorum: usize,
+    grace: Duration,
+    /// The candidate waiting for its missing channels and when it reached the quorum.
+    pending: Option<(DataVersion, Instant)>,
+    /// The versions skipped since the last `take_dropped`.
+    dropped: Vec<SyncDropReason>,
+}
+
+impl Default for QuorumSynchronizer {
+    fn default() -> Self {
+        Self {
+            quorum: 1,
+            grace: Duration::ZERO,
+            pending: None,
+            dropped: vec![],
+        }
+    }
+}
+
+impl QuorumSynchronizer {
+    /// Sets the min number of channels that must have a packet at the candidate version.
+    pub fn with_quorum(mut self, quorum: usize) -> Self {
+        assert!(quorum > 0, "The quorum must be at least one channel");
+        self.quorum = quorum;
+        self
+    }
+
+    /// Sets how long the channels missing from a set that reached the quorum are waited for.
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+}
+
+impl PacketSynchronizer for QuorumSynchronizer {
+    fn synchronize(
+        &mut self,
+        ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
+    ) -> Option<HashMap<ChannelID, Option<DataVersion>>> {
+        let buffer = ordered_buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let channels = buffer.available_channels();
+
+        let mut candidates: Vec<DataVersion> = channels
+            .iter()
+            .filter_map(|channel| buffer.iterator(channel))
+            .flatten()
+            .cloned()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        let skipped = candidates.iter().position(|version| {
+            channels
+                .iter()
+                .filter(|channel| buffer.has_version(channel, version))
+                .count()
+                >= self.quorum
+        });
+        let (skipped, version) = match skipped {
+            Some(skipped) => (skipped, candidates[skipped]),
+            None => {
+                self.pending = None;
+                return None;
+            }
+        };
+
+        let versions: HashMap<ChannelID, Option<DataVersion>> = channels
+            .iter()
+            .map(|channel| {
+                let matched = buffer.has_version(channel, &version).then_some(version);
+                ((*channel).clone(), matched)
+            })
+            .collect();
+        let waiting = versions.iter().any(|(channel, matched)| {
+            matched.is_none()
+                && !buffer
+                    .latest_version(channel)
+                    .is_some_and(|latest| *latest > version)
+        });
+        if waiting {
+            let since = match self.pending {
+                Some((pending, since)) if pending == version => since,
+                _ => Instant::now(),
+            };
+            if since.elapsed() < self.grace {
+                self.pending = Some((version, since));
+                return None;
+            }
+        }
+        self.pending = None;
+        // The older versions are dropped when the set is read.
+        self.dropped
+            .extend(std::iter::repeat(SyncDropReason::QuorumNotMet).take(skipped));
+        Some(versions)
+    }
+
+    fn take_dropped(&mut self) -> Vec<SyncDropReason> {
+        std::mem::take(&mut self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffers::synchronizers::tests::{
+            add_data, check_packet_set_contains_versions, create_test_buffer,
+        },
+        channels::read_channel::InputGenerator,
+    };
+    use std::thread;
+
+    #[test]
+    fn test_quorum_synchronize_returns_set_once_quorum_is_reached() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = QuorumSynchronizer::default().with_quorum(2);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c1".to_string(), 3);
+        assert!(test_synch.synchronize(safe_buffer.clone()).is_none());
+
+        add_data(safe_buffer.clone(), "c3".to_string(), 3);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(3), None, Some(3)]);
+        assert_eq!(
+            test_synch.take_dropped(),
+            vec![SyncDropReason::QuorumNotMet]
+        );
+
+        safe_buffer
+            .write()
+            .unwrap()
+            .get_packets_for_version(&synch.unwrap(), false);
+        assert!(safe_buffer.read().unwrap().are_buffers_empty());
+    }
+
+    #[test]
+    fn test_quorum_synchronize_waits_grace_for_missing_channels() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = QuorumSynchronizer::default()
+            .with_quorum(2)
+            .with_grace(Duration::from_millis(50));
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c2".to_string(), 2);
+        assert!(test_synch.synchronize(safe_buffer.clone()).is_none());
+
+        thread::sleep(Duration::from_millis(60));
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(2), Some(2), None]);
+    }
+
+    #[test]
+    fn test_quorum_synchronize_does_not_wait_for_channels_ahead() {
+        let safe_buffer = Arc::new(RwLock::new(create_test_buffer()));
+        let mut test_synch = QuorumSynchronizer::default()
+            .with_quorum(2)
+            .with_grace(Duration::from_secs(10));
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c2".to_string(), 2);
+        add_data(safe_buffer.clone(), "c3".to_string(), 4);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(2), Some(2), None]);
+    }
+}
@@ -83,16 +83,14 @@ impl RealTimeSynchronizer {
 /// An optional (if there is a matching tuple) vector of data versions containing the matched
 /// data. If `wait_all` is false, any channel can have a None entry.
 fn extract_matches(
-    buffers: &Vec<VecDeque<u128>>,
+    buffers: &Vec<VecDeque<DataVersion>>,
     wait_all: bool
 ) -> Option<Vec<Option<DataVersion>>> {
     let data: Vec<Option<DataVersion>> = buffers
         .iter()
         .map(|b| {
             if !b.is_empty() {
-                Some(DataVersion {
-                    timestamp_ns: b[0],
-                })
+                Some(b[0])
             } else {
                 None
             }
@@ -128,7 +126,7 @@ fn find_common_max(
     wait_all: bool,
 ) -> Option<Vec<Option<DataVersion>>> {
     let iterators_len = iterators.len();
-    let mut buffers_tolerance = vec![VecDeque::<u128>::new(); iterators_len];
+    let mut buffers_tolerance = vec![VecDeque::<DataVersion>::new(); iterators_len];
 
     let mut peekers: Vec<Peekable<&mut Box<dyn Iterator<Item = &DataVersion>>>> =
         iterators.iter_mut().map(|i| i.peekable()).collect();
@@ -162,7 +160,7 @@ fn find_common_max(
                     } else if target - min(tolerance, target) <= peek_next.timestamp_ns
                         && peek_next.timestamp_ns <= target + tolerance
                     {
-                        buffers_tolerance[i].push_back(peek_next.timestamp_ns);
+                        buffers_tolerance[i].push_back(*peek_next);
                         peek.next();
                     } else {
                         peek.next();
@@ -184,7 +182,7 @@ fn find_common_max(
                 
                 target = nt.0;
                 for b in buffers_tolerance.iter_mut() {
-                    while !b.is_empty() && b[0] > target + tolerance {
+                    while !b.is_empty() && b[0].timestamp_ns > target + tolerance {
                         b.pop_front();
                     }
                 }
@@ -5,23 +5,100 @@ use crate::{
 
 use super::{exact_synchronize, PacketSynchronizer};
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    collections::{HashMap, HashSet},
+    sync::{Arc, PoisonError, RwLock},
 };
 
 /// A synchronizer mostly used for offline computations. It always tries to match
 /// the minimum version within the ReadChannel. A data timestamp is never jumped over.
 /// It's better to use this moduler only for very determined scenarios when you are sure
 /// that data is never dropped by their producers or consumers.
+///
+/// Channels marked as optional do not need to match: a set is returned as soon as the
+/// other channels match, and the optional channels without data at that version are None.
 #[derive(Debug, Default, Clone)]
-pub struct TimestampSynchronizer {}
+pub struct TimestampSynchronizer {
+    optional_channels: HashSet<ChannelID>,
+}
+
+impl TimestampSynchronizer {
+    /// Marks `channels` as optional inputs of the ReadChannel.
+    /// At least one channel must stay required, see `check_channels`.
+    pub fn with_optional_channels(mut self, channels: &[ChannelID]) -> Self {
+        self.optional_channels.extend(channels.iter().cloned());
+        self
+    }
+
+    /// Matches the oldest version of the required channels. The data of the optional
+    /// channels older than the match is dropped, it can no longer be part of a set.
+    fn synchronize_required(
+        &self,
+        ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
+    ) -> Option<HashMap<ChannelID, Option<DataVersion>>> {
+        let buffer = ordered_buffer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (optional, required): (Vec<&ChannelID>, Vec<&ChannelID>) = buffer
+            .available_channels()
+            .into_iter()
+            .partition(|channel| self.optional_channels.contains(*channel));
+        if required.is_empty() {
+            tracing::error!("Cannot synchronize because every channel is optional");
+            return None;
+        }
+
+        let version = *buffer.peek(required.first()?)?;
+        if !required
+            .iter()
+            .all(|channel| buffer.peek(channel) == Some(&version))
+        {
+            return None;
+        }
+        let mut versions: HashMap<ChannelID, Option<DataVersion>> = required
+            .into_iter()
+            .map(|channel| (channel.clone(), Some(version)))
+            .collect();
+        let mut stale = vec![];
+        for channel in optional {
+            let matched = buffer.has_version(channel, &version).then_some(version);
+            if matched.is_none() && buffer.peek(channel).is_some_and(|oldest| *oldest < version) {
+                stale.push(channel.clone());
+            }
+            versions.insert(channel.clone(), matched);
+        }
+        drop(buffer);
+
+        if !stale.is_empty() {
+            let mut buffer = ordered_buffer
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            for channel in stale {
+                buffer.discard_before(&channel, &version);
+            }
+        }
+        Some(versions)
+    }
+}
 
 impl PacketSynchronizer for TimestampSynchronizer {
     fn synchronize(
         &mut self,
         ordered_buffer: Arc<RwLock<dyn ChannelBuffer>>,
     ) -> Option<HashMap<ChannelID, Option<DataVersion>>> {
-        exact_synchronize(ordered_buffer.clone())
+        if self.optional_channels.is_empty() {
+            return exact_synchronize(ordered_buffer.clone());
+        }
+        self.synchronize_required(ordered_buffer)
+    }
+
+    fn check_channels(&self, channels: &[&ChannelID]) -> Result<(), String> {
+        if channels
+            .iter()
+            .all(|channel| self.optional_channels.contains(*channel))
+        {
+            return Err("at least one channel must not be optional".to_string());
+        }
+        Ok(())
     }
 }
 
@@ -29,10 +106,13 @@ impl PacketSynchronizer for TimestampSynchronizer {
 mod tests {
     use super::*;
     use crate::{
-        buffers::synchronizers::tests::{
-            add_data, check_packet_set_contains_versions, create_test_buffer,
+        buffers::{
+            single_buffers::FixedSizeBuffer,
+            synchronizers::tests::{
+                add_data, check_packet_set_contains_versions, create_test_buffer,
+            },
         },
-        channels::read_channel::InputGenerator,
+        channels::{read_channel::InputGenerator, Packet},
     };
 
     #[test]
@@ -82,4 +162,101 @@ mod tests {
 
         assert!(safe_buffer.read().unwrap().are_buffers_empty());
     }
+
+    #[test]
+    fn test_timestamp_synchronize_does_not_wait_for_optional_channels() {
+        let buffer = create_test_buffer();
+        let safe_buffer = Arc::new(RwLock::new(buffer));
+        let mut test_synch =
+            TimestampSynchronizer::default().with_optional_channels(&[ChannelID::from("c2")]);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        assert!(synch.is_none());
+
+        add_data(safe_buffer.clone(), "c3".to_string(), 2);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(2), None, Some(2)]);
+        safe_buffer
+            .write()
+            .unwrap()
+            .get_packets_for_version(&synch.unwrap(), true);
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 3);
+        add_data(safe_buffer.clone(), "c2".to_string(), 3);
+        add_data(safe_buffer.clone(), "c3".to_string(), 3);
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(3); 3]);
+    }
+
+    #[test]
+    fn test_timestamp_synchronize_drops_stale_optional_data() {
+        let buffer = create_test_buffer();
+        let safe_buffer = Arc::new(RwLock::new(buffer));
+        let mut test_synch =
+            TimestampSynchronizer::default().with_optional_channels(&[ChannelID::from("c2")]);
+
+        add_data(safe_buffer.clone(), "c2".to_string(), 1);
+        add_data(safe_buffer.clone(), "c2".to_string(), 4);
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c3".to_string(), 2);
+
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(2), None, Some(2)]);
+        assert_eq!(
+            safe_buffer.read().unwrap().peek(&ChannelID::from("c2")),
+            Some(&DataVersion::new(4))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_synchronize_needs_a_required_channel() {
+        let buffer = create_test_buffer();
+        let channels = [ChannelID::from("c1"), ChannelID::from("c2"), ChannelID::from("c3")];
+        let safe_buffer = Arc::new(RwLock::new(buffer));
+        let mut test_synch = TimestampSynchronizer::default().with_optional_channels(&channels);
+        assert!(test_synch
+            .check_channels(&channels.iter().collect::<Vec<_>>())
+            .is_err());
+        assert!(test_synch
+            .check_channels(&channels[1..].iter().collect::<Vec<_>>())
+            .is_err());
+        assert!(TimestampSynchronizer::default()
+            .with_optional_channels(&channels[1..])
+            .check_channels(&channels.iter().collect::<Vec<_>>())
+            .is_ok());
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 1);
+        assert!(test_synch.synchronize(safe_buffer).is_none());
+    }
+
+    #[test]
+    fn test_timestamp_synchronize_skips_gaps() {
+        let buffer = create_test_buffer();
+        let safe_buffer = Arc::new(RwLock::new(buffer));
+        let mut test_synch = TimestampSynchronizer::default();
+
+        add_data(safe_buffer.clone(), "c1".to_string(), 2);
+        add_data(safe_buffer.clone(), "c3".to_string(), 2);
+        safe_buffer
+            .write()
+            .unwrap()
+            .c2()
+            .buffer
+            .insert(Packet::gap(String::new(), DataVersion::new(2)))
+            .unwrap();
+
+        let synch = test_synch.synchronize(safe_buffer.clone());
+        check_packet_set_contains_versions(synch.as_ref().unwrap(), vec![Some(2); 3]);
+
+        let packet_set = safe_buffer
+            .write()
+            .unwrap()
+            .get_packets_for_version(&synch.unwrap(), true)
+            .unwrap();
+        assert!(packet_set.c1().is_some());
+        assert!(packet_set.c2().is_none());
+        assert!(packet_set.c3().is_some());
+        assert!(safe_buffer.read().unwrap().are_buffers_empty());
+    }
 }
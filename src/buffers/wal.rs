@@ -0,0 +1,180 @@
+//! Write-ahead log of the packets inserted into the buffers of a ReadChannel.
+//! It allows to capture the data flowing through a node and replay it later
+//! to reproduce the exact buffer states.
+//!
+//! Each entry is a frame made of a little endian u32 length followed by the
+//! serialized channel id, data version and payload.
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::BufferError;
+use crate::{
+    channels::{ChannelID, Packet},
+    DataVersion,
+};
+
+/// Callback recording a packet before it's inserted into a buffer.
+pub type PacketRecorder<T> = Box<dyn Fn(&Packet<T>) + Send + Sync>;
+
+/// Appends packets to a log file. It's shared by all the channels of a ReadChannel
+/// so that the log keeps the order of insertion across channels.
+#[derive(Clone)]
+pub struct WriteAheadLog {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl WriteAheadLog {
+    /// Creates the log file, truncating it if it already exists.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, BufferError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .map_err(wal_error)?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Appends a packet received on `channel` to the log.
+    pub fn append<T: Serialize>(
+        &self,
+        channel: &ChannelID,
+        packet: &Packet<T>,
+    ) -> Result<(), BufferError> {
+        let payload = bincode::serialize(&packet.data).map_err(wal_error)?;
+        let entry = (
+            channel.id(),
+            packet.version.timestamp_ns,
+            packet.version.sequence,
+            payload,
+        );
+        let frame = bincode::serialize(&entry).map_err(wal_error)?;
+        let frame_len = u32::try_from(frame.len())
+            .map_err(|_| BufferError::WalError("Packet is too large to log".to_string()))?;
+
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer
+            .write_u32::<LittleEndian>(frame_len)
+            .and_then(|_| writer.write_all(&frame))
+            .and_then(|_| writer.flush())
+            .map_err(wal_error)
+    }
+
+    /// Creates a recorder appending the packets of `channel` to the log.
+    /// Errors are logged and do not stop the data flow.
+    pub fn recorder<T: Serialize>(&self, channel: ChannelID) -> PacketRecorder<T> {
+        let wal = self.clone();
+        Box::new(move |packet| {
+            if let Err(err) = wal.append(&channel, packet) {
+                tracing::error!("Cannot log packet of channel {channel}: {err}");
+            }
+        })
+    }
+}
+
+/// A packet read back from the log. The payload is kept serialized until
+/// the caller knows its type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalEntry {
+    pub channel: ChannelID,
+    pub version: DataVersion,
+    payload: Vec<u8>,
+}
+
+impl WalEntry {
+    /// Deserializes the payload into a packet of type `T`.
+    pub fn packet<T: DeserializeOwned>(&self) -> Result<Packet<T>, BufferError> {
+        let data = bincode::deserialize(&self.payload).map_err(wal_error)?;
+        Ok(Packet::new(data, self.version))
+    }
+}
+
+/// Reads the entries of a log in insertion order.
+pub struct WalReader {
+    reader: BufReader<File>,
+}
+
+impl WalReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BufferError> {
+        let file = File::open(path).map_err(wal_error)?;
+        Ok(Self {
+            reader: BufReader::new(file),
+        })
+    }
+
+    fn read_entry(&mut self) -> Result<Option<WalEntry>, BufferError> {
+        let frame_len = match self.reader.read_u32::<LittleEndian>() {
+            Ok(frame_len) => frame_len,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(wal_error(err)),
+        };
+        let mut frame = vec![0; frame_len as usize];
+        self.reader.read_exact(&mut frame).map_err(wal_error)?;
+        let (channel, timestamp_ns, sequence, payload): (String, u128, u64, Vec<u8>) =
+            bincode::deserialize(&frame).map_err(wal_error)?;
+        Ok(Some(WalEntry {
+            channel: ChannelID::from(channel),
+            version: DataVersion::new(timestamp_ns).with_sequence(sequence),
+            payload,
+        }))
+    }
+}
+
+impl Iterator for WalReader {
+    type Item = Result<WalEntry, BufferError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+fn wal_error(err: impl ToString) -> BufferError {
+    BufferError::WalError(err.to_string())
+}
+
+/// A path in the temporary directory no other test uses, even when several test
+/// binaries run at the same time.
+#[cfg(test)]
+pub(crate) fn test_wal_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("rusted_pipe_{name}_{}.wal", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wal_reads_back_packets_in_order() {
+        let path = test_wal_path("test_wal_reads_back");
+        let wal = WriteAheadLog::create(&path).unwrap();
+        wal.append(
+            &ChannelID::from("c1"),
+            &Packet::new("data".to_string(), DataVersion::new(1)),
+        )
+        .unwrap();
+        let recorder = wal.recorder::<u32>(ChannelID::from("c2"));
+        recorder(&Packet::new(7, DataVersion::new(2).with_sequence(3)));
+
+        let entries = WalReader::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].channel, ChannelID::from("c1"));
+        assert_eq!(entries[0].packet::<String>().unwrap().data, "data");
+        assert_eq!(entries[1].channel, ChannelID::from("c2"));
+        let packet = entries[1].packet::<u32>().unwrap();
+        assert_eq!(packet.data, 7);
+        assert_eq!(packet.version, DataVersion::new(2).with_sequence(3));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+//! A ReadChannel with N input channels of the same type, used by nodes that merge
+//! several sources of the same data, e.g. the returns of multiple lidars.
+//!
+//! Unlike the typed ReadChannels, the matched packets are not returned in named
+//! fields but in a MergePacketSet, in the order of the channels.
+use super::read_channel::BufferReceiver;
+use super::read_channel::ChannelBuffer;
+use super::read_channel::InputGenerator;
+use super::ChannelError;
+use super::ChannelID;
+use super::LinkId;
+use super::Packet;
+use crate::buffers::single_buffers::{FixedSizeBuffer, LenTrait, RtRingBuffer};
+use crate::buffers::{ArrivalStats, BufferError, BufferIterator, BufferStats};
+use crate::graph::metrics::BufferMonitorBuilder;
+use crate::packet::typed::MergePacketSet;
+use crate::DataVersion;
+
+use crossbeam::channel::{Receiver, Select};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Reads N channels of type `T`, named `c1` to `cN` unless renamed with `with_channel_ids`.
+pub struct MergeReadChannel<T: Clone, const N: usize> {
+    receivers: Vec<BufferReceiver<RtRingBuffer<T>>>,
+    channels: Vec<ChannelID>,
+}
+
+impl<T: Clone + Send, const N: usize> MergeReadChannel<T, N> {
+    pub fn create(buffers: [RtRingBuffer<T>; N]) -> Self {
+        Self {
+            receivers: buffers
+                .into_iter()
+                .map(|buffer| BufferReceiver::new(Box::new(buffer)))
+                .collect(),
+            channels: (1..=N).map(|i| ChannelID::from(format!("c{i}"))).collect(),
+        }
+    }
+
+    /// Names the channels, in order, with the given ids instead of `c1`, `c2`, ...
+    ///
+    /// * Returns
+    ///
+    /// `BufferError::DuplicateChannelError` if an id is used twice.
+    pub fn with_channel_ids(mut self, ids: [ChannelID; N]) -> Result<Self, BufferError> {
+        let mut unique = HashSet::new();
+        if let Some(duplicate) = ids.iter().find(|id| !unique.insert(*id)) {
+            return Err(BufferError::DuplicateChannelError(duplicate.clone()));
+        }
+        self.channels = ids.to_vec();
+        Ok(self)
+    }
+
+    /// The receiver of `channel`, to link it to a WriteChannel.
+    pub fn channel(&mut self, channel: &ChannelID) -> Option<&mut BufferReceiver<RtRingBuffer<T>>> {
+        let index = self.index(channel)?;
+        Some(&mut self.receivers[index])
+    }
+
+    fn index(&self, channel: &ChannelID) -> Option<usize> {
+        self.channels.iter().position(|id| id == channel)
+    }
+
+    fn receiver(&self, channel: &ChannelID) -> Option<&BufferReceiver<RtRingBuffer<T>>> {
+        self.index(channel).map(|index| &self.receivers[index])
+    }
+
+    fn expect_linked(&self, index: usize) -> &Receiver<Packet<T>> {
+        &self.receivers[index]
+            .channel
+            .as_ref()
+            .unwrap_or_else(|| {
+                panic!(
+                    "Node MergeReadChannel has no reader channel {}",
+                    self.channels[index]
+                )
+            })
+            .receiver
+    }
+}
+
+impl<T: Clone + Send + 'static, const N: usize> ChannelBuffer for MergeReadChannel<T, N> {
+    fn available_channels(&self) -> Vec<&ChannelID> {
+        self.channels.iter().collect()
+    }
+
+    fn unlinked_channels(&self) -> Vec<&ChannelID> {
+        self.channels
+            .iter()
+            .zip(self.receivers.iter())
+            .filter(|(_, receiver)| receiver.channel.is_none())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn has_version(&self, channel: &ChannelID, version: &DataVersion) -> bool {
+        self.receiver(channel)
+            .map(|receiver| receiver.buffer.get(version).is_some())
+            .unwrap_or(false)
+    }
+
+    fn max_version(&self) -> Option<&DataVersion> {
+        self.receivers
+            .iter()
+            .filter_map(|receiver| receiver.buffer.back())
+            .max()
+    }
+
+    fn peek(&self, channel: &ChannelID) -> Option<&DataVersion> {
+        self.receiver(channel)?.buffer.peek()
+    }
+
+    fn latest_version(&self, channel: &ChannelID) -> Option<&DataVersion> {
+        self.receiver(channel)?.buffer.back()
+    }
+
+    fn iterator(&self, channel: &ChannelID) -> Option<Box<BufferIterator>> {
+        Some(self.receiver(channel)?.buffer.iter())
+    }
+
+    fn link_untyped(
+        &mut self,
+        channel: &ChannelID,
+        receiver: Box<dyn Any>,
+    ) -> Result<(), ChannelError> {
+        self.channel(channel)
+            .ok_or_else(|| ChannelError::MissingChannel(channel.clone()))?
+            .link_untyped(receiver)
+    }
+
+    fn check_link(&self, channel: &ChannelID, receiver: &dyn Any) -> Result<(), ChannelError> {
+        self.receiver(channel)
+            .ok_or_else(|| ChannelError::MissingChannel(channel.clone()))?
+            .check_link(receiver)
+    }
+
+    fn links(&self) -> Vec<(ChannelID, LinkId)> {
+        self.channels
+            .iter()
+            .zip(self.receivers.iter())
+            .filter_map(|(id, receiver)| Some((id.clone(), receiver.channel.as_ref()?.link_id())))
+            .collect()
+    }
+
+    fn are_buffers_empty(&self) -> bool {
+        self.receivers
+            .iter()
+            .all(|receiver| receiver.buffer.len() == 0)
+    }
+
+    fn occupancy(&self) -> HashMap<ChannelID, usize> {
+        self.channels
+            .iter()
+            .cloned()
+            .zip(self.receivers.iter().map(|receiver| receiver.buffer.len()))
+            .collect()
+    }
+
+    fn stats(&self, channel: &ChannelID) -> BufferStats {
+        self.receiver(channel)
+            .map(|receiver| receiver.stats())
+            .unwrap_or_default()
+    }
+
+    fn arrival_stats(&self, channel: &ChannelID) -> ArrivalStats {
+        self.receiver(channel)
+            .map(|receiver| receiver.arrival_stats())
+            .unwrap_or_default()
+    }
+
+    fn try_receive(&mut self, timeout: Duration) -> Result<Option<&ChannelID>, ChannelError> {
+        // A packet read ahead by `peek` is not seen by the select.
+        for index in 0..N {
+            if let Some(inserted) = self.receivers[index].insert_lookahead() {
+                inserted?;
+                return Ok(Some(&self.channels[index]));
+            }
+        }
+        let (index, msg) = {
+            let mut select = Select::new();
+            for index in 0..N {
+                select.recv(self.expect_linked(index));
+            }
+            match select.select_timeout(timeout) {
+                Err(_) => return Ok(None),
+                Ok(operation) => {
+                    let index = operation.index();
+                    (index, operation.recv(self.expect_linked(index)))
+                }
+            }
+        };
+        let msg = msg.map_err(|_| ChannelError::Disconnected(self.channels[index].clone()))?;
+        let receiver = &mut self.receivers[index];
+        if let Some(channel) = receiver.channel.as_ref() {
+            channel.record_received();
+        }
+        receiver.insert(msg)?;
+        Ok(Some(&self.channels[index]))
+    }
+
+    fn wait_for_data(&self, timeout: Duration) -> Result<bool, ChannelError> {
+        if self.receivers.iter().any(|receiver| receiver.has_lookahead()) {
+            return Ok(true);
+        }
+        let mut select = Select::new();
+        for index in 0..N {
+            select.recv(self.expect_linked(index));
+        }
+        match select.ready_timeout(timeout) {
+            Err(_) => Ok(false),
+            Ok(_) => Ok(true),
+        }
+    }
+}
+
+impl<T: Clone + Send, const N: usize> InputGenerator for MergeReadChannel<T, N> {
+    type INPUT = MergePacketSet<T>;
+
+    fn get_packets_for_version(
+        &mut self,
+        data_versions: &HashMap<ChannelID, Option<DataVersion>>,
+        exact_match: bool,
+    ) -> Option<Self::INPUT> {
+        let packets = self
+            .channels
+            .iter()
+            .zip(self.receivers.iter_mut())
+            .map(|(id, receiver)| {
+                let version = data_versions
+                    .get(id)
+                    .unwrap_or_else(|| panic!("Cannot find channel {}", id));
+                receiver.get_data(version, exact_match)
+            })
+            .collect();
+        Some(MergePacketSet::new(packets))
+    }
+
+    fn create_channels(
+        buffer_size: usize,
+        block_on_full: bool,
+        monitor: BufferMonitorBuilder,
+    ) -> Self {
+        Self::create_channels_with_sizes(buffer_size, &HashMap::new(), block_on_full, monitor)
+    }
+
+    fn create_channels_with_sizes(
+        buffer_size: usize,
+        channel_sizes: &HashMap<ChannelID, usize>,
+        block_on_full: bool,
+        monitor: BufferMonitorBuilder,
+    ) -> Self {
+        Self::create(std::array::from_fn(|i| {
+            let id = format!("c{}", i + 1);
+            RtRingBuffer::<T>::new(
+                channel_sizes
+                    .get(&ChannelID::from(id.as_str()))
+                    .copied()
+                    .unwrap_or(buffer_size),
+                block_on_full,
+                monitor.make_channel(&id),
+            )
+        }))
+    }
+}
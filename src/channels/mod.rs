@@ -11,17 +11,31 @@
 //! - Typed versions' data is known at compilation time and will catch graph linking at compile time.
 //! - Untyped versions instead have named channels with dynamically typed data. There is an overhead
 //! in using this channel due to type casting and are also less secure at compile time.
+pub mod merge_read_channel;
+pub mod net;
 pub mod read_channel;
 pub mod typed_read_channel;
 pub mod typed_write_channel;
 
-use crossbeam::channel::{unbounded, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+use typed_write_channel::{AlignmentRole, ReplayClock, TimeAlignment};
+
+use std::{
+    any::Any,
+    marker::PhantomData,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    time::Duration,
+};
+
+use crossbeam::channel::{
+    bounded, unbounded, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError, TrySendError,
+};
 
 pub use crate::packet::{
     ChannelID, DataVersion, Packet, PacketError, UntypedPacket, UntypedPacketCast,
 };
 use crate::{
-    buffers::BufferError,
+    buffers::{single_buffers::LenTrait, BufferError},
+    graph::metrics::ThroughputMonitor,
     packet::{work_queue::WorkQueue, Untyped},
 };
 
@@ -29,7 +43,7 @@ use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq, Clone)]
 pub enum ChannelError {
-    #[error("Trying to use a channel which does not exist, channel id {0:?}")]
+    #[error("Trying to use a channel which does not exist, channel id {0}")]
     MissingChannel(ChannelID),
     #[error("Trying to use a channel index which does not exist, channel index {0:?}")]
     MissingChannelIndex(usize),
@@ -45,21 +59,47 @@ pub enum ChannelError {
     SendError(String),
     #[error(transparent)]
     PacketError(#[from] PacketError),
-    #[error("No more data to send. Closing channel.")]
+    #[error("No more data to send. Closing channel {0}.")]
     EndOfStreamError(ChannelID),
     #[error("Error in the buffer operation.")]
     ErrorInBuffer(#[from] BufferError),
     #[error("Channel was not initialized.")]
     NotInitializedError,
+    #[error("Channel is full, cannot send more data.")]
+    ChannelFull,
+    #[error("Timed out while waiting for data.")]
+    Timeout,
+    #[error("Channel {0} is disconnected, no more data will arrive.")]
+    Disconnected(ChannelID),
+    #[error("Error in the network transport: {0}")]
+    NetworkError(String),
+    #[error("Sent {sent} packets before failing: {error}")]
+    PartialSendError { sent: usize, error: Box<ChannelError> },
+    #[error("Channel {channel} received timestamp {timestamp_ns} older than {previous_ns}")]
+    OutOfOrderVersion {
+        channel: ChannelID,
+        previous_ns: u128,
+        timestamp_ns: u128,
+    },
+}
+
+/// Thresholds coupling a writer to the length of a downstream queue. Once the queue
+/// holds `high` items the writer is congested, and it stays so until the queue
+/// drains down to `low` items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaterMarks {
+    pub high: usize,
+    pub low: usize,
 }
 
 /// Creates an untyped channel set (sender and receiver). An channel
 /// is shared between a ReadChannel and a WriteChannel. The channel has an unbounded
 /// buffer size that grows indefinitely. It can crash the application if not addressed.
 /// These buffers data is generally consumed as fast as possible by the graph.
+/// Throughput metrics can be enabled on each end with `with_metrics`.
 pub fn untyped_channel() -> (UntypedSenderChannel, UntypedReceiverChannel) {
     let (channel_sender, channel_receiver) = unbounded::<UntypedPacket>();
-    (
+    paired(
         SenderChannel::new(&channel_sender),
         ReceiverChannel::new(&channel_receiver),
     )
@@ -69,63 +109,509 @@ pub fn untyped_channel() -> (UntypedSenderChannel, UntypedReceiverChannel) {
 /// is shared between a ReadChannel and a WriteChannel. The channel has an unbounded
 /// buffer size that grows indefinitely. It can crash the application if not addressed.
 /// These buffers data is generally consumed as fast as possible by the graph.
+/// Packets are never untyped, so `T` does not need to be `'static` and can borrow
+/// its data, e.g. from a pool of buffers shared with scoped threads. This is not
+/// supported by the Graph: `link` requires `'static` data since the nodes run on the
+/// worker threads of the executor.
 pub fn typed_channel<T>() -> (SenderChannel<T>, ReceiverChannel<T>) {
     let (channel_sender, channel_receiver) = unbounded::<Packet<T>>();
-    (
+    paired(
         SenderChannel::new(&channel_sender),
         ReceiverChannel::new(&channel_receiver),
     )
 }
 
-pub type UntypedReceiverChannel = ReceiverChannel<Box<Untyped>>;
-pub type UntypedSenderChannel = SenderChannel<Box<Untyped>>;
+/// Creates an untyped channel set whose endpoints cast the data to and from `T`.
+/// The transport carries untyped packets but users send and receive `Packet<T>`
+/// without calling `to_untyped` or handling the downcast manually.
+pub fn untyped_channel_of<T: 'static>() -> (TypedSenderChannel<T>, TypedReceiverChannel<T>) {
+    let (sender, receiver) = untyped_channel();
+    (TypedSenderChannel::new(sender), TypedReceiverChannel::new(receiver))
+}
+
+/// Creates an untyped channel set (sender and receiver) with a bounded buffer.
+/// Once `capacity` packets are waiting to be read, the sender either blocks until
+/// the receiver frees a slot or returns a `ChannelError::ChannelFull` error.
+///
+/// * Arguments
+///
+/// `capacity` - Maximum number of packets that can wait in the channel.
+/// `block_full` - If true, sending blocks when full, otherwise an error is returned.
+pub fn bounded_channel(
+    capacity: usize,
+    block_full: bool,
+) -> (UntypedSenderChannel, UntypedReceiverChannel) {
+    typed_bounded_channel(capacity, block_full)
+}
+
+/// Creates a typed channel set (sender and receiver) with a bounded buffer.
+/// See `bounded_channel` for the behavior when the channel is full.
+pub fn typed_bounded_channel<T>(
+    capacity: usize,
+    block_full: bool,
+) -> (SenderChannel<T>, ReceiverChannel<T>) {
+    let (channel_sender, channel_receiver) = bounded::<Packet<T>>(capacity);
+    paired(
+        SenderChannel::new_bounded(&channel_sender, block_full),
+        ReceiverChannel::new(&channel_receiver),
+    )
+}
+
+/// Identifies a channel created with its sender and receiver, e.g. by `link`, to
+/// tell which nodes it connects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LinkId(usize);
+
+/// Lets the sender know when the receiver is dropped, see `SenderChannel::has_consumer`.
+fn paired<T>(
+    mut sender: SenderChannel<T>,
+    receiver: ReceiverChannel<T>,
+) -> (SenderChannel<T>, ReceiverChannel<T>) {
+    sender.consumer = Some(Arc::downgrade(&receiver.alive));
+    (sender, receiver)
+}
+
+pub type UntypedReceiverChannel = ReceiverChannel<Arc<Untyped>>;
+pub type UntypedSenderChannel = SenderChannel<Arc<Untyped>>;
 
 /// A receiver channel data struct.
 #[derive(Debug)]
 pub struct ReceiverChannel<T> {
     pub receiver: Receiver<Packet<T>>,
+    /// Optional id of the channel, used to report disconnections.
+    id: Option<ChannelID>,
+    /// A packet read ahead by `peek` and not yet consumed. Reading directly from
+    /// `receiver` bypasses this slot, the ReadChannels insert it before selecting.
+    lookahead: Mutex<Option<Packet<T>>>,
+    /// Counts the received packets if metrics are enabled.
+    monitor: ThroughputMonitor,
+    /// Dropped with the receiver, telling the paired sender that nobody reads the channel.
+    alive: Arc<()>,
 }
 
 impl<T> ReceiverChannel<T> {
     pub fn new(receiver: &Receiver<Packet<T>>) -> Self {
         Self {
             receiver: receiver.clone(),
+            id: None,
+            lookahead: Mutex::new(None),
+            monitor: ThroughputMonitor::default(),
+            alive: Arc::new(()),
         }
     }
+
+    /// Counts the received packets in the `packets_received_total` metric
+    /// labelled with `channel_id`.
+    pub fn with_metrics(mut self, channel_id: &str) -> Self {
+        self.monitor = ThroughputMonitor::received(channel_id);
+        self
+    }
+
+    /// Counts a packet read directly from `receiver` bypassing the channel methods.
+    pub fn record_received(&self) {
+        self.monitor.inc();
+    }
+
+    /// Identifies the channel, shared with the sender created with this receiver.
+    pub fn link_id(&self) -> LinkId {
+        // The allocation is kept by the sender while it exists, so it is not reused.
+        LinkId(Arc::as_ptr(&self.alive) as usize)
+    }
+
+    /// Assigns an id to the channel. Once known, a closed channel is reported
+    /// as `ChannelError::Disconnected` instead of a generic receive error.
+    pub fn with_id(mut self, id: ChannelID) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn id(&self) -> Option<&ChannelID> {
+        self.id.as_ref()
+    }
+
     pub fn try_receive(&self) -> Result<Packet<T>, ChannelError> {
+        if let Some(packet) = self.take_lookahead() {
+            self.monitor.inc();
+            return Ok(packet);
+        }
         match self.receiver.try_recv() {
-            Ok(packet) => Ok(packet),
+            Ok(packet) => {
+                self.monitor.inc();
+                Ok(packet)
+            }
+            Err(TryRecvError::Disconnected) => {
+                Err(self.disconnected_error(TryRecvError::Disconnected.into()))
+            }
             Err(error) => Err(ChannelError::TryReceiveError(error)),
         }
     }
+
+    /// Waits up to `timeout` for a packet to be available.
+    ///
+    /// * Returns
+    ///
+    /// The packet or a `ChannelError::Timeout` if no data arrived in time.
+    pub fn try_receive_timeout(&self, timeout: Duration) -> Result<Packet<T>, ChannelError> {
+        if let Some(packet) = self.take_lookahead() {
+            self.monitor.inc();
+            return Ok(packet);
+        }
+        match self.receiver.recv_timeout(timeout) {
+            Ok(packet) => {
+                self.monitor.inc();
+                Ok(packet)
+            }
+            Err(RecvTimeoutError::Timeout) => Err(ChannelError::Timeout),
+            Err(error) => Err(self.disconnected_error(error.into())),
+        }
+    }
+
+    /// Maps a disconnection error to `ChannelError::Disconnected` if the channel id is known.
+    fn disconnected_error(&self, error: ChannelError) -> ChannelError {
+        match self.id.as_ref() {
+            Some(id) => ChannelError::Disconnected(id.clone()),
+            None => error,
+        }
+    }
+
+    /// Reads up to `max` packets without blocking. It returns whatever is
+    /// available, which can be an empty vector.
+    pub fn try_receive_batch(&self, max: usize) -> Vec<Packet<T>> {
+        self.iter().take(max).collect()
+    }
+
+    /// An iterator over the packets currently in the channel. It stops as soon
+    /// as the channel is empty or disconnected and never blocks.
+    pub fn iter(&self) -> impl Iterator<Item = Packet<T>> + '_ {
+        self.take_lookahead()
+            .into_iter()
+            .chain(self.receiver.try_iter())
+            .inspect(|_| self.monitor.inc())
+    }
+
+    /// Returns the version of the next packet without consuming it.
+    /// The packet is kept aside and returned by the next read.
+    pub fn peek(&self) -> Option<DataVersion> {
+        let mut lookahead = self.lookahead.lock().unwrap_or_else(PoisonError::into_inner);
+        if lookahead.is_none() {
+            *lookahead = self.receiver.try_recv().ok();
+        }
+        lookahead.as_ref().map(|packet| packet.version)
+    }
+
+    /// True if a packet was read ahead by `peek` and not yet consumed.
+    fn has_lookahead(&self) -> bool {
+        self.lookahead
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    fn take_lookahead(&self) -> Option<Packet<T>> {
+        self.lookahead
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+impl<T> LenTrait for ReceiverChannel<T> {
+    /// Number of packets waiting to be read in the channel.
+    fn len(&self) -> usize {
+        let lookahead = self.lookahead.lock().unwrap_or_else(PoisonError::into_inner);
+        self.receiver.len() + usize::from(lookahead.is_some())
+    }
 }
 
 /// A sender channel data struct.
 #[derive(Debug)]
 pub struct SenderChannel<T> {
     sender: Sender<Packet<T>>,
+    /// If true, sending on a full bounded channel blocks instead of failing.
+    block_full: bool,
+    /// Counts the sent packets if metrics are enabled.
+    monitor: ThroughputMonitor,
+    /// Alive while the receiver created with the sender exists, None if it is unknown.
+    consumer: Option<Weak<()>>,
 }
 
 impl<T> SenderChannel<T> {
     pub fn new(sender: &Sender<Packet<T>>) -> Self {
+        Self::new_bounded(sender, true)
+    }
+
+    /// Creates a sender for a bounded channel.
+    ///
+    /// * Arguments
+    ///
+    /// `sender` - The crossbeam sender to wrap.
+    /// `block_full` - If true, `send` blocks when the channel is full, otherwise it errors.
+    pub fn new_bounded(sender: &Sender<Packet<T>>, block_full: bool) -> Self {
         Self {
             sender: sender.clone(),
+            block_full,
+            monitor: ThroughputMonitor::default(),
+            consumer: None,
         }
     }
+
+    /// Identifies the channel, shared with the receiver created with this sender.
+    /// None for senders wrapping a crossbeam channel directly.
+    pub fn link_id(&self) -> Option<LinkId> {
+        self.consumer
+            .as_ref()
+            .map(|consumer| LinkId(consumer.as_ptr() as usize))
+    }
+
+    /// False once the receiver created with this sender is dropped. Senders wrapping
+    /// a crossbeam channel directly cannot tell and always have a consumer.
+    pub fn has_consumer(&self) -> bool {
+        self.consumer
+            .as_ref()
+            .is_none_or(|consumer| consumer.strong_count() > 0)
+    }
+
+    /// Counts the sent packets in the `packets_sent_total` metric
+    /// labelled with `channel_id`.
+    pub fn with_metrics(mut self, channel_id: &str) -> Self {
+        self.monitor = ThroughputMonitor::sent(channel_id);
+        self
+    }
+
     pub fn send(&self, data: Packet<T>) -> Result<(), ChannelError> {
-        match self.sender.send(data) {
+        let result = self.send_packet(data);
+        if result.is_ok() {
+            self.monitor.inc();
+        }
+        result
+    }
+
+    fn send_packet(&self, data: Packet<T>) -> Result<(), ChannelError> {
+        if self.block_full {
+            return match self.sender.send(data) {
+                Ok(res) => Ok(res),
+                Err(_err) => Err(ChannelError::SendError(
+                    "Could not send because the channel is disconnected".to_string(),
+                )),
+            };
+        }
+        match self.sender.try_send(data) {
             Ok(res) => Ok(res),
-            Err(_err) => Err(ChannelError::SendError(
+            Err(TrySendError::Full(_)) => Err(ChannelError::ChannelFull),
+            Err(TrySendError::Disconnected(_)) => Err(ChannelError::SendError(
                 "Could not send because the channel is disconnected".to_string(),
             )),
         }
     }
+
+    /// Sends the packets in order and stops at the first failure.
+    ///
+    /// * Returns
+    ///
+    /// Ok if all packets were sent, otherwise a `ChannelError::PartialSendError`
+    /// with the number of packets sent before the failure.
+    pub fn send_all(&self, packets: Vec<Packet<T>>) -> Result<(), ChannelError> {
+        for (sent, packet) in packets.into_iter().enumerate() {
+            if let Err(error) = self.send(packet) {
+                return Err(ChannelError::PartialSendError {
+                    sent,
+                    error: Box::new(error),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes this end of the channel. Once all the senders of a channel are closed,
+    /// its receiver reads the remaining packets and then reports a disconnection.
+    pub fn close(self) {
+        drop(self.sender);
+    }
+}
+
+impl<T> LenTrait for SenderChannel<T> {
+    /// Number of packets sent but not yet read by the receiver.
+    fn len(&self) -> usize {
+        self.sender.len()
+    }
+}
+
+/// A sender that fans out every packet to all of its subscribed receivers.
+/// Packets are untyped once and every receiver gets a clone of the untyped
+/// packet, which shares the data instead of copying it.
+#[derive(Debug, Default)]
+pub struct BroadcastSenderChannel {
+    senders: Vec<Sender<UntypedPacket>>,
+}
+
+impl BroadcastSenderChannel {
+    /// Adds a new receiver to the broadcast. Packets sent before subscribing
+    /// are not delivered to the new receiver.
+    pub fn subscribe(&mut self) -> UntypedReceiverChannel {
+        let (channel_sender, channel_receiver) = unbounded::<UntypedPacket>();
+        self.senders.push(channel_sender);
+        ReceiverChannel::new(&channel_receiver)
+    }
+
+    /// Untypes the packet and sends it to each receiver.
+    ///
+    /// * Returns
+    ///
+    /// Ok if at least one receiver got the packet, `ChannelError::SendError` if
+    /// all receivers are disconnected.
+    pub fn send<T: 'static>(&self, data: Packet<T>) -> Result<(), ChannelError> {
+        let packet = data.to_untyped();
+        let delivered = self
+            .senders
+            .iter()
+            .filter(|sender| sender.send(packet.clone()).is_ok())
+            .count();
+        if delivered == 0 {
+            return Err(ChannelError::SendError(
+                "Could not send because all receivers are disconnected".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A sender that untypes `Packet<T>` before sending it over an untyped channel.
+#[derive(Debug)]
+pub struct TypedSenderChannel<T> {
+    sender: UntypedSenderChannel,
+    _marker: PhantomData<T>,
+}
+
+impl<T: 'static> TypedSenderChannel<T> {
+    pub fn new(sender: UntypedSenderChannel) -> Self {
+        Self {
+            sender,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn send(&self, data: Packet<T>) -> Result<(), ChannelError> {
+        self.sender.send(data.to_untyped())
+    }
+}
+
+/// A receiver that casts the untyped packets of a channel back into `Packet<T>`.
+#[derive(Debug)]
+pub struct TypedReceiverChannel<T> {
+    receiver: UntypedReceiverChannel,
+    _marker: PhantomData<T>,
+}
+
+impl<T: 'static> TypedReceiverChannel<T> {
+    pub fn new(receiver: UntypedReceiverChannel) -> Self {
+        Self {
+            receiver,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reads a packet without blocking and casts it to `T`.
+    ///
+    /// * Returns
+    ///
+    /// The typed packet or a `ChannelError::PacketError` if the data is of another type.
+    pub fn try_receive(&self) -> Result<Packet<T>, ChannelError> {
+        let packet = self.receiver.try_receive()?.deref_owned::<T>()?;
+        Ok(Packet {
+            data: *packet.data,
+            version: packet.version,
+            end: packet.end,
+            gap: packet.gap,
+            metadata: packet.metadata,
+        })
+    }
 }
 
 /// A generic trait for WriteChannels
 pub trait WriteChannelTrait {
     /// Creates a new WriteChannel.
     fn create() -> Self;
+    /// True if some receiver still reads the channel named `channel`. Processors can
+    /// skip producing the data of outputs nobody listens to, e.g. debug visualizations.
+    /// Unknown and unlinked channels have no consumers. Defaults to true, for
+    /// channels which cannot tell.
+    fn has_consumers(&self, _channel: &ChannelID) -> bool {
+        true
+    }
+    /// The channels not linked to any receiver, whose data is never consumed.
+    fn unlinked_channels(&self) -> Vec<ChannelID> {
+        vec![]
+    }
+    /// Writes an untyped packet to the channel named `channel`, casting it to the channel type.
+    ///
+    /// * Returns
+    /// A `ChannelError::MissingChannel` if there is no such channel, or a
+    /// `ChannelError::PacketError` if the data is not of the channel type.
+    fn write_untyped(
+        &mut self,
+        channel: &ChannelID,
+        _packet: UntypedPacket,
+    ) -> Result<(), ChannelError> {
+        Err(ChannelError::MissingChannel(channel.clone()))
+    }
+    /// Sends a typed packet to the channel named `channel`, for processors writing
+    /// to several outputs selected by id.
+    ///
+    /// * Returns
+    /// A `ChannelError::MissingChannel` if there is no such channel, or a
+    /// `ChannelError::PacketError` if `T` is not the channel type.
+    fn send_to<T: 'static>(
+        &mut self,
+        channel: &ChannelID,
+        packet: Packet<T>,
+    ) -> Result<(), ChannelError>
+    where
+        Self: Sized,
+    {
+        self.write_untyped(channel, packet.to_untyped())
+    }
+    /// Links the channel named `channel` to a new typed channel, for linking nodes
+    /// known only by the names of their channels, see `Graph::add_node`.
+    ///
+    /// * Returns
+    /// The `ReceiverChannel` of the new channel, to pass to `ChannelBuffer::link_untyped`,
+    /// or a `ChannelError::MissingChannel` if there is no such channel.
+    fn link_untyped(&mut self, channel: &ChannelID) -> Result<Box<dyn Any>, ChannelError> {
+        Err(ChannelError::MissingChannel(channel.clone()))
+    }
+    /// Removes the channels whose receiver was dropped, e.g. by a node removed from
+    /// a running graph.
+    fn unlink_dropped(&mut self) {}
+    /// The channels linked to each output, to derive the topology of a graph.
+    fn links(&self) -> Vec<(ChannelID, LinkId)> {
+        vec![]
+    }
+    /// True if a queue downstream of any channel is above its high water mark.
+    /// Source nodes are not called while their WriteChannel is congested.
+    fn is_congested(&self) -> bool {
+        false
+    }
+    /// Paces the writes of all the channels with `clock`.
+    fn set_replay_clock(&mut self, _clock: Arc<ReplayClock>) {}
+    /// Anchors `alignment` with the versions written on all the channels, or shifts
+    /// them by it, depending on `role`.
+    fn set_time_alignment(&mut self, _alignment: &TimeAlignment, _role: AlignmentRole) {}
+    /// Rejects the packets whose timestamp is older than the previous one written
+    /// on the same channel with a `ChannelError::OutOfOrderVersion`.
+    fn validate_version_order(&mut self) {}
+    /// Stages the packets written on all the channels until they are committed,
+    /// see `Processor::transactional_writes`. By default the packets are sent
+    /// as soon as they are written.
+    fn set_transactional(&mut self) {}
+    /// Sends the staged packets of every channel, nothing if the writes are not transactional.
+    ///
+    /// * Returns
+    /// The first error of the channels, the packets of the other channels are still sent.
+    fn commit(&mut self) -> Result<(), ChannelError> {
+        Ok(())
+    }
+    /// Drops the staged packets of every channel.
+    fn rollback(&mut self) {}
 }
 
 /// A generic trait for WriteChannels
@@ -140,8 +626,13 @@ pub trait ReadChannelTrait {
     /// all data.
     ///
     /// * Returns
-    /// A ChannelID if something was read, None otherwise.
-    fn read(&mut self, channel_id: String, done_notification: Sender<String>) -> Option<ChannelID>;
+    /// A ChannelID if something was read, None otherwise, or the error raised while
+    /// waiting for or receiving data. A disconnected channel is not an error.
+    fn read(
+        &mut self,
+        channel_id: String,
+        done_notification: Sender<String>,
+    ) -> Result<Option<ChannelID>, ChannelError>;
 
     /// Starts the channel buffer.
     ///
@@ -152,3 +643,260 @@ pub trait ReadChannelTrait {
     /// Stops the channel buffer.
     fn stop(&mut self);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_channel_returns_error_if_full_and_not_blocking() {
+        let (sender, receiver) = typed_bounded_channel::<String>(1, false);
+        let packet = Packet::new("data".to_string(), DataVersion::new(1));
+        sender.send(packet.clone()).unwrap();
+        assert_eq!(sender.send(packet).unwrap_err(), ChannelError::ChannelFull);
+
+        receiver.try_receive().unwrap();
+        let packet = Packet::new("data".to_string(), DataVersion::new(2));
+        assert!(sender.send(packet).is_ok());
+    }
+
+    #[test]
+    fn test_untyped_bounded_channel_sends_untyped_packets() {
+        let (sender, receiver) = bounded_channel(2, true);
+        let packet = Packet::new(1_u32, DataVersion::new(1)).to_untyped();
+        sender.send(packet).unwrap();
+        let received = receiver.try_receive().unwrap();
+        assert_eq!(received.version, DataVersion::new(1));
+    }
+
+    #[test]
+    fn test_send_all_sends_in_order_and_reports_partial_failures() {
+        let (sender, receiver) = typed_bounded_channel::<u128>(2, false);
+        let packets = (0..3)
+            .map(|i| Packet::new(i, DataVersion::new(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sender.send_all(packets).unwrap_err(),
+            ChannelError::PartialSendError {
+                sent: 2,
+                error: Box::new(ChannelError::ChannelFull)
+            }
+        );
+        assert_eq!(receiver.iter().map(|p| p.data).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_close_disconnects_receiver_after_remaining_data() {
+        let (sender, receiver) = typed_channel::<u128>();
+        let receiver = receiver.with_id(ChannelID::from("c1"));
+        sender.send(Packet::new(1, DataVersion::new(1))).unwrap();
+        sender.close();
+        assert_eq!(receiver.try_receive().unwrap().data, 1);
+        assert_eq!(
+            receiver.try_receive().unwrap_err(),
+            ChannelError::Disconnected(ChannelID::from("c1"))
+        );
+    }
+
+    #[test]
+    fn test_channel_with_metrics_counts_sent_and_received_packets() {
+        let (sender, receiver) = untyped_channel();
+        let sender = sender.with_metrics("test_throughput");
+        let receiver = receiver.with_metrics("test_throughput");
+        for i in 0..3 {
+            let packet = Packet::new(i, DataVersion::new(i)).to_untyped();
+            sender.send(packet).unwrap();
+        }
+        receiver.try_receive().unwrap();
+        assert_eq!(receiver.iter().count(), 2);
+        assert_eq!(ThroughputMonitor::sent("test_throughput").get(), 3);
+        assert_eq!(ThroughputMonitor::received("test_throughput").get(), 3);
+    }
+
+    #[test]
+    fn test_channel_len_returns_unread_packets() {
+        let (sender, receiver) = untyped_channel();
+        assert!(receiver.is_empty());
+        for i in 0..3 {
+            let packet = Packet::new(i, DataVersion::new(i)).to_untyped();
+            sender.send(packet).unwrap();
+        }
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(sender.len(), 3);
+
+        receiver.try_receive().unwrap();
+        assert_eq!(receiver.len(), 2);
+        assert!(!sender.is_empty());
+    }
+
+    #[test]
+    fn test_try_receive_timeout_returns_timeout_if_no_data() {
+        let (sender, receiver) = untyped_channel();
+        assert_eq!(
+            receiver
+                .try_receive_timeout(Duration::from_millis(5))
+                .unwrap_err(),
+            ChannelError::Timeout
+        );
+
+        let packet = Packet::new(1, DataVersion::new(1)).to_untyped();
+        sender.send(packet).unwrap();
+        assert!(receiver
+            .try_receive_timeout(Duration::from_millis(5))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_try_receive_batch_returns_at_most_max_packets() {
+        let (sender, receiver) = untyped_channel();
+        assert!(receiver.try_receive_batch(10).is_empty());
+        for i in 0..5 {
+            let packet = Packet::new(i, DataVersion::new(i)).to_untyped();
+            sender.send(packet).unwrap();
+        }
+        let batch = receiver.try_receive_batch(3);
+        assert_eq!(
+            batch.iter().map(|p| p.version.timestamp_ns).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(receiver.try_receive_batch(10).len(), 2);
+    }
+
+    #[test]
+    fn test_iter_stops_when_channel_is_empty_or_disconnected() {
+        let (sender, receiver) = typed_channel::<u128>();
+        for i in 0..3 {
+            sender.send(Packet::new(i, DataVersion::new(i))).unwrap();
+        }
+        let data = receiver.iter().map(|p| p.data).collect::<Vec<_>>();
+        assert_eq!(data, vec![0, 1, 2]);
+
+        sender.send(Packet::new(3, DataVersion::new(3))).unwrap();
+        drop(sender);
+        assert_eq!(receiver.iter().count(), 1);
+        assert_eq!(receiver.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_peek_does_not_consume_the_next_packet() {
+        let (sender, receiver) = typed_channel::<u128>();
+        assert!(receiver.peek().is_none());
+        for i in 1..3 {
+            sender.send(Packet::new(i, DataVersion::new(i))).unwrap();
+        }
+        assert_eq!(receiver.peek(), Some(DataVersion::new(1)));
+        assert_eq!(receiver.peek(), Some(DataVersion::new(1)));
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.try_receive().unwrap().data, 1);
+        assert_eq!(receiver.peek(), Some(DataVersion::new(2)));
+        assert_eq!(receiver.iter().map(|p| p.data).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_typed_receiver_casts_untyped_packets() {
+        let (sender, receiver) = untyped_channel_of::<String>();
+        sender
+            .send(Packet::new("data".to_string(), DataVersion::new(1)))
+            .unwrap();
+        let packet = receiver.try_receive().unwrap();
+        assert_eq!(packet.data, "data".to_string());
+        assert_eq!(packet.version, DataVersion::new(1));
+    }
+
+    #[test]
+    fn test_typed_receiver_returns_error_on_wrong_type() {
+        let (sender, receiver) = untyped_channel();
+        let receiver = TypedReceiverChannel::<String>::new(receiver);
+        sender
+            .send(Packet::new(1_u32, DataVersion::new(1)).to_untyped())
+            .unwrap();
+        assert_eq!(
+            receiver.try_receive().unwrap_err(),
+            ChannelError::PacketError(PacketError::UnexpectedDataType {
+                expected: std::any::type_name::<String>(),
+                found: std::any::type_name::<u32>(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_receive_returns_disconnected_if_sender_dropped() {
+        let (sender, receiver) = untyped_channel();
+        let receiver = receiver.with_id(ChannelID::from("c1"));
+        assert_eq!(
+            receiver.try_receive().unwrap_err(),
+            ChannelError::TryReceiveError(TryRecvError::Empty)
+        );
+        drop(sender);
+        assert_eq!(
+            receiver.try_receive().unwrap_err(),
+            ChannelError::Disconnected(ChannelID::from("c1"))
+        );
+    }
+
+    #[test]
+    fn test_typed_channel_sends_borrowed_data() {
+        let pool = vec![vec![1_u8, 2], vec![3_u8, 4]];
+        let (sender, receiver) = typed_channel::<&[u8]>();
+        let received = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for (i, buffer) in pool.iter().enumerate() {
+                    sender
+                        .send(Packet::new(buffer.as_slice(), DataVersion::new(i as u128)))
+                        .unwrap();
+                }
+                drop(sender);
+            });
+            receiver.iter().map(|packet| packet.data).collect::<Vec<_>>()
+        });
+        assert_eq!(received, vec![&[1_u8, 2][..], &[3_u8, 4][..]]);
+    }
+
+    #[test]
+    fn test_broadcast_sender_fans_out_to_all_subscribers() {
+        let mut sender = BroadcastSenderChannel::default();
+        let receivers = (0..3).map(|_| sender.subscribe()).collect::<Vec<_>>();
+        sender
+            .send(Packet::new("data".to_string(), DataVersion::new(1)))
+            .unwrap();
+        let packets = receivers
+            .iter()
+            .map(|receiver| receiver.try_receive().unwrap())
+            .collect::<Vec<_>>();
+        assert!(packets
+            .iter()
+            .all(|packet| Arc::ptr_eq(&packet.data, &packets[0].data)));
+        assert_eq!(
+            packets[0].clone().deref_cloned::<String>().unwrap().data,
+            "data"
+        );
+
+        drop(receivers);
+        assert!(sender
+            .send(Packet::new("data".to_string(), DataVersion::new(2)))
+            .is_err());
+    }
+
+    struct NoOutput;
+
+    impl WriteChannelTrait for NoOutput {
+        fn create() -> Self {
+            NoOutput
+        }
+    }
+
+    #[test]
+    fn test_write_channel_only_needs_create() {
+        let mut channel = NoOutput::create();
+        let id = ChannelID::from("c1");
+        assert!(channel.has_consumers(&id));
+        assert!(channel.unlinked_channels().is_empty());
+        assert!(!channel.is_congested());
+        assert_eq!(
+            channel.send_to(&id, Packet::new(1_u32, DataVersion::new(1))),
+            Err(ChannelError::MissingChannel(id))
+        );
+        channel.set_transactional();
+        assert!(channel.commit().is_ok());
+    }
+}
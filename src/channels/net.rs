@@ -0,0 +1,523 @@
+//! Channels that transport packets over a TCP socket. They allow splitting a pipeline
+//! across processes. The sender serializes typed packets and the receiver rebuilds them
+//! as untyped packets so that they can be consumed like any other channel.
+//!
+//! Each packet is sent as a frame made of a little endian u32 length followed by the
+//! serialized data version and payload. When it connects, the receiver first sends a
+//! frame with the version of the last packet it received, if any, so that the sender
+//! can resume the stream after a reconnection.
+use std::{
+    collections::VecDeque,
+    io::{BufReader, Read, Write},
+    marker::PhantomData,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crossbeam::channel::{unbounded, Sender};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{ChannelError, ChannelID, Packet, ReceiverChannel, UntypedPacket};
+use crate::DataVersion;
+
+/// How a `NetworkReceiverChannel` reconnects to its sender when the connection is lost.
+/// The default policy never reconnects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    max_retries: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    read_timeout: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            read_timeout: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Sets how many consecutive connection attempts are made before the channel
+    /// is reported as disconnected.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the wait before the first attempt. It doubles after each failed attempt,
+    /// up to `max_backoff`.
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets how long the socket can stay silent before the connection is considered lost.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = Some(read_timeout);
+        self
+    }
+
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// The wait before the connection attempt number `attempt`, starting from 0.
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Largest frame sent or accepted, in bytes. A peer announcing a longer frame is
+/// assumed to be broken or hostile and the connection is dropped before allocating it.
+pub const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+
+/// Sends packets of type `T` to a connected `NetworkReceiverChannel`.
+pub struct NetworkSenderChannel<T> {
+    stream: TcpStream,
+    /// The frames of the last packets sent, to send them again after a reconnection.
+    history: Mutex<VecDeque<(DataVersion, Vec<u8>)>>,
+    history_size: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Serialize> NetworkSenderChannel<T> {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            history: Mutex::new(VecDeque::new()),
+            history_size: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Blocks until a receiver connects to the listener.
+    pub fn accept(listener: &TcpListener) -> Result<Self, ChannelError> {
+        let (mut stream, _) = listener.accept().map_err(network_error)?;
+        read_resume_version(&mut stream)?;
+        Ok(Self::new(stream))
+    }
+
+    /// Keeps the last `history_size` packets sent, so that the ones the receiver
+    /// missed are sent again by `accept_reconnection`.
+    pub fn with_history(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+
+    /// Serializes the packet and writes it to the socket. The packet is kept in the
+    /// history even if the write fails.
+    pub fn send(&self, data: &Packet<T>) -> Result<(), ChannelError> {
+        let version = (data.version.timestamp_ns, data.version.sequence);
+        let frame = bincode::serialize(&(version, &data.data))
+            .map_err(|err| ChannelError::SendError(err.to_string()))?;
+        if self.history_size > 0 {
+            let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+            if history.len() == self.history_size {
+                history.pop_front();
+            }
+            history.push_back((data.version, frame.clone()));
+        }
+        write_frame(&self.stream, &frame)
+    }
+
+    /// Blocks until the receiver reconnects to the listener after the connection was lost,
+    /// then sends again the packets of the history newer than the last one it received.
+    pub fn accept_reconnection(&mut self, listener: &TcpListener) -> Result<(), ChannelError> {
+        let (mut stream, _) = listener.accept().map_err(network_error)?;
+        let resume = read_resume_version(&mut stream)?;
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        for (version, frame) in history.iter() {
+            if resume.is_none_or(|resume| *version > resume) {
+                write_frame(&stream, frame)?;
+            }
+        }
+        drop(history);
+        self.stream = stream;
+        Ok(())
+    }
+}
+
+/// Receives packets sent by a `NetworkSenderChannel`. A background thread reads
+/// the socket and pushes the rebuilt packets into a local channel.
+pub struct NetworkReceiverChannel<T> {
+    receiver: ReceiverChannel<T>,
+    _reader: JoinHandle<()>,
+}
+
+impl<T: DeserializeOwned + Send + 'static> NetworkReceiverChannel<T> {
+    /// Connects to a sender and starts reading packets of type `T`.
+    ///
+    /// * Arguments
+    ///
+    /// `addr` - Address of the listening sender.
+    /// `id` - Id of the channel, reported when the connection is closed.
+    pub fn connect(addr: impl ToSocketAddrs, id: ChannelID) -> Result<Self, ChannelError> {
+        Self::connect_with_policy(addr, id, ReconnectPolicy::default())
+    }
+
+    /// Connects to a sender and starts reading packets of type `T`, reconnecting
+    /// according to `policy` when the connection is lost. After a reconnection, the
+    /// packets already received are kept and the stream resumes after the last one.
+    /// The channel is reported as disconnected once the retries are exhausted.
+    pub fn connect_with_policy(
+        addr: impl ToSocketAddrs,
+        id: ChannelID,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, ChannelError> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs().map_err(network_error)?.collect();
+        let stream = open_stream(&addrs, None, &policy)?;
+        let (channel_sender, channel_receiver) = unbounded::<Packet<T>>();
+        let reader = thread::spawn(move || {
+            let mut last_version = None;
+            let mut stream = stream;
+            let mut resumed = false;
+            loop {
+                if !forward_packets(stream, &channel_sender, &mut last_version, resumed) {
+                    return;
+                }
+                resumed = true;
+                match reconnect(&addrs, last_version, &policy) {
+                    Some(reconnected) => stream = reconnected,
+                    None => return,
+                }
+            }
+        });
+        Ok(Self {
+            receiver: ReceiverChannel::new(&channel_receiver).with_id(id),
+            _reader: reader,
+        })
+    }
+
+    /// Starts reading packets of type `T` from an already connected stream.
+    pub fn from_stream(mut stream: TcpStream, id: ChannelID) -> Self {
+        let (channel_sender, channel_receiver) = unbounded::<Packet<T>>();
+        let reader = thread::spawn(move || {
+            if write_resume_version(&mut stream, None).is_ok() {
+                forward_packets(stream, &channel_sender, &mut None, false);
+            }
+        });
+        Self {
+            receiver: ReceiverChannel::new(&channel_receiver).with_id(id),
+            _reader: reader,
+        }
+    }
+
+    /// Reads a packet without blocking. Returns `ChannelError::Disconnected`
+    /// once the socket is closed and all received packets are consumed.
+    pub fn try_receive(&self) -> Result<UntypedPacket, ChannelError> {
+        Ok(self.receiver.try_receive()?.to_untyped())
+    }
+
+    /// The local channel holding the received packets. It can be linked to a
+    /// ReadChannel like any other typed channel.
+    pub fn receiver(&self) -> &ReceiverChannel<T> {
+        &self.receiver
+    }
+}
+
+/// Pushes the packets read from the stream into the local channel. On a stream
+/// `resumed` after a reconnection, the sender first sends its history again: the
+/// packets it replays up to `last_version` are skipped, and all the packets after
+/// the first newer one are forwarded, in whatever order they arrive.
+///
+/// * Returns
+/// False if the local channel was dropped, true once the connection is lost.
+fn forward_packets<T: DeserializeOwned>(
+    stream: TcpStream,
+    channel_sender: &Sender<Packet<T>>,
+    last_version: &mut Option<DataVersion>,
+    resumed: bool,
+) -> bool {
+    let mut stream = BufReader::new(stream);
+    let mut replayed_until = if resumed { *last_version } else { None };
+    loop {
+        let frame = match read_frame(&mut stream) {
+            Ok(frame) => frame,
+            Err(err) => {
+                tracing::warn!(error = %err, "Connection to the network sender lost");
+                return true;
+            }
+        };
+        // The frame length is known, a bad payload does not break the stream.
+        let packet = match decode_packet::<T>(&frame) {
+            Ok(packet) => packet,
+            Err(err) => {
+                tracing::error!(error = %err, "Cannot deserialize packet, skipping it");
+                continue;
+            }
+        };
+        if let Some(replayed) = replayed_until {
+            if packet.version <= replayed {
+                continue;
+            }
+            replayed_until = None;
+        }
+        *last_version = Some(packet.version);
+        if channel_sender.send(packet).is_err() {
+            return false;
+        }
+    }
+}
+
+/// Tries to connect again with the backoff of the policy.
+fn reconnect(
+    addrs: &[SocketAddr],
+    last_version: Option<DataVersion>,
+    policy: &ReconnectPolicy,
+) -> Option<TcpStream> {
+    for attempt in 0..policy.max_retries() {
+        thread::sleep(policy.backoff(attempt));
+        match open_stream(addrs, last_version, policy) {
+            Ok(stream) => return Some(stream),
+            Err(err) => {
+                tracing::warn!(attempt, error = %err, "Cannot reconnect to the network sender")
+            }
+        }
+    }
+    None
+}
+
+fn open_stream(
+    addrs: &[SocketAddr],
+    last_version: Option<DataVersion>,
+    policy: &ReconnectPolicy,
+) -> Result<TcpStream, ChannelError> {
+    let mut stream = TcpStream::connect(addrs).map_err(network_error)?;
+    stream
+        .set_read_timeout(policy.read_timeout())
+        .map_err(network_error)?;
+    write_resume_version(&mut stream, last_version)?;
+    Ok(stream)
+}
+
+fn write_frame(mut stream: &TcpStream, frame: &[u8]) -> Result<(), ChannelError> {
+    let frame_len = u32::try_from(frame.len())
+        .ok()
+        .filter(|frame_len| *frame_len <= MAX_FRAME_LEN)
+        .ok_or_else(|| ChannelError::SendError("Packet is too large to send".to_string()))?;
+    stream
+        .write_u32::<LittleEndian>(frame_len)
+        .and_then(|_| stream.write_all(frame))
+        .map_err(|err| ChannelError::SendError(err.to_string()))
+}
+
+/// Reads a single frame. `read_exact` takes care of partial reads from the socket.
+fn read_frame(stream: &mut impl Read) -> Result<Vec<u8>, ChannelError> {
+    let frame_len = stream.read_u32::<LittleEndian>().map_err(network_error)?;
+    if frame_len > MAX_FRAME_LEN {
+        return Err(ChannelError::NetworkError(format!(
+            "Frame of {frame_len} bytes is larger than the maximum of {MAX_FRAME_LEN}"
+        )));
+    }
+    let mut frame = vec![0; frame_len as usize];
+    stream.read_exact(&mut frame).map_err(network_error)?;
+    Ok(frame)
+}
+
+fn write_resume_version(
+    stream: &mut TcpStream,
+    version: Option<DataVersion>,
+) -> Result<(), ChannelError> {
+    let version = version.map(|version| (version.timestamp_ns, version.sequence));
+    let frame =
+        bincode::serialize(&version).map_err(|err| ChannelError::NetworkError(err.to_string()))?;
+    write_frame(stream, &frame)
+}
+
+fn read_resume_version(stream: &mut TcpStream) -> Result<Option<DataVersion>, ChannelError> {
+    let frame = read_frame(stream)?;
+    let version: Option<(u128, u64)> =
+        bincode::deserialize(&frame).map_err(|err| ChannelError::NetworkError(err.to_string()))?;
+    Ok(version
+        .map(|(timestamp_ns, sequence)| DataVersion::new(timestamp_ns).with_sequence(sequence)))
+}
+
+/// Rebuilds the packet serialized in a frame.
+fn decode_packet<T: DeserializeOwned>(frame: &[u8]) -> Result<Packet<T>, ChannelError> {
+    let ((timestamp_ns, sequence), data): ((u128, u64), T) =
+        bincode::deserialize(frame).map_err(|err| ChannelError::NetworkError(err.to_string()))?;
+    Ok(Packet::new(
+        data,
+        DataVersion::new(timestamp_ns).with_sequence(sequence),
+    ))
+}
+
+fn network_error(err: std::io::Error) -> ChannelError {
+    ChannelError::NetworkError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::UntypedPacketCast;
+    use std::net::Shutdown;
+    use std::time::Instant;
+
+    fn receive_until(
+        receiver: &NetworkReceiverChannel<String>,
+        deadline: Instant,
+    ) -> Result<UntypedPacket, ChannelError> {
+        loop {
+            match receiver.try_receive() {
+                Err(ChannelError::TryReceiveError(_)) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn test_network_channel_sends_packets_and_reports_disconnection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver =
+            NetworkReceiverChannel::<String>::connect(addr, ChannelID::from("c1")).unwrap();
+        let sender = NetworkSenderChannel::<String>::accept(&listener).unwrap();
+
+        for i in 0..3 {
+            sender
+                .send(&Packet::new(
+                    format!("data {i}"),
+                    DataVersion::new(i),
+                ))
+                .unwrap();
+        }
+        drop(sender);
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        for i in 0..3 {
+            let packet = receive_until(&receiver, deadline)
+                .unwrap()
+                .deref_owned::<String>()
+                .unwrap();
+            assert_eq!(*packet.data, format!("data {i}"));
+            assert_eq!(packet.version, DataVersion::new(i));
+        }
+        assert_eq!(
+            receive_until(&receiver, deadline).unwrap_err(),
+            ChannelError::Disconnected(ChannelID::from("c1"))
+        );
+    }
+
+    #[test]
+    fn test_network_receiver_reconnects_and_resumes_after_last_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let policy = ReconnectPolicy::default()
+            .with_max_retries(3)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        let receiver = NetworkReceiverChannel::<String>::connect_with_policy(
+            addr,
+            ChannelID::from("c1"),
+            policy,
+        )
+        .unwrap();
+        let mut sender = NetworkSenderChannel::<String>::accept(&listener)
+            .unwrap()
+            .with_history(10);
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        for i in 0..2 {
+            sender
+                .send(&Packet::new(format!("data {i}"), DataVersion::new(i)))
+                .unwrap();
+            let packet = receive_until(&receiver, deadline).unwrap();
+            assert_eq!(packet.version, DataVersion::new(i));
+        }
+
+        sender.stream.shutdown(Shutdown::Both).unwrap();
+        assert!(sender
+            .send(&Packet::new("data 2".to_string(), DataVersion::new(2)))
+            .is_err());
+        sender.accept_reconnection(&listener).unwrap();
+
+        let packet = receive_until(&receiver, deadline)
+            .unwrap()
+            .deref_owned::<String>()
+            .unwrap();
+        assert_eq!(*packet.data, "data 2");
+        assert_eq!(packet.version, DataVersion::new(2));
+
+        drop(sender);
+        drop(listener);
+        assert_eq!(
+            receive_until(&receiver, deadline).unwrap_err(),
+            ChannelError::Disconnected(ChannelID::from("c1"))
+        );
+    }
+
+    #[test]
+    fn test_network_receiver_forwards_out_of_order_packets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver =
+            NetworkReceiverChannel::<String>::connect(addr, ChannelID::from("c1")).unwrap();
+        let sender = NetworkSenderChannel::<String>::accept(&listener).unwrap();
+
+        for i in [2, 1, 3] {
+            sender
+                .send(&Packet::new(format!("data {i}"), DataVersion::new(i)))
+                .unwrap();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        for i in [2, 1, 3] {
+            let packet = receive_until(&receiver, deadline).unwrap();
+            assert_eq!(packet.version, DataVersion::new(i));
+        }
+    }
+
+    #[test]
+    fn test_network_receiver_skips_packets_it_cannot_deserialize() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver =
+            NetworkReceiverChannel::<String>::connect(addr, ChannelID::from("c1")).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        read_resume_version(&mut stream).unwrap();
+
+        write_frame(&stream, &[1, 2, 3]).unwrap();
+        let frame = bincode::serialize(&((1_u128, 0_u64), "data 1")).unwrap();
+        write_frame(&stream, &frame).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let packet = receive_until(&receiver, deadline).unwrap();
+        assert_eq!(packet.version, DataVersion::new(1));
+    }
+
+    #[test]
+    fn test_read_frame_rejects_frames_above_max_len() {
+        let mut stream = std::io::Cursor::new(u32::MAX.to_le_bytes());
+        assert!(matches!(
+            read_frame(&mut stream).unwrap_err(),
+            ChannelError::NetworkError(_)
+        ));
+    }
+
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_max() {
+        let policy = ReconnectPolicy::default()
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(40));
+        assert_eq!(policy.backoff(3), Duration::from_millis(50));
+        assert_eq!(policy.backoff(100), Duration::from_millis(50));
+    }
+}
@@ -3,29 +3,38 @@
 //! allocate space for the incoming data and synchronize that data using the
 //! user configured syncrhonizer.
 use std::{
+    any::{type_name, Any},
     sync::{Arc, PoisonError, RwLock},
     thread,
     time::Duration,
 };
 
+use atomic::{Atomic, Ordering};
 use crossbeam::channel::Sender;
-use log::debug;
 
 use crate::{
-    buffers::{single_buffers::RtRingBuffer, synchronizers::PacketSynchronizer},
-    graph::metrics::{BufferMonitor, BufferMonitorBuilder},
+    buffers::synchronizers::{
+        latest::LatestSynchronizer, quorum::QuorumSynchronizer, PacketSynchronizer, SyncDropReason,
+    },
+    graph::build::WorkerStatus,
+    graph::metrics::{BufferMonitor, BufferMonitorBuilder, SyncMonitor},
     packet::work_queue::WorkQueue,
 };
 
 use std::collections::HashMap;
 
 use crate::{
-    buffers::{single_buffers::FixedSizeBuffer, BufferIterator},
+    buffers::{
+        single_buffers::FixedSizeBuffer, wal::PacketRecorder, ArrivalStats, ArrivalTracker,
+        BufferError, BufferIterator, BufferStats,
+    },
     packet::typed::PacketSetTrait,
     DataVersion,
 };
 
-use super::{ChannelError, ChannelID, Packet, ReadChannelTrait, ReceiverChannel};
+use super::{
+    ChannelError, ChannelID, LinkId, Packet, PacketError, ReadChannelTrait, ReceiverChannel,
+};
 
 /// A struct that holds a single FixedSizeBuffer and
 /// an optional ReceiverChannel that maps its data into that buffer.
@@ -35,9 +44,52 @@ pub struct BufferReceiver<T: FixedSizeBuffer + ?Sized> {
     /// An optional ReceiverChannel with the data type.
     /// It can be None if the channel is not yet connected.
     pub channel: Option<ReceiverChannel<T::Data>>,
+    /// An optional recorder called with each packet before inserting it.
+    pub recorder: Option<PacketRecorder<T::Data>>,
+    /// If true, inserting a version older than the latest consumed one fails.
+    pub reject_regression: bool,
+    last_consumed: Option<DataVersion>,
+    stats: BufferStats,
+    arrivals: ArrivalTracker,
 }
 
 impl<T: FixedSizeBuffer + ?Sized> BufferReceiver<T> {
+    /// Creates a receiver not yet linked to a channel.
+    pub fn new(buffer: Box<T>) -> Self {
+        BufferReceiver {
+            buffer,
+            channel: None,
+            recorder: None,
+            reject_regression: false,
+            last_consumed: None,
+            stats: BufferStats::default(),
+            arrivals: ArrivalTracker::default(),
+        }
+    }
+
+    /// Counters of the packets inserted, evicted and consumed so far.
+    pub fn stats(&self) -> BufferStats {
+        self.stats
+    }
+
+    /// Statistics of the deltas between the versions of the packets inserted so far.
+    pub fn arrival_stats(&self) -> ArrivalStats {
+        self.arrivals.stats()
+    }
+
+    /// The version of the last packet consumed from the buffer, the watermark
+    /// to checkpoint for resuming after a restart.
+    pub fn last_consumed(&self) -> Option<DataVersion> {
+        self.last_consumed
+    }
+
+    /// Resumes from a checkpointed watermark: the versions up to and including
+    /// `version` are considered consumed and inserting them is rejected.
+    pub fn resume_from(&mut self, version: DataVersion) {
+        self.last_consumed = Some(version);
+        self.reject_regression = true;
+    }
+
     /// Link a receiver channel to a data transport. From now on
     /// the channel can start reading data.
     pub fn link(&mut self, receiver: ReceiverChannel<T::Data>) {
@@ -47,17 +99,164 @@ impl<T: FixedSizeBuffer + ?Sized> BufferReceiver<T> {
         self.channel = Some(receiver);
     }
 
+    /// Links the buffer to `receiver` in place of the channel it was linked to.
+    /// The packets left in the previous channel are inserted first, the ones
+    /// that cannot be inserted are dropped.
+    pub fn relink(&mut self, receiver: ReceiverChannel<T::Data>) {
+        if let Some(previous) = self.channel.replace(receiver) {
+            let left = previous
+                .take_lookahead()
+                .into_iter()
+                .chain(previous.receiver.try_iter());
+            for packet in left {
+                if let Err(err) = self.insert(packet) {
+                    tracing::debug!(error = ?err, "Dropping packet of the previous channel");
+                }
+            }
+        }
+    }
+
+    /// Links the buffer to a receiver returned by `WriteChannelTrait::link_untyped`,
+    /// in place of the channel it was linked to, see `relink`.
+    ///
+    /// * Returns
+    /// A `ChannelError::PacketError` if the receiver is not of the buffer type.
+    pub fn link_untyped(&mut self, receiver: Box<dyn Any>) -> Result<(), ChannelError>
+    where
+        T::Data: 'static,
+    {
+        self.check_link(receiver.as_ref())?;
+        if let Ok(receiver) = receiver.downcast::<ReceiverChannel<T::Data>>() {
+            self.relink(*receiver);
+        }
+        Ok(())
+    }
+
+    /// Checks that `link_untyped` accepts `receiver`, without linking it.
+    ///
+    /// * Returns
+    /// A `ChannelError::PacketError` if the receiver is not of the buffer type.
+    pub fn check_link(&self, receiver: &dyn Any) -> Result<(), ChannelError>
+    where
+        T::Data: 'static,
+    {
+        if receiver.is::<ReceiverChannel<T::Data>>() {
+            return Ok(());
+        }
+        Err(PacketError::UnexpectedDataType {
+            expected: type_name::<T::Data>(),
+            found: "a channel of another type",
+        }
+        .into())
+    }
+
     /// Tries to read data from the data transport channel or an error
     /// it the channel has no connection yet.
     pub fn try_read(&mut self) -> Result<DataVersion, ChannelError> {
         if let Some(channel) = self.channel.as_ref() {
             let packet = channel.try_receive()?;
             let version = packet.version;
-            self.buffer.insert(packet)?;
+            self.insert(packet)?;
             return Ok(version);
         }
         Err(ChannelError::NotInitializedError)
     }
+
+    /// True if the linked channel holds a packet read ahead by `ReceiverChannel::peek`.
+    pub fn has_lookahead(&self) -> bool {
+        self.channel
+            .as_ref()
+            .is_some_and(|channel| channel.has_lookahead())
+    }
+
+    /// Inserts the packet read ahead by `ReceiverChannel::peek`, if any. Readers
+    /// selecting on the crossbeam receiver call it first, so that the packet is
+    /// not overtaken by the ones still in the channel.
+    ///
+    /// * Returns
+    /// None if no packet was read ahead, otherwise the result of the insert.
+    pub fn insert_lookahead(&mut self) -> Option<Result<(), BufferError>> {
+        let channel = self.channel.as_ref()?;
+        let packet = channel.take_lookahead()?;
+        channel.record_received();
+        Some(self.insert(packet))
+    }
+
+    /// Records the packet if a recorder is set and inserts it in the buffer.
+    pub fn insert(&mut self, packet: Packet<T::Data>) -> Result<(), BufferError> {
+        self.check_regression(&packet.version)?;
+        if let Some(recorder) = self.recorder.as_ref() {
+            recorder(&packet);
+        }
+        self.insert_unrecorded(packet)
+    }
+
+    /// Inserts the packet in the buffer without recording it, e.g. a packet replayed
+    /// from a write-ahead log.
+    pub fn insert_unrecorded(&mut self, packet: Packet<T::Data>) -> Result<(), BufferError> {
+        self.check_regression(&packet.version)?;
+        let len = self.buffer.len();
+        let version = packet.version;
+        let id = self.channel.as_ref().and_then(|channel| channel.id());
+        self.buffer
+            .insert(packet)
+            .map_err(|error| match (error, id) {
+                (BufferError::DuplicateDataVersionError((_, version)), Some(id)) => {
+                    BufferError::DuplicateDataVersionError((id.clone(), version))
+                }
+                (error, _) => error,
+            })?;
+        self.stats.inserted += 1;
+        self.stats.evicted += (len + 1 - self.buffer.len()) as u64;
+        self.arrivals.record(&version);
+        Ok(())
+    }
+
+    fn check_regression(&self, version: &DataVersion) -> Result<(), BufferError> {
+        match self.last_consumed {
+            Some(last_consumed) if self.reject_regression && *version <= last_consumed => {
+                Err(BufferError::VersionRegression(*version, last_consumed))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the packet with `data_version` from the buffer. See `get_data`.
+    pub fn get_data(
+        &mut self,
+        data_version: &Option<DataVersion>,
+        exact_match: bool,
+    ) -> Option<Packet<T::Data>> {
+        let len = self.buffer.len();
+        let packet = get_data(self.buffer.as_mut(), data_version, exact_match);
+        let consumed = packet.is_some() as usize;
+        if let Some(packet) = packet.as_ref() {
+            self.last_consumed = Some(packet.version);
+        }
+        self.stats.consumed += consumed as u64;
+        self.stats.evicted += (len - self.buffer.len() - consumed) as u64;
+        packet
+    }
+
+    /// Removes all packets up to and including `version`. See `consume_until`.
+    pub fn consume_until(&mut self, version: &DataVersion) -> Vec<Packet<T::Data>> {
+        let packets = consume_until(self.buffer.as_mut(), version);
+        if let Some(packet) = packets.last() {
+            self.last_consumed = Some(packet.version);
+        }
+        self.stats.consumed += packets.len() as u64;
+        packets
+    }
+
+    /// Drops the packets older than `version`, counting them as evicted.
+    pub fn discard_before(&mut self, version: &DataVersion) {
+        while self.buffer.peek().is_some_and(|oldest| oldest < version) {
+            if let Some(packet) = self.buffer.pop() {
+                self.buffer.discard(packet);
+                self.stats.evicted += 1;
+            }
+        }
+    }
 }
 
 /// A trait for buffer data manipulation. Mostly used by the synchronizer
@@ -65,6 +264,10 @@ impl<T: FixedSizeBuffer + ?Sized> BufferReceiver<T> {
 pub trait ChannelBuffer {
     /// A list of named channels.
     fn available_channels(&self) -> Vec<&ChannelID>;
+    /// The channels not linked to any sender, which will never receive data.
+    fn unlinked_channels(&self) -> Vec<&ChannelID> {
+        vec![]
+    }
     /// True if a channel has the given data version.
     ///
     /// * Arguments
@@ -78,13 +281,76 @@ pub trait ChannelBuffer {
     /// * Arguments
     /// `channel` - The name of the channel to inquire.
     fn peek(&self, channel: &ChannelID) -> Option<&DataVersion>;
+    /// Returns the most recent version in `channel`. The packet itself
+    /// can be fetched with `FixedSizeBuffer::get_latest` on the typed buffer.
+    ///
+    /// * Arguments
+    ///
+    /// `channel` - The name of the channel to inquire.
+    fn latest_version(&self, channel: &ChannelID) -> Option<&DataVersion> {
+        self.iterator(channel)?.next()
+    }
     /// Returns an iterator in `channel`.
     ///
     /// * Arguments
     /// `channel` - The name of the channel to inquire.
     fn iterator(&self, channel: &ChannelID) -> Option<Box<BufferIterator>>;
+    /// Returns the oldest version in `channel`.
+    ///
+    /// * Arguments
+    ///
+    /// `channel` - The name of the channel to inquire.
+    fn earliest_version(&self, channel: &ChannelID) -> Option<DataVersion> {
+        self.peek(channel).cloned()
+    }
+    /// Returns all the versions in `channel` from the oldest.
+    ///
+    /// * Arguments
+    ///
+    /// `channel` - The name of the channel to inquire.
+    fn all_versions(&self, channel: &ChannelID) -> Vec<DataVersion> {
+        let mut versions: Vec<DataVersion> = self
+            .iterator(channel)
+            .map(|iterator| iterator.cloned().collect())
+            .unwrap_or_default();
+        versions.reverse();
+        versions
+    }
+    /// Returns every buffered version with its channel, from the oldest version.
+    /// Useful to log the content of the buffers when a synchronization fails.
+    fn buffered_versions(&self) -> Vec<(ChannelID, DataVersion)> {
+        let mut versions: Vec<(ChannelID, DataVersion)> = self
+            .available_channels()
+            .into_iter()
+            .flat_map(|channel| {
+                self.all_versions(channel)
+                    .into_iter()
+                    .map(move |version| (channel.clone(), version))
+            })
+            .collect();
+        versions.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        versions
+    }
     /// Returns true if there is no data in any buffer.
     fn are_buffers_empty(&self) -> bool;
+    /// Number of packets currently held by the buffer of each channel.
+    fn occupancy(&self) -> HashMap<ChannelID, usize> {
+        self.available_channels()
+            .into_iter()
+            .map(|channel| (channel.clone(), self.all_versions(channel).len()))
+            .collect()
+    }
+    /// Counters of the packets going through the buffer of `channel`.
+    /// Unknown channels have empty counters, and so have all the channels by default.
+    fn stats(&self, _channel: &ChannelID) -> BufferStats {
+        BufferStats::default()
+    }
+    /// Statistics of the deltas between the versions of the packets inserted in
+    /// `channel`. Unknown channels have empty statistics, and so have all the
+    /// channels by default.
+    fn arrival_stats(&self, _channel: &ChannelID) -> ArrivalStats {
+        ArrivalStats::default()
+    }
     /// Tries to read data for up to 'timeout' duration.
     ///
     /// * Arguments
@@ -98,6 +364,38 @@ pub trait ChannelBuffer {
     /// * Returns
     /// true if there is dat a in any channel before timeout.
     fn wait_for_data(&self, timeout: Duration) -> Result<bool, ChannelError>;
+    /// Links the channel named `channel` to a receiver returned by
+    /// `WriteChannelTrait::link_untyped`, in place of the channel it was linked to.
+    ///
+    /// * Returns
+    /// A `ChannelError::MissingChannel` if there is no such channel, or a
+    /// `ChannelError::PacketError` if the receiver is not of the channel type.
+    fn link_untyped(
+        &mut self,
+        channel: &ChannelID,
+        _receiver: Box<dyn Any>,
+    ) -> Result<(), ChannelError> {
+        Err(ChannelError::MissingChannel(channel.clone()))
+    }
+    /// Checks that `link_untyped` would link `receiver` to the channel named `channel`,
+    /// without linking it.
+    ///
+    /// * Returns
+    /// The error `link_untyped` would return.
+    fn check_link(&self, channel: &ChannelID, _receiver: &dyn Any) -> Result<(), ChannelError> {
+        Err(ChannelError::MissingChannel(channel.clone()))
+    }
+    /// The channel linked to each input, to derive the topology of a graph.
+    fn links(&self) -> Vec<(ChannelID, LinkId)> {
+        vec![]
+    }
+    /// Drops the packets of `channel` older than `version`, e.g. the data of an
+    /// optional channel that a synchronizer skipped. Does nothing by default.
+    ///
+    /// * Arguments
+    /// `channel` - The name of the channel to clean.
+    /// `version` - The oldest version to keep.
+    fn discard_before(&mut self, _channel: &ChannelID, _version: &DataVersion) {}
 }
 
 /// A trait for generating packet set from an existing ReadChannel.
@@ -122,8 +420,43 @@ pub trait InputGenerator {
         block_on_full: bool,
         monitor: BufferMonitorBuilder,
     ) -> Self;
+
+    /// Same as `create_channels` but the buffers of the channels in `channel_sizes`
+    /// get their own size. By default the sizes are ignored.
+    ///
+    /// * Arguments
+    ///
+    /// `buffer_size` - Size of the buffers without an entry in `channel_sizes`.
+    /// `channel_sizes` - Size of the buffer of specific channels.
+    /// `block_on_full` - Block the buffers when full instead of dropping data.
+    /// `monitor` - Creates the monitor of each buffer.
+    fn create_channels_with_sizes(
+        buffer_size: usize,
+        _channel_sizes: &HashMap<ChannelID, usize>,
+        block_on_full: bool,
+        monitor: BufferMonitorBuilder,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        Self::create_channels(buffer_size, block_on_full, monitor)
+    }
 }
 
+/// How a ReadChannel assembles the sets of packets it hands to its processor.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sets are made of the packets matched by the synchronizer.
+    #[default]
+    Match,
+    /// Each time data arrives, a set is made of the latest packet of every channel.
+    /// Channels without new data repeat their packet of the previous set.
+    SampleAndHold,
+}
+
+/// Observer of the sets of packets assembled by a ReadChannel.
+pub type PacketSetCallback<T> = Box<dyn Fn(&T) + Send + Sync>;
+
 /// A generic ReadChannel that holds a reference to a struct that has
 /// a set of trait for managing the internal channels.
 pub struct ReadChannel<T: InputGenerator + ChannelBuffer + Send> {
@@ -133,6 +466,15 @@ pub struct ReadChannel<T: InputGenerator + ChannelBuffer + Send> {
     pub work_queue: Option<WorkQueue<T::INPUT>>,
     /// A reference to the channels of the ReadChannel.
     pub channels: Arc<RwLock<T>>,
+    sync_policy: SyncPolicy,
+    /// The last set, whose packets are held with `SyncPolicy::SampleAndHold`.
+    last_set: Option<T::INPUT>,
+    on_packet_set: Option<PacketSetCallback<T::INPUT>>,
+    /// Status of the worker of the node, marked as stalled while a set cannot be assembled.
+    worker_status: Option<Arc<Atomic<WorkerStatus>>>,
+    /// The quorum synchronizer configuration, see `with_quorum`.
+    quorum: Option<QuorumSynchronizer>,
+    sync_monitor: SyncMonitor,
 }
 
 unsafe impl<T: InputGenerator + ChannelBuffer + Send> Sync for ReadChannel<T> {}
@@ -141,21 +483,23 @@ unsafe impl<T: InputGenerator + ChannelBuffer + Send> Send for ReadChannel<T> {}
 impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannelTrait for ReadChannel<T> {
     type Data = T::INPUT;
 
-    fn read(&mut self, node_id: String, done_notification: Sender<String>) -> Option<ChannelID> {
+    fn read(
+        &mut self,
+        node_id: String,
+        done_notification: Sender<String>,
+    ) -> Result<Option<ChannelID>, ChannelError> {
         let data;
 
-        {
+        let has_data = {
             let read_locked = self.channels.read().unwrap_or_else(PoisonError::into_inner);
-            let has_data = read_locked.wait_for_data(Duration::from_millis(50));
-            if let Err(err) = has_data {
-                tracing::error!("Error while waiting for data {err} on channel {node_id}.");
-                return None;
-            }
-            if let Ok(data) = has_data {
-                if !data {
-                    return None;
-                }
+            read_locked.wait_for_data(Duration::from_millis(50))?
+        };
+        if !has_data {
+            // The grace period of a set waiting for its missing channels may have expired.
+            if self.quorum.is_some() {
+                self.synchronize();
             }
+            return Ok(None);
         }
 
         {
@@ -168,22 +512,23 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannelTrait for Re
             data = match result {
                 Ok(has_data) => has_data.cloned(),
                 Err(err) => {
-                    tracing::error!("Node {node_id}: Exception while reading {err:?}");
+                    tracing::debug!(node_id = %node_id, error = ?err, "Exception while reading");
                     match err {
-                        crate::channels::ChannelError::ReceiveError(_) => {
+                        crate::channels::ChannelError::ReceiveError(_)
+                        | crate::channels::ChannelError::Disconnected(_) => {
                             if write_locked.are_buffers_empty() {
                                 let _ = done_notification.send(node_id);
                             }
-                            tracing::error!("Channel is disonnected, closing");
+                            tracing::error!(node_id = %node_id, "Channel is disconnected, closing");
                             thread::sleep(Duration::from_millis(100));
-                            return None;
+                            return Ok(None);
                         }
                         _ => {
                             if write_locked.are_buffers_empty() {
-                                debug!("Sending done {node_id}");
+                                tracing::debug!(node_id = %node_id, "Sending done");
                                 let _ = done_notification.send(node_id);
                             }
-                            None
+                            return Err(err);
                         }
                     }
                 }
@@ -193,7 +538,7 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannelTrait for Re
         if data.is_some() {
             self.synchronize()
         }
-        data
+        Ok(data)
     }
 
     fn start(&mut self, work_queue: WorkQueue<Self::Data>) {
@@ -213,6 +558,12 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannel<T> {
             synch_strategy,
             work_queue,
             channels: Arc::new(RwLock::new(channels)),
+            sync_policy: SyncPolicy::default(),
+            last_set: None,
+            on_packet_set: None,
+            worker_status: None,
+            quorum: None,
+            sync_monitor: SyncMonitor::default(),
         }
     }
 
@@ -223,6 +574,29 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannel<T> {
         process_buffer_size: usize,
         synch_strategy: Box<dyn PacketSynchronizer>,
         monitor: bool,
+    ) -> Self {
+        Self::create_with_channel_sizes(
+            id,
+            block_channel_full,
+            channel_buffer_size,
+            &HashMap::new(),
+            process_buffer_size,
+            synch_strategy,
+            monitor,
+        )
+    }
+
+    /// Same as `create` but the buffers of the channels in `channel_sizes` get their
+    /// own size instead of `channel_buffer_size`. Useful when a fast channel needs a
+    /// deeper buffer than the others.
+    pub fn create_with_channel_sizes(
+        id: &str,
+        block_channel_full: bool,
+        channel_buffer_size: usize,
+        channel_sizes: &HashMap<ChannelID, usize>,
+        process_buffer_size: usize,
+        synch_strategy: Box<dyn PacketSynchronizer>,
+        monitor: bool,
     ) -> Self {
         let mut monitor_builder = BufferMonitorBuilder::no_monitor();
         if monitor {
@@ -238,19 +612,116 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannel<T> {
             process_buffer_size,
             work_monitor,
         ));
+        let sync_monitor = if monitor {
+            SyncMonitor::new(id)
+        } else {
+            SyncMonitor::default()
+        };
 
-        let channels = T::create_channels(channel_buffer_size, block_channel_full, monitor_builder);
+        let channels = T::create_channels_with_sizes(
+            channel_buffer_size,
+            channel_sizes,
+            block_channel_full,
+            monitor_builder,
+        );
 
         Self {
             synch_strategy,
             work_queue,
             channels: Arc::new(RwLock::new(channels)),
+            sync_policy: SyncPolicy::default(),
+            last_set: None,
+            on_packet_set: None,
+            worker_status: None,
+            quorum: None,
+            sync_monitor,
+        }
+    }
+
+    /// Selects how the sets of packets are assembled. `SyncPolicy::SampleAndHold`
+    /// replaces the synchronizer with a `LatestSynchronizer`.
+    pub fn with_sync_policy(mut self, policy: SyncPolicy) -> Self {
+        if policy == SyncPolicy::SampleAndHold {
+            self.synch_strategy = Box::<LatestSynchronizer>::default();
         }
+        self.sync_policy = policy;
+        self
+    }
+
+    /// Assembles a set as soon as `quorum` of the channels have a packet at the same
+    /// version, replacing the synchronizer with a `QuorumSynchronizer`. The other
+    /// channels are None in the set unless they receive the version within the grace
+    /// period set with `with_grace`, none by default.
+    pub fn with_quorum(mut self, quorum: usize) -> Self {
+        let synchronizer = self.quorum.take().unwrap_or_default().with_quorum(quorum);
+        self.synch_strategy = Box::new(synchronizer.clone());
+        self.quorum = Some(synchronizer);
+        self
+    }
+
+    /// Sets how long a set that reached its quorum waits for the missing channels.
+    /// It must be called after `with_quorum`.
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        let synchronizer = self
+            .quorum
+            .take()
+            .expect("The grace period is set after the quorum with `with_quorum`")
+            .with_grace(grace);
+        self.synch_strategy = Box::new(synchronizer.clone());
+        self.quorum = Some(synchronizer);
+        self
+    }
+
+    /// Statistics of the deltas between the versions of the packets received by `channel`,
+    /// e.g. to check that a camera produces at its nominal frame rate.
+    pub fn arrival_stats(&self, channel: &ChannelID) -> ArrivalStats {
+        self.channels
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .arrival_stats(channel)
+    }
+
+    /// Calls `callback` with each set of packets before it is queued for the processor.
+    pub fn on_packet_set(mut self, callback: impl Fn(&T::INPUT) + Send + Sync + 'static) -> Self {
+        self.on_packet_set = Some(Box::new(callback));
+        self
+    }
+
+    /// Reports in `status` when the worker of the node is stalled, see `WorkerStatus::Stalled`.
+    pub(crate) fn report_stalls(&mut self, status: Arc<Atomic<WorkerStatus>>) {
+        self.worker_status = Some(status);
+    }
+
+    /// Marks the worker as stalled if no set was assembled while only some
+    /// of the channels hold data, and back to idle once a set is assembled.
+    fn update_stalled(&self, assembled: bool) {
+        let status = match self.worker_status.as_ref() {
+            Some(status) => status,
+            None => return,
+        };
+        let stalled = !assembled && {
+            let occupancy = self
+                .channels
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .occupancy();
+            occupancy.values().any(|len| *len > 0) && occupancy.values().any(|len| *len == 0)
+        };
+        let (from, to) = if stalled {
+            (WorkerStatus::Idle, WorkerStatus::Stalled)
+        } else {
+            (WorkerStatus::Stalled, WorkerStatus::Idle)
+        };
+        let _ = status.compare_exchange(from, to, Ordering::Relaxed, Ordering::Relaxed);
     }
 
     pub fn synchronize(&mut self) {
         if let Some(queue) = self.work_queue.as_mut() {
             let synch = self.synch_strategy.synchronize(self.channels.clone());
+            for reason in self.synch_strategy.take_dropped() {
+                self.sync_monitor.dropped(reason);
+            }
+            let assembled = synch.is_some();
             if let Some(sync) = synch {
                 let mut channels = if let Ok(channels) = self.channels.write() {
                     channels
@@ -258,19 +729,35 @@ impl<T: InputGenerator + ChannelBuffer + Send + 'static> ReadChannel<T> {
                     return;
                 };
 
-                if let Some(value) = channels.get_packets_for_version(&sync, false) {
-                    queue.push(value);
+                if let Some(mut value) = channels.get_packets_for_version(&sync, false) {
+                    if self.sync_policy == SyncPolicy::SampleAndHold {
+                        if let Some(last_set) = self.last_set.as_ref() {
+                            value.hold(last_set);
+                        }
+                        self.last_set = Some(value.clone());
+                    }
+                    if let Some(callback) = self.on_packet_set.as_ref() {
+                        callback(&value);
+                    }
+                    self.sync_monitor.assembled();
+                    for _ in 0..queue.push(value) {
+                        self.sync_monitor.dropped(SyncDropReason::Evicted);
+                    }
                 }
             }
+            self.update_stalled(assembled);
         }
     }
 }
 
-pub fn get_data<T>(
-    buffer: &mut RtRingBuffer<T>,
+/// Removes the packet matching `data_version` from the buffer, discarding the older
+/// ones or, with `exact_match`, only the oldest. A gap packet is removed but not
+/// returned since it carries no data.
+pub fn get_data<T: FixedSizeBuffer + ?Sized>(
+    buffer: &mut T,
     data_version: &Option<DataVersion>,
     exact_match: bool,
-) -> Option<Packet<T>> {
+) -> Option<Packet<T::Data>> {
     if data_version.is_none() {
         return None;
     }
@@ -279,12 +766,11 @@ pub fn get_data<T>(
 
         if let Some(entry) = removed_packet {
             if let Some(data_version) = data_version {
-                if entry.version == *data_version {
-                    return Some(entry);
-                } else if exact_match {
-                    break;
+                if entry.covers(data_version) {
+                    return (!entry.gap).then_some(entry);
                 }
             }
+            buffer.discard(entry);
             if exact_match {
                 break;
             }
@@ -295,15 +781,40 @@ pub fn get_data<T>(
     None
 }
 
+/// Removes all packets up to and including `version` from the buffer.
+pub fn consume_until<T: FixedSizeBuffer + ?Sized>(
+    buffer: &mut T,
+    version: &DataVersion,
+) -> Vec<Packet<T::Data>> {
+    let mut packets = vec![];
+    while buffer.peek().is_some_and(|oldest| oldest <= version) {
+        packets.extend(buffer.pop());
+    }
+    packets
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::buffers::single_buffers::RtRingBuffer;
+    use crate::buffers::single_buffers::{
+        FixedSizeBuffer, FixedSizeRingBuffer, LenTrait, RtRingBuffer,
+    };
+    use crate::buffers::wal::test_wal_path;
+    use crate::buffers::{ArrivalStats, BufferError, BufferStats};
     use crate::buffers::synchronizers::timestamp::TimestampSynchronizer;
 
     use crate::channels::read_channel::ReadChannel;
+    use crate::channels::read_channel::SyncPolicy;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::channels::read_channel::ChannelBuffer;
+    use crate::channels::read_channel::InputGenerator;
     use crate::channels::read_channel::ReadChannelTrait;
     use crate::channels::typed_channel;
 
+    use crate::channels::ChannelID;
     use crate::channels::SenderChannel;
 
     use crate::channels::typed_read_channel::ReadChannel2;
@@ -312,6 +823,7 @@ mod tests {
     use crate::packet::typed::ReadChannel2PacketSet;
     use crate::packet::work_queue::WorkQueue;
     use crate::packet::Packet;
+    use crate::packet::UntypedPacketCast;
     use crate::DataVersion;
 
     fn create_typed_read_channel() -> (
@@ -343,7 +855,7 @@ mod tests {
         crossbeam_channels
             .send(Packet::new(
                 "my_data".to_string(),
-                DataVersion { timestamp_ns: 1 },
+                DataVersion::new(1),
             ))
             .unwrap();
         read_channel.start(WorkQueue::default());
@@ -356,8 +868,507 @@ mod tests {
                 .try_read()
                 .ok()
                 .unwrap(),
-            DataVersion { timestamp_ns: 1 }
+            DataVersion::new(1)
+        );
+    }
+
+    #[test]
+    fn test_read_channel_try_receive_inserts_peeked_packet_first() {
+        let (read_channel, sender) = create_typed_read_channel();
+        sender
+            .send(Packet::new("first".to_string(), DataVersion::new(1)))
+            .unwrap();
+        let mut channels = read_channel.channels.write().unwrap();
+        assert_eq!(
+            channels.c1().channel.as_ref().unwrap().peek(),
+            Some(DataVersion::new(1))
+        );
+        assert!(channels.wait_for_data(Duration::from_millis(10)).unwrap());
+
+        sender
+            .send(Packet::new("second".to_string(), DataVersion::new(2)))
+            .unwrap();
+        assert_eq!(
+            channels.try_receive(Duration::from_millis(10)),
+            Ok(Some(&ChannelID::from("c1")))
+        );
+        assert_eq!(channels.c1().buffer.len(), 1);
+        assert_eq!(channels.c1().buffer.peek(), Some(&DataVersion::new(1)));
+        assert_eq!(
+            channels.try_receive(Duration::from_millis(10)),
+            Ok(Some(&ChannelID::from("c1")))
+        );
+        assert_eq!(channels.c1().buffer.len(), 2);
+    }
+
+    #[test]
+    fn test_read_channel_creates_buffers_of_the_channel_type() {
+        let read_channel =
+            ReadChannel::<ReadChannel2<String, String, FixedSizeRingBuffer<String>>>::create(
+                "ring",
+                false,
+                2,
+                2,
+                Box::<TimestampSynchronizer>::default(),
+                false,
+            );
+        let mut channels = read_channel.channels.write().unwrap();
+        for version in [2, 1, 3] {
+            channels
+                .c1()
+                .buffer
+                .insert(Packet::new("data".to_string(), DataVersion::new(version)))
+                .unwrap();
+        }
+
+        // The ring buffer accepts out of order versions and drops the oldest insert.
+        assert_eq!(channels.c1().buffer.len(), 2);
+        assert_eq!(
+            channels.c1().buffer.pop().unwrap().version,
+            DataVersion::new(1)
+        );
+        assert_eq!(
+            channels.c1().buffer.pop().unwrap().version,
+            DataVersion::new(3)
+        );
+    }
+
+    #[test]
+    fn test_read_channel_read_returns_channel_and_ignores_disconnections() {
+        let (mut read_channel, sender) = create_typed_read_channel();
+        let (other_sender, channel_receiver) = typed_channel::<String>();
+        read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c2()
+            .link(channel_receiver);
+        read_channel.start(WorkQueue::default());
+        let (done, _) = crossbeam::channel::unbounded();
+
+        sender
+            .send(Packet::new("my_data".to_string(), DataVersion::new(1)))
+            .unwrap();
+        assert_eq!(
+            read_channel.read("node".to_string(), done.clone()),
+            Ok(Some(ChannelID::from("c1")))
+        );
+
+        drop(sender);
+        drop(other_sender);
+        assert_eq!(read_channel.read("node".to_string(), done), Ok(None));
+    }
+
+    #[test]
+    fn test_packet_set_callback_observes_assembled_sets() {
+        let sets = Arc::new(Mutex::new(vec![]));
+        let observed = sets.clone();
+        let (read_channel, _) = create_typed_read_channel();
+        let mut read_channel = read_channel.on_packet_set(
+            move |set: &ReadChannel2PacketSet<String, String>| {
+                let (c1, c2) = set.values();
+                observed
+                    .lock()
+                    .unwrap()
+                    .push((c1.map(|p| p.version), c2.map(|p| p.version)));
+            },
+        );
+        for version in [1, 2] {
+            let mut channels = read_channel.channels.write().unwrap();
+            let packet = Packet::new("data".to_string(), DataVersion::new(version));
+            channels.c1().buffer.insert(packet.clone()).unwrap();
+            channels.c2().buffer.insert(packet).unwrap();
+        }
+        read_channel.synchronize();
+        read_channel.synchronize();
+
+        assert_eq!(
+            *sets.lock().unwrap(),
+            vec![
+                (Some(DataVersion::new(1)), Some(DataVersion::new(1))),
+                (Some(DataVersion::new(2)), Some(DataVersion::new(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sample_and_hold_repeats_latest_packets() {
+        let (read_channel, _) = create_typed_read_channel();
+        let mut read_channel = read_channel.with_sync_policy(SyncPolicy::SampleAndHold);
+        let mut work_queue = read_channel.work_queue.clone().unwrap();
+        let insert = |read_channel: &ReadChannel<ReadChannel2<String, String>>, c1, version| {
+            let mut channels = read_channel.channels.write().unwrap();
+            let receiver = if c1 { channels.c1() } else { channels.c2() };
+            receiver
+                .buffer
+                .insert(Packet::new("data".to_string(), DataVersion::new(version)))
+                .unwrap();
+        };
+
+        insert(&read_channel, true, 1);
+        read_channel.synchronize();
+        let set = work_queue.get(None).unwrap().packet_data;
+        assert_eq!(set.c1().unwrap().version, DataVersion::new(1));
+        assert!(set.c2().is_none());
+
+        insert(&read_channel, false, 2);
+        read_channel.synchronize();
+        let set = work_queue.get(None).unwrap().packet_data;
+        assert_eq!(set.c1().unwrap().version, DataVersion::new(1));
+        assert_eq!(set.c2().unwrap().version, DataVersion::new(2));
+
+        insert(&read_channel, true, 3);
+        insert(&read_channel, true, 4);
+        read_channel.synchronize();
+        let set = work_queue.get(None).unwrap().packet_data;
+        assert_eq!(set.c1().unwrap().version, DataVersion::new(4));
+        assert_eq!(set.c2().unwrap().version, DataVersion::new(2));
+    }
+
+    #[test]
+    fn test_quorum_set_is_assembled_once_grace_expires() {
+        let (read_channel, sender) = create_typed_read_channel();
+        let mut read_channel = read_channel
+            .with_quorum(1)
+            .with_grace(Duration::from_millis(30));
+        let (_other_sender, channel_receiver) = typed_channel::<String>();
+        read_channel
+            .channels
+            .write()
+            .unwrap()
+            .c2()
+            .link(channel_receiver);
+        let mut work_queue = read_channel.work_queue.clone().unwrap();
+        let (done, _) = crossbeam::channel::unbounded();
+
+        sender
+            .send(Packet::new("my_data".to_string(), DataVersion::new(1)))
+            .unwrap();
+        assert_eq!(
+            read_channel.read("node".to_string(), done.clone()),
+            Ok(Some(ChannelID::from("c1")))
+        );
+        assert!(work_queue.get(Some(Duration::ZERO)).is_err());
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(read_channel.read("node".to_string(), done), Ok(None));
+        let set = work_queue.get(Some(Duration::ZERO)).unwrap().packet_data;
+        assert_eq!(set.c1().unwrap().version, DataVersion::new(1));
+        assert!(set.c2().is_none());
+    }
+
+    #[test]
+    fn test_arrival_stats_measure_deltas_between_versions() {
+        let (read_channel, _) = create_typed_read_channel();
+        {
+            let mut channels = read_channel.channels.write().unwrap();
+            for timestamp in [0, 100, 200, 400] {
+                channels
+                    .c1()
+                    .insert(Packet::new("data".to_string(), DataVersion::new(timestamp)))
+                    .unwrap();
+            }
+        }
+
+        let expected = ArrivalStats {
+            count: 3,
+            mean: Duration::from_nanos(133),
+            stddev: Duration::from_nanos(47),
+            jitter: Duration::from_nanos(50),
+        };
+        assert_eq!(read_channel.arrival_stats(&ChannelID::from("c1")), expected);
+        assert_eq!(
+            read_channel.arrival_stats(&ChannelID::from("c2")),
+            ArrivalStats::default()
+        );
+    }
+
+    #[test]
+    fn test_buffered_versions_lists_all_channels_in_version_order() {
+        let (read_channel, _) = create_typed_read_channel();
+        let mut channels = read_channel.channels.write().unwrap();
+        for (timestamp, data) in [(1, "c1 1"), (3, "c1 3")] {
+            channels
+                .c1()
+                .buffer
+                .insert(Packet::new(data.to_string(), DataVersion::new(timestamp)))
+                .unwrap();
+        }
+        channels
+            .c2()
+            .buffer
+            .insert(Packet::new("c2 2".to_string(), DataVersion::new(2)))
+            .unwrap();
+
+        assert_eq!(
+            channels.buffered_versions(),
+            vec![
+                (ChannelID::from("c1"), DataVersion::new(1)),
+                (ChannelID::from("c2"), DataVersion::new(2)),
+                (ChannelID::from("c1"), DataVersion::new(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_channel_occupancy_reports_buffered_packets() {
+        let (read_channel, crossbeam_channels) = create_typed_read_channel();
+        crossbeam_channels
+            .send(Packet::new("my_data".to_string(), DataVersion::new(1)))
+            .unwrap();
+        let mut channels = read_channel.channels.write().unwrap();
+        channels.c1().try_read().unwrap();
+
+        let occupancy = channels.occupancy();
+        assert_eq!(occupancy[&ChannelID::from("c1")], 1);
+        assert_eq!(occupancy[&ChannelID::from("c2")], 0);
+        assert_eq!(channels.c1().buffer.capacity(), 2);
+        assert_eq!(
+            channels.latest_version(&ChannelID::from("c1")),
+            Some(&DataVersion::new(1))
+        );
+        assert!(channels.latest_version(&ChannelID::from("c2")).is_none());
+    }
+
+    #[test]
+    fn test_read_channel_lists_versions_from_oldest() {
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        );
+        for i in 3..6 {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            channels.c1().insert(packet).unwrap();
+        }
+        let c1 = ChannelID::from("c1");
+        assert_eq!(channels.earliest_version(&c1), Some(DataVersion::new(3)));
+        assert_eq!(
+            channels.all_versions(&c1),
+            [3, 4, 5].map(DataVersion::new).to_vec()
+        );
+        assert!(channels.earliest_version(&ChannelID::from("c2")).is_none());
+        assert!(channels.all_versions(&ChannelID::from("c3")).is_empty());
+    }
+
+    #[test]
+    fn test_read_channel_on_evict_reports_channel_and_packet() {
+        let (read_channel, crossbeam_channels) = create_typed_read_channel();
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let evicted_clone = evicted.clone();
+        read_channel
+            .channels
+            .write()
+            .unwrap()
+            .on_evict(move |channel, packet| {
+                let data = packet.deref_owned::<String>().unwrap();
+                evicted_clone.lock().unwrap().push((channel, *data.data));
+            });
+        for i in 0..2 {
+            crossbeam_channels
+                .send(Packet::new(format!("data {i}"), DataVersion::new(i)))
+                .unwrap();
+            read_channel.channels.write().unwrap().c1().try_read().unwrap();
+        }
+
+        let versions = HashMap::from([
+            (ChannelID::from("c1"), Some(DataVersion::new(1))),
+            (ChannelID::from("c2"), None),
+        ]);
+        read_channel
+            .channels
+            .write()
+            .unwrap()
+            .get_packets_for_version(&versions, false);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![(ChannelID::from("c1"), "data 0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_read_channel_replays_write_ahead_log() {
+        let path = test_wal_path("test_read_channel_replay");
+        let mut recorded = ReadChannel2::create(
+            RtRingBuffer::<String>::new(2, true, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(2, true, BufferMonitor::default()),
+        )
+        .with_wal(&path)
+        .unwrap();
+        for i in 0..3 {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            assert_eq!(recorded.c1().insert(packet).is_ok(), i < 2);
+        }
+        recorded
+            .c2()
+            .insert(Packet::new("data".to_string(), DataVersion::new(5)))
+            .unwrap();
+
+        let mut replayed = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(2, true, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(2, true, BufferMonitor::default()),
+        );
+        let (inserted, errors) = replayed.replay(&path).unwrap();
+        assert_eq!(inserted, 3);
+        assert!(matches!(errors[..], [BufferError::BufferFull]));
+        assert_eq!(replayed.occupancy(), recorded.occupancy());
+        assert_eq!(replayed.c1().buffer.back(), Some(&DataVersion::new(1)));
+        assert_eq!(replayed.c2().buffer.peek(), Some(&DataVersion::new(5)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_channel_consume_until_returns_packets_in_order() {
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(8, true, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(8, true, BufferMonitor::default()),
+        );
+        for i in 0..5 {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            channels.c1().insert(packet).unwrap();
+        }
+
+        let consumed = channels.consume_until(&ChannelID::from("c1"), &DataVersion::new(2));
+        let data = consumed
+            .into_iter()
+            .map(|packet| *packet.deref_owned::<String>().unwrap().data)
+            .collect::<Vec<_>>();
+        assert_eq!(data, ["data 0", "data 1", "data 2"]);
+        assert_eq!(channels.c1().buffer.peek(), Some(&DataVersion::new(3)));
+        assert!(channels
+            .consume_until(&ChannelID::from("c2"), &DataVersion::new(2))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_read_channel_uses_per_channel_buffer_sizes() {
+        let read_channel = ReadChannel::<ReadChannel2<String, String>>::create_with_channel_sizes(
+            "node",
+            true,
+            4,
+            &HashMap::from([(ChannelID::from("c2"), 16)]),
+            2,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        let mut channels = read_channel.channels.write().unwrap();
+        assert_eq!(channels.c1().buffer.capacity(), 4);
+        assert_eq!(channels.c2().buffer.capacity(), 16);
+    }
+
+    #[test]
+    fn test_read_channel_stats_count_inserted_evicted_and_consumed() {
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        );
+        for i in 0..6 {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            channels.c1().insert(packet).unwrap();
+        }
+        let versions = HashMap::from([
+            (ChannelID::from("c1"), Some(DataVersion::new(3))),
+            (ChannelID::from("c2"), None),
+        ]);
+        channels.get_packets_for_version(&versions, false);
+        channels.consume_until(&ChannelID::from("c1"), &DataVersion::new(4));
+
+        let expected = BufferStats {
+            inserted: 6,
+            evicted: 3,
+            consumed: 2,
+        };
+        assert_eq!(channels.stats(&ChannelID::from("c1")), expected);
+        assert_eq!(channels.stats(&ChannelID::from("c2")), BufferStats::default());
+    }
+
+    #[test]
+    fn test_read_channel_rejects_regressions_if_configured() {
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        )
+        .with_reject_regression(true);
+        for i in [2, 4] {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            channels.c1().insert(packet).unwrap();
+        }
+        channels.consume_until(&ChannelID::from("c1"), &DataVersion::new(4));
+
+        let packet = Packet::new("late".to_string(), DataVersion::new(3));
+        assert_eq!(
+            channels.c1().insert(packet).unwrap_err(),
+            BufferError::VersionRegression(DataVersion::new(3), DataVersion::new(4))
+        );
+        let packet = Packet::new("new".to_string(), DataVersion::new(5));
+        assert!(channels.c1().insert(packet).is_ok());
+
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        );
+        let packet = Packet::new("data".to_string(), DataVersion::new(4));
+        channels.c1().insert(packet).unwrap();
+        channels.consume_until(&ChannelID::from("c1"), &DataVersion::new(4));
+        let packet = Packet::new("late".to_string(), DataVersion::new(3));
+        assert!(channels.c1().insert(packet).is_ok());
+    }
+
+    #[test]
+    fn test_read_channel_resumes_from_checkpointed_watermarks() {
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        );
+        for i in [2, 4] {
+            let packet = Packet::new(format!("data {i}"), DataVersion::new(i));
+            channels.c1().insert(packet).unwrap();
+        }
+        channels.consume_until(&ChannelID::from("c1"), &DataVersion::new(2));
+        let watermarks = channels.watermarks();
+        assert_eq!(
+            watermarks,
+            HashMap::from([(ChannelID::from("c1"), DataVersion::new(2))])
+        );
+
+        let mut channels = ReadChannel2::<String, String>::create(
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+            RtRingBuffer::<String>::new(4, false, BufferMonitor::default()),
+        )
+        .with_watermarks(&watermarks);
+        let packet = Packet::new("processed".to_string(), DataVersion::new(2));
+        assert_eq!(
+            channels.c1().insert(packet).unwrap_err(),
+            BufferError::VersionRegression(DataVersion::new(2), DataVersion::new(2))
+        );
+        let packet = Packet::new("new".to_string(), DataVersion::new(3));
+        assert!(channels.c1().insert(packet).is_ok());
+        let packet = Packet::new("new".to_string(), DataVersion::new(1));
+        assert!(channels.c2().insert(packet).is_ok());
+    }
+
+    #[test]
+    fn test_read_channel_with_channel_ids_renames_all_or_nothing() {
+        let create = || {
+            ReadChannel2::<String, String>::create(
+                RtRingBuffer::<String>::new(2, false, BufferMonitor::default()),
+                RtRingBuffer::<String>::new(2, false, BufferMonitor::default()),
+            )
+        };
+        let channels = create()
+            .with_channel_ids(&[ChannelID::from("imu"), ChannelID::from("gps")])
+            .unwrap();
+        assert_eq!(
+            channels.available_channels(),
+            [&ChannelID::from("imu"), &ChannelID::from("gps")]
+        );
+
+        assert_eq!(
+            create()
+                .with_channel_ids(&[ChannelID::from("imu"), ChannelID::from("imu")])
+                .err(),
+            Some(BufferError::DuplicateChannelError(ChannelID::from("imu")))
         );
+        assert!(create().with_channel_ids(&[ChannelID::from("imu")]).is_err());
     }
 
     #[test]
@@ -408,7 +1419,7 @@ mod tests {
             .c2()
             .link(channel_receiver);
 
-        let mut packet = Packet::new("my_data".to_string(), DataVersion { timestamp_ns: 1 });
+        let mut packet = Packet::new("my_data".to_string(), DataVersion::new(1));
 
         read_channel.start(WorkQueue::default());
         s1.send(packet.clone()).unwrap();
@@ -1,10 +1,10 @@
 //! A typed ReadChannel for a set of possible data inputs.
 //! There are currently only a maximum of 8 typed entry channels.
-use super::read_channel::get_data;
 use super::read_channel::BufferReceiver;
 use super::read_channel::ChannelBuffer;
 use super::read_channel::InputGenerator;
 use super::ChannelID;
+use super::LinkId;
 use crossbeam::channel::Select;
 use crate::{
 
@@ -14,15 +14,25 @@ use crate::{
 
 
 use super::ChannelError;
+use crate::buffers::single_buffers::BufferFactory;
 use crate::buffers::single_buffers::FixedSizeBuffer;
 use crate::buffers::single_buffers::LenTrait;
 use crate::buffers::BufferIterator;
 use crate::packet::work_queue::ReadEvent;
+use crate::packet::UntypedPacket;
+use crate::buffers::wal::{WalReader, WriteAheadLog};
+use crate::buffers::BufferError;
+use crate::buffers::ArrivalStats;
+use crate::buffers::BufferStats;
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::Path;
 use crate::DataVersion;
 
 use crossbeam::channel::select;
 use paste::item;
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
 struct NamedBufferReceiver<T: FixedSizeBuffer> {
@@ -34,21 +44,40 @@ macro_rules! read_channels {
     ($struct_name:ident, $($T:ident),+) => {
         item!{
             use crate::packet::typed::[<$struct_name PacketSet>];
+            /// The buffer of each channel is an `RtRingBuffer` unless another buffer
+            /// type is given after the data types, e.g. `ReadChannel2<A, B, FixedSizeRingBuffer<A>>`.
             #[allow(non_camel_case_types)]
-            pub struct $struct_name<$($T: Clone),+> {
+            pub struct $struct_name<
+                $($T: Clone),+,
+                $([<$T _buffer>]: FixedSizeBuffer<Data = $T> = RtRingBuffer<$T>),+
+            > {
                 $(
-                    $T: NamedBufferReceiver<RtRingBuffer<$T>>,
+                    $T: NamedBufferReceiver<[<$T _buffer>]>,
                 )+
                 channels: Vec<ChannelID>,
             }
         }
 
+        item! {
         #[allow(non_camel_case_types)]
-        impl<$($T: Clone + Send),+> ChannelBuffer for $struct_name<$($T),+> {
+        impl<
+            $($T: Clone + Send + 'static),+,
+            $([<$T _buffer>]: FixedSizeBuffer<Data = $T>),+
+        > ChannelBuffer for $struct_name<$($T),+, $([<$T _buffer>]),+> {
             fn available_channels(&self) -> Vec<&ChannelID> {
                 self.channels.iter().collect()
             }
 
+            fn unlinked_channels(&self) -> Vec<&ChannelID> {
+                let mut unlinked = vec![];
+                $(
+                    if self.$T.receiver.channel.is_none() {
+                        unlinked.push(&self.$T.id);
+                    }
+                )+
+                unlinked
+            }
+
             fn has_version(&self, channel: &ChannelID, version: &DataVersion) -> bool {
                 $(
                     if channel == &self.$T.id {
@@ -67,13 +96,49 @@ macro_rules! read_channels {
                 None
             }
 
+            fn latest_version(&self, channel: &ChannelID) -> Option<&DataVersion> {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.buffer.back();
+                    }
+                )+
+                None
+            }
+
             fn are_buffers_empty(&self) -> bool {
                 [$(
                     self.$T.receiver.buffer.len() == 0,
                 )+].iter().all(|b| *b)
             }
 
+            fn stats(&self, channel: &ChannelID) -> BufferStats {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.stats();
+                    }
+                )+
+                BufferStats::default()
+            }
+
+            fn arrival_stats(&self, channel: &ChannelID) -> ArrivalStats {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.arrival_stats();
+                    }
+                )+
+                ArrivalStats::default()
+            }
+
+            fn occupancy(&self) -> HashMap<ChannelID, usize> {
+                HashMap::from([$(
+                    (self.$T.id.clone(), self.$T.receiver.buffer.len()),
+                )+])
+            }
+
             fn wait_for_data(&self, timeout: Duration) -> Result<bool, ChannelError>{
+                if [$(self.$T.receiver.has_lookahead(),)+].iter().any(|b| *b) {
+                    return Ok(true);
+                }
                 let mut select = Select::new();
                 $(select.recv(&self.$T.receiver.channel.as_ref().expect(&format!("Node {} has no reader channel {}",
                     stringify!($struct_name), self.$T.id)).receiver);)+
@@ -85,6 +150,13 @@ macro_rules! read_channels {
             }
 
             fn try_receive(&mut self, timeout: Duration) -> Result<Option<&ChannelID>, ChannelError>{
+                // A packet read ahead by `peek` is not seen by the select.
+                $(
+                    if let Some(inserted) = self.$T.receiver.insert_lookahead() {
+                        inserted?;
+                        return Ok(Some(&self.$T.id));
+                    }
+                )+
                 let has_data = select! {
                     $(
                         recv(self.$T.receiver.channel
@@ -92,11 +164,12 @@ macro_rules! read_channels {
                             .expect(&format!("Node {} has no reader channel {}",
                                 stringify!($struct_name), self.$T.id)).receiver) -> msg =>
                                     {
-                                        if self.$T.receiver.buffer.insert(msg?).is_ok() {
-                                            Some(&self.$T.id)
-                                        } else {
-                                            None
+                                        let msg = msg.map_err(|_| ChannelError::Disconnected(self.$T.id.clone()))?;
+                                        if let Some(channel) = self.$T.receiver.channel.as_ref() {
+                                            channel.record_received();
                                         }
+                                        self.$T.receiver.insert(msg)?;
+                                        Some(&self.$T.id)
                                     },
                     )+
                     default(timeout) => None,
@@ -104,6 +177,50 @@ macro_rules! read_channels {
                 Ok(has_data)
             }
 
+            fn link_untyped(
+                &mut self,
+                channel: &ChannelID,
+                receiver: Box<dyn Any>,
+            ) -> Result<(), ChannelError> {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.link_untyped(receiver);
+                    }
+                )+
+                Err(ChannelError::MissingChannel(channel.clone()))
+            }
+
+            fn check_link(
+                &self,
+                channel: &ChannelID,
+                receiver: &dyn Any,
+            ) -> Result<(), ChannelError> {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.check_link(receiver);
+                    }
+                )+
+                Err(ChannelError::MissingChannel(channel.clone()))
+            }
+
+            fn links(&self) -> Vec<(ChannelID, LinkId)> {
+                let mut links = vec![];
+                $(
+                    if let Some(channel) = self.$T.receiver.channel.as_ref() {
+                        links.push((self.$T.id.clone(), channel.link_id()));
+                    }
+                )+
+                links
+            }
+
+            fn discard_before(&mut self, channel: &ChannelID, version: &DataVersion) {
+                $(
+                    if channel == &self.$T.id {
+                        self.$T.receiver.discard_before(version);
+                    }
+                )+
+            }
+
             fn iterator(&self, channel: &ChannelID) -> Option<Box<BufferIterator>> {
                 $(
                     if channel == &self.$T.id {
@@ -122,12 +239,15 @@ macro_rules! read_channels {
         }
 
         #[allow(non_camel_case_types, dead_code)]
-        impl<$($T: Clone + Send),+> $struct_name<$($T),+> {
-            pub fn create($($T: RtRingBuffer<$T>),+) -> Self {
+        impl<
+            $($T: Clone + Send),+,
+            $([<$T _buffer>]: FixedSizeBuffer<Data = $T>),+
+        > $struct_name<$($T),+, $([<$T _buffer>]),+> {
+            pub fn create($($T: [<$T _buffer>]),+) -> Self {
                 Self {
                     $(
                         $T: NamedBufferReceiver {
-                            receiver: BufferReceiver {buffer: Box::new($T), channel: None},
+                            receiver: BufferReceiver::new(Box::new($T)),
                             id: ChannelID::from(stringify!($T))
                         },
                     )+
@@ -136,24 +256,181 @@ macro_rules! read_channels {
             }
 
             $(
-                pub fn $T(&mut self) -> &mut BufferReceiver<RtRingBuffer<$T>> {
+                pub fn $T(&mut self) -> &mut BufferReceiver<[<$T _buffer>]> {
                     &mut self.$T.receiver
                 }
             )+
+
+            /// Names the channels, in order, with the given ids instead of `c1`, `c2`, ...
+            /// Either all the channels are renamed or none is, if there is an error.
+            ///
+            /// * Returns
+            ///
+            /// `BufferError::DuplicateChannelError` if an id is used twice.
+            pub fn with_channel_ids(mut self, ids: &[ChannelID]) -> Result<Self, BufferError> {
+                if ids.len() != self.channels.len() {
+                    return Err(BufferError::InternalError(format!(
+                        "Expected {} channel ids, got {}", self.channels.len(), ids.len()
+                    )));
+                }
+                let mut unique = std::collections::HashSet::new();
+                if let Some(duplicate) = ids.iter().find(|id| !unique.insert(*id)) {
+                    return Err(BufferError::DuplicateChannelError(duplicate.clone()));
+                }
+                let mut ids = ids.iter().cloned();
+                $(
+                    self.$T.id = ids.next().expect("Number of ids already checked");
+                )+
+                self.channels = vec![$(self.$T.id.clone(),)+];
+                Ok(self)
+            }
+
+            /// If true, inserting in a channel a version older than the latest consumed
+            /// one returns `BufferError::VersionRegression`. False by default.
+            pub fn with_reject_regression(mut self, reject_regression: bool) -> Self {
+                $(
+                    self.$T.receiver.reject_regression = reject_regression;
+                )+
+                self
+            }
+
+            /// The version of the last packet consumed in each channel, to checkpoint
+            /// and resume from with `with_watermarks`. Channels never consumed are left out.
+            pub fn watermarks(&self) -> HashMap<ChannelID, DataVersion> {
+                let mut watermarks = HashMap::new();
+                $(
+                    if let Some(version) = self.$T.receiver.last_consumed() {
+                        watermarks.insert(self.$T.id.clone(), version);
+                    }
+                )+
+                watermarks
+            }
+
+            /// Resumes the channels from the watermarks returned by `watermarks`, rejecting
+            /// the versions already consumed with `BufferError::VersionRegression`.
+            pub fn with_watermarks(mut self, watermarks: &HashMap<ChannelID, DataVersion>) -> Self {
+                $(
+                    if let Some(version) = watermarks.get(&self.$T.id) {
+                        self.$T.receiver.resume_from(*version);
+                    }
+                )+
+                self
+            }
+
+            /// Logs every packet inserted into the buffers to a write-ahead log at `path`.
+            pub fn with_wal(mut self, path: impl AsRef<Path>) -> Result<Self, BufferError>
+            where
+                $($T: Serialize + 'static),+
+            {
+                let wal = WriteAheadLog::create(path)?;
+                $(
+                    self.$T.receiver.recorder = Some(wal.recorder(self.$T.id.clone()));
+                )+
+                Ok(self)
+            }
+
+            /// Inserts the packets of a write-ahead log back into the buffers in their
+            /// original order, without recording them again. Inserts failing at recording
+            /// time fail again, so the buffers end up in the same state. The packets of
+            /// channels this ReadChannel does not have are skipped.
+            ///
+            /// * Returns
+            ///
+            /// The number of packets inserted and the errors of the inserts that failed.
+            pub fn replay(
+                &mut self,
+                path: impl AsRef<Path>,
+            ) -> Result<(usize, Vec<BufferError>), BufferError>
+            where
+                $($T: DeserializeOwned),+
+            {
+                let mut count = 0;
+                let mut errors = vec![];
+                for entry in WalReader::open(path)? {
+                    let entry = entry?;
+                    $(
+                        if entry.channel == self.$T.id {
+                            match self.$T.receiver.insert_unrecorded(entry.packet::<$T>()?) {
+                                Ok(()) => count += 1,
+                                Err(err) => errors.push(err),
+                            }
+                        }
+                    )+
+                }
+                Ok((count, errors))
+            }
+
+            /// Removes all packets of `channel` up to and including `version`.
+            ///
+            /// * Returns
+            ///
+            /// The removed packets from the oldest, empty if the channel does not exist.
+            pub fn consume_until(
+                &mut self,
+                channel: &ChannelID,
+                version: &DataVersion,
+            ) -> Vec<UntypedPacket>
+            where
+                $($T: 'static),+
+            {
+                $(
+                    if channel == &self.$T.id {
+                        return self.$T.receiver.consume_until(version)
+                            .into_iter()
+                            .map(|packet| packet.to_untyped())
+                            .collect();
+                    }
+                )+
+                vec![]
+            }
+
+            /// Registers a callback called with the channel id for each packet dropped
+            /// by one of the buffers before being consumed.
+            pub fn on_evict<F>(&mut self, on_evict: F)
+            where
+                F: Fn(ChannelID, UntypedPacket) + Send + Sync + 'static,
+                $($T: 'static),+
+            {
+                let on_evict = Arc::new(on_evict);
+                $(
+                    let callback = on_evict.clone();
+                    let id = self.$T.id.clone();
+                    self.$T.receiver.buffer.set_on_evict(Box::new(move |packet| {
+                        callback(id.clone(), packet.to_untyped())
+                    }));
+                )+
+            }
+        }
         }
 
         item! {
             #[allow(non_camel_case_types)]
-            impl<$($T: Clone + Send),+> InputGenerator for $struct_name<$($T),+> {
+            impl<
+                $($T: Clone + Send),+,
+                $([<$T _buffer>]: FixedSizeBuffer<Data = $T> + BufferFactory),+
+            > InputGenerator for $struct_name<$($T),+, $([<$T _buffer>]),+> {
                 type INPUT = [<$struct_name PacketSet>]<$($T),+>;
 
                 fn create_channels(
                     buffer_size: usize,
                     block_on_full: bool,
                     monitor: BufferMonitorBuilder
-                ) -> $struct_name<$($T),+> {
+                ) -> Self {
+                    Self::create_channels_with_sizes(buffer_size, &HashMap::new(), block_on_full, monitor)
+                }
+
+                fn create_channels_with_sizes(
+                    buffer_size: usize,
+                    channel_sizes: &HashMap<ChannelID, usize>,
+                    block_on_full: bool,
+                    monitor: BufferMonitorBuilder
+                ) -> Self {
                     $struct_name::create(
-                        $(RtRingBuffer::<$T>::new(buffer_size, block_on_full, monitor.make_channel(stringify!($T)))),+
+                        $([<$T _buffer>]::create(
+                            channel_sizes.get(&ChannelID::from(stringify!($T))).copied().unwrap_or(buffer_size),
+                            block_on_full,
+                            monitor.make_channel(stringify!($T))
+                        )),+
                     )
                 }
 
@@ -166,7 +443,7 @@ macro_rules! read_channels {
 
                     $(
                         let version = data_versions.get(&self.$T.id).expect(&format!("Cannot find channel {}", self.$T.id));
-                        let data = get_data(&mut self.$T.receiver.buffer, version, exact_match);
+                        let data = self.$T.receiver.get_data(version, exact_match);
                         result.[<set_ $T>](data);
                     )+
 
@@ -223,10 +500,18 @@ impl ChannelBuffer for NoBuffer {
         todo!()
     }
 
+    fn latest_version(&self, _: &ChannelID) -> Option<&DataVersion> {
+        None
+    }
+
     fn are_buffers_empty(&self) -> bool {
         todo!()
     }
 
+    fn occupancy(&self) -> HashMap<ChannelID, usize> {
+        HashMap::new()
+    }
+
     fn try_receive(&mut self, _: Duration) -> Result<Option<&ChannelID>, ChannelError> {
         todo!()
     }
@@ -1,6 +1,15 @@
 //! A typed WriteChannel for a set of possible data outputs.
 //! There are currently only a maximum of 8 typed output channels.
-use super::{ChannelError, Packet, SenderChannel};
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::{
+    typed_channel, ChannelError, ChannelID, LinkId, Packet, SenderChannel, UntypedPacket,
+    UntypedPacketCast, WaterMarks,
+};
+use crate::buffers::single_buffers::LenTrait;
 use crate::channels::WriteChannelTrait;
 use crate::DataVersion;
 
@@ -8,24 +17,356 @@ pub struct TypedWriteChannel<OUTPUT: WriteChannelTrait + ?Sized> {
     pub writer: Box<OUTPUT>,
 }
 
+/// A downstream queue watched by a BufferWriter.
+struct Backpressure {
+    queue: Box<dyn LenTrait + Send>,
+    water_marks: WaterMarks,
+    congested: AtomicBool,
+}
+
+impl Backpressure {
+    fn is_congested(&self) -> bool {
+        let len = self.queue.len();
+        if len >= self.water_marks.high {
+            self.congested.store(true, Ordering::Relaxed);
+        } else if len <= self.water_marks.low {
+            self.congested.store(false, Ordering::Relaxed);
+        }
+        self.congested.load(Ordering::Relaxed)
+    }
+}
+
+/// Replays packets at the pace of their timestamps. The first packet written is
+/// due immediately, the following ones once as much time passed since the first,
+/// divided by the speed, as between their timestamps.
+pub struct ReplayClock {
+    speed: f64,
+    state: Mutex<ReplayState>,
+    stopped: Condvar,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    start: Option<(Instant, u128)>,
+    stopped: bool,
+}
+
+impl ReplayClock {
+    /// Panics if `speed` is not positive.
+    pub fn new(speed: f64) -> Self {
+        if speed <= 0.0 || !speed.is_finite() {
+            panic!("Replay speed must be positive, got {speed}");
+        }
+        Self {
+            speed,
+            state: Mutex::default(),
+            stopped: Condvar::new(),
+        }
+    }
+
+    /// Blocks until the packet with `version` is due or the clock is stopped.
+    /// Packets older than the first one are due immediately.
+    pub fn wait(&self, version: &DataVersion) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (started_at, first_timestamp) =
+            *state.start.get_or_insert((Instant::now(), version.timestamp_ns));
+        let delta_ns = version.timestamp_ns.saturating_sub(first_timestamp) as f64;
+        let due = started_at + Duration::from_secs_f64(delta_ns / self.speed / 1e9);
+        let wait = due.saturating_duration_since(Instant::now());
+        let _ = self
+            .stopped
+            .wait_timeout_while(state, wait, |state| !state.stopped)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Releases the writers waiting on the clock, e.g. when the graph terminates.
+    /// From now on packets are due immediately.
+    pub fn stop(&self) {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stopped = true;
+        self.stopped.notify_all();
+    }
+}
+
+/// Whether the packets written on a channel anchor a `TimeAlignment` or are shifted by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentRole {
+    /// The first packet written sets the timestamp the recorded streams are aligned to.
+    Live,
+    /// The packets are shifted to line up with the live streams.
+    Recorded,
+}
+
+#[derive(Default)]
+struct AlignmentStart {
+    live_ns: Option<u128>,
+    recorded_ns: Option<u128>,
+}
+
+/// Aligns recorded streams to live ones sharing the alignment: the first recorded
+/// packet is shifted to the timestamp of the first live packet, and the following
+/// ones by the same offset.
+#[derive(Clone, Default)]
+pub struct TimeAlignment {
+    start: Arc<(Mutex<AlignmentStart>, Condvar)>,
+}
+
+impl TimeAlignment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Anchors the alignment to the first live version, the following ones are ignored.
+    pub fn observe_live(&self, version: &DataVersion) {
+        let (start, anchored) = &*self.start;
+        let mut start = start.lock().unwrap_or_else(PoisonError::into_inner);
+        if start.live_ns.is_none() {
+            start.live_ns = Some(version.timestamp_ns);
+            anchored.notify_all();
+        }
+    }
+
+    /// True once a live packet was written.
+    pub fn is_anchored(&self) -> bool {
+        self.lock().live_ns.is_some()
+    }
+
+    /// Waits up to `timeout` for the first live packet.
+    ///
+    /// * Returns
+    /// True if the alignment is anchored.
+    pub fn wait_anchored(&self, timeout: Duration) -> bool {
+        let (start, anchored) = &*self.start;
+        let start = start.lock().unwrap_or_else(PoisonError::into_inner);
+        let (start, _) = anchored
+            .wait_timeout_while(start, timeout, |start| start.live_ns.is_none())
+            .unwrap_or_else(PoisonError::into_inner);
+        start.live_ns.is_some()
+    }
+
+    /// Nanoseconds added to the recorded timestamps, known once both a live and a
+    /// recorded packet were written.
+    pub fn offset_ns(&self) -> Option<i128> {
+        let start = self.lock();
+        Some(start.live_ns? as i128 - start.recorded_ns? as i128)
+    }
+
+    /// Shifts a recorded version, the first one sets the offset. Versions aligned
+    /// before the alignment is anchored are unchanged.
+    pub fn align(&self, version: &DataVersion) -> DataVersion {
+        let mut start = self.lock();
+        let live_ns = match start.live_ns {
+            Some(live_ns) => live_ns,
+            None => return *version,
+        };
+        let recorded_ns = *start.recorded_ns.get_or_insert(version.timestamp_ns);
+        let timestamp_ns = version.timestamp_ns as i128 + live_ns as i128 - recorded_ns as i128;
+        DataVersion::new(timestamp_ns.max(0) as u128).with_sequence(version.sequence)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AlignmentStart> {
+        self.start.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The timestamp of the last packet written on a channel validating the order of versions.
+struct VersionOrder {
+    channel: ChannelID,
+    previous_ns: Option<u128>,
+}
+
+impl VersionOrder {
+    fn check(&mut self, version: &DataVersion) -> Result<(), ChannelError> {
+        match self.previous_ns {
+            Some(previous_ns) if version.timestamp_ns < previous_ns => {
+                Err(ChannelError::OutOfOrderVersion {
+                    channel: self.channel.clone(),
+                    previous_ns,
+                    timestamp_ns: version.timestamp_ns,
+                })
+            }
+            _ => {
+                self.previous_ns = Some(version.timestamp_ns);
+                Ok(())
+            }
+        }
+    }
+}
+
 pub struct BufferWriter<U> {
     pub channels: Vec<SenderChannel<U>>,
+    backpressure: Vec<Backpressure>,
+    replay_clock: Option<Arc<ReplayClock>>,
+    version_order: Option<Mutex<VersionOrder>>,
+    /// The packets written since the last commit, if the writes are transactional.
+    staged: Option<Mutex<Vec<Packet<U>>>>,
+    alignment: Option<(TimeAlignment, AlignmentRole)>,
+    /// Set once a write found no receiver left, to warn only once.
+    disconnected: AtomicBool,
 }
 
 impl<U: Clone + 'static> Default for BufferWriter<U> {
     fn default() -> Self {
-        Self { channels: vec![] }
+        Self {
+            channels: vec![],
+            backpressure: vec![],
+            replay_clock: None,
+            version_order: None,
+            staged: None,
+            alignment: None,
+            disconnected: AtomicBool::new(false),
+        }
     }
 }
 
 impl<U: Clone + 'static> BufferWriter<U> {
     pub fn link(&mut self, sender: SenderChannel<U>) {
         self.channels.push(sender);
+        *self.disconnected.get_mut() = false;
     }
-    pub fn write(&self, data: U, version: &DataVersion) -> Result<(), ChannelError> {
+
+    /// Identifies the linked channels.
+    pub fn links(&self) -> Vec<LinkId> {
         self.channels
             .iter()
-            .try_for_each(|sender| sender.send(Packet::<U>::new(data.clone(), *version)))?;
+            .filter_map(|sender| sender.link_id())
+            .collect()
+    }
+
+    /// True if any of the linked channels still has a receiver.
+    pub fn has_consumers(&self) -> bool {
+        self.channels.iter().any(|channel| channel.has_consumer())
+    }
+
+    /// Removes the linked channels whose receiver was dropped.
+    pub fn unlink_dropped(&mut self) {
+        self.channels.retain(|channel| channel.has_consumer());
+    }
+
+    /// Couples the writer to a queue downstream, usually the work queue of the node
+    /// reading this channel. See `WaterMarks` for when the writer is congested.
+    pub fn set_backpressure(
+        &mut self,
+        queue: impl LenTrait + Send + 'static,
+        water_marks: WaterMarks,
+    ) {
+        self.backpressure.push(Backpressure {
+            queue: Box::new(queue),
+            water_marks,
+            congested: AtomicBool::new(false),
+        });
+    }
+
+    /// Paces the writes with `clock`, see `ReplayClock`.
+    pub fn set_replay_clock(&mut self, clock: Arc<ReplayClock>) {
+        self.replay_clock = Some(clock);
+    }
+
+    /// Anchors `alignment` with the written versions or shifts them by it, see `TimeAlignment`.
+    pub fn set_time_alignment(&mut self, alignment: TimeAlignment, role: AlignmentRole) {
+        self.alignment = Some((alignment, role));
+    }
+
+    /// Rejects the packets with a timestamp older than the previous one written.
+    /// `channel` is the id of the channel reported in the errors.
+    pub fn validate_version_order(&mut self, channel: ChannelID) {
+        self.version_order = Some(Mutex::new(VersionOrder {
+            channel,
+            previous_ns: None,
+        }));
+    }
+
+    /// Stages the written packets until `commit` sends them or `rollback` drops them.
+    pub fn set_transactional(&mut self) {
+        self.staged.get_or_insert_with(Mutex::default);
+    }
+
+    /// Sends the packets staged since the last commit, in the order they were written.
+    /// Does nothing if the writes are not transactional.
+    ///
+    /// * Returns
+    /// The error of the first packet that could not be written, the next ones are dropped.
+    pub fn commit(&mut self) -> Result<(), ChannelError> {
+        let staged = match self.staged.as_mut() {
+            Some(staged) => std::mem::take(staged.get_mut().unwrap_or_else(PoisonError::into_inner)),
+            None => return Ok(()),
+        };
+        staged
+            .into_iter()
+            .try_for_each(|packet| self.send_packet(packet))
+    }
+
+    /// Drops the packets staged since the last commit.
+    pub fn rollback(&mut self) {
+        if let Some(staged) = self.staged.as_mut() {
+            staged.get_mut().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+    }
+
+    /// True if any of the queues set with `set_backpressure` is congested.
+    /// All the queues are checked to keep their state up to date.
+    pub fn is_congested(&self) -> bool {
+        self.backpressure
+            .iter()
+            .filter(|backpressure| backpressure.is_congested())
+            .count()
+            > 0
+    }
+    /// Sends the data to all the linked channels, stopping at the first error.
+    /// Channels whose receiver was dropped, for instance because its node was
+    /// removed from the graph, are skipped. A warning is logged the first time no
+    /// receiver is left.
+    /// If a replay clock is set, it first waits for the packet to be due.
+    /// If the order of versions is validated, packets older than the previous one
+    /// are not sent and a `ChannelError::OutOfOrderVersion` is returned.
+    pub fn write(&self, data: U, version: &DataVersion) -> Result<(), ChannelError> {
+        self.write_packet(Packet::new(data, *version))
+    }
+
+    /// Like `write`, but keeps the metadata of the packet.
+    /// If the writes are transactional the packet is only staged, see `set_transactional`.
+    pub fn write_packet(&self, packet: Packet<U>) -> Result<(), ChannelError> {
+        if let Some(staged) = self.staged.as_ref() {
+            staged
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(packet);
+            return Ok(());
+        }
+        self.send_packet(packet)
+    }
+
+    fn send_packet(&self, mut packet: Packet<U>) -> Result<(), ChannelError> {
+        match self.alignment.as_ref() {
+            Some((alignment, AlignmentRole::Live)) => alignment.observe_live(&packet.version),
+            Some((alignment, AlignmentRole::Recorded)) => {
+                packet.version = alignment.align(&packet.version);
+                packet.end = packet.end.map(|end| alignment.align(&end));
+            }
+            None => {}
+        }
+        if let Some(version_order) = self.version_order.as_ref() {
+            version_order
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .check(&packet.version)?;
+        }
+        if let Some(clock) = self.replay_clock.as_ref() {
+            clock.wait(&packet.version);
+        }
+        let mut sent = false;
+        for sender in self.channels.iter() {
+            match sender.send(packet.clone()) {
+                Ok(()) => sent = true,
+                Err(ChannelError::SendError(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !sent && !self.channels.is_empty() && !self.disconnected.swap(true, Ordering::Relaxed) {
+            tracing::warn!("Every receiver of the channel was dropped, the written data is lost");
+        }
         Ok(())
     }
 }
@@ -48,6 +389,112 @@ macro_rules! write_channels {
                     )+
                 }
             }
+
+            fn has_consumers(&self, channel: &ChannelID) -> bool {
+                $(
+                    if channel.id() == stringify!($T) {
+                        return self.$T.has_consumers();
+                    }
+                )+
+                false
+            }
+
+            fn unlinked_channels(&self) -> Vec<ChannelID> {
+                let mut unlinked = vec![];
+                $(
+                    if self.$T.channels.is_empty() {
+                        unlinked.push(ChannelID::from(stringify!($T)));
+                    }
+                )+
+                unlinked
+            }
+
+            fn write_untyped(
+                &mut self,
+                channel: &ChannelID,
+                packet: UntypedPacket,
+            ) -> Result<(), ChannelError> {
+                $(
+                    if channel.id() == stringify!($T) {
+                        return self.$T.write_packet(packet.deref_cloned::<$T>()?);
+                    }
+                )+
+                Err(ChannelError::MissingChannel(channel.clone()))
+            }
+
+            fn link_untyped(&mut self, channel: &ChannelID) -> Result<Box<dyn Any>, ChannelError> {
+                $(
+                    if channel.id() == stringify!($T) {
+                        let (sender, receiver) = typed_channel::<$T>();
+                        self.$T.link(sender);
+                        return Ok(Box::new(receiver));
+                    }
+                )+
+                Err(ChannelError::MissingChannel(channel.clone()))
+            }
+
+            fn unlink_dropped(&mut self) {
+                $(
+                    self.$T.unlink_dropped();
+                )+
+            }
+
+            fn links(&self) -> Vec<(ChannelID, LinkId)> {
+                let mut links = vec![];
+                $(
+                    for link in self.$T.links() {
+                        links.push((ChannelID::from(stringify!($T)), link));
+                    }
+                )+
+                links
+            }
+
+            fn is_congested(&self) -> bool {
+                [$(
+                    self.$T.is_congested(),
+                )+].iter().any(|congested| *congested)
+            }
+
+            fn set_replay_clock(&mut self, clock: Arc<ReplayClock>) {
+                $(
+                    self.$T.set_replay_clock(clock.clone());
+                )+
+            }
+
+            fn set_time_alignment(&mut self, alignment: &TimeAlignment, role: AlignmentRole) {
+                $(
+                    self.$T.set_time_alignment(alignment.clone(), role);
+                )+
+            }
+
+            fn validate_version_order(&mut self) {
+                $(
+                    self.$T.validate_version_order(ChannelID::from(stringify!($T)));
+                )+
+            }
+
+            fn set_transactional(&mut self) {
+                $(
+                    self.$T.set_transactional();
+                )+
+            }
+
+            fn commit(&mut self) -> Result<(), ChannelError> {
+                let mut result = Ok(());
+                $(
+                    let committed = self.$T.commit();
+                    if result.is_ok() {
+                        result = committed;
+                    }
+                )+
+                result
+            }
+
+            fn rollback(&mut self) {
+                $(
+                    self.$T.rollback();
+                )+
+            }
         }
 
         #[allow(non_camel_case_types, dead_code)]
@@ -81,7 +528,11 @@ mod tests {
 
     use crate::DataVersion;
 
-    use super::WriteChannel3;
+    use super::{ReplayClock, WriteChannel3};
+    use crate::channels::{ChannelError, ChannelID, Packet};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     fn create_write_channel() -> (
         WriteChannel3<String, String, String>,
@@ -93,6 +544,164 @@ mod tests {
         (write_channel, crossbeam_channels.1)
     }
 
+    #[test]
+    fn test_replay_clock_paces_writes_by_timestamp() {
+        let (mut write_channel, read_channel) = create_write_channel();
+        write_channel.set_replay_clock(Arc::new(ReplayClock::new(2.0)));
+
+        let start = Instant::now();
+        for timestamp_ms in [1000, 1040, 1100] {
+            write_channel
+                .c1
+                .write("TestData".to_string(), &DataVersion::new(timestamp_ms * 1_000_000))
+                .unwrap();
+        }
+        // 100ms of data replayed twice as fast.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_secs(1));
+        assert_eq!(read_channel.receiver.len(), 3);
+    }
+
+    #[test]
+    fn test_stopped_replay_clock_releases_waiting_writes() {
+        let clock = Arc::new(ReplayClock::new(1.0));
+        clock.wait(&DataVersion::new(0));
+
+        let waiting = clock.clone();
+        let start = Instant::now();
+        let writer = thread::spawn(move || waiting.wait(&DataVersion::from_millis(3_600_000)));
+        thread::sleep(Duration::from_millis(10));
+        clock.stop();
+        writer.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_channels_without_receivers_have_no_consumers() {
+        let (write_channel, read_channel) = create_write_channel();
+        assert!(write_channel.has_consumers(&ChannelID::from("c1")));
+        assert!(!write_channel.has_consumers(&ChannelID::from("c2")));
+        assert!(!write_channel.has_consumers(&ChannelID::from("missing")));
+
+        drop(read_channel);
+        assert!(!write_channel.has_consumers(&ChannelID::from("c1")));
+    }
+
+    #[test]
+    fn test_out_of_order_versions_are_rejected() {
+        let (mut write_channel, read_channel) = create_write_channel();
+        write_channel.validate_version_order();
+
+        for timestamp in [10, 10, 20] {
+            write_channel
+                .c1
+                .write("TestData".to_string(), &DataVersion::new(timestamp))
+                .unwrap();
+        }
+        assert_eq!(
+            write_channel
+                .c1
+                .write("TestData".to_string(), &DataVersion::new(15)),
+            Err(ChannelError::OutOfOrderVersion {
+                channel: ChannelID::from("c1"),
+                previous_ns: 20,
+                timestamp_ns: 15,
+            })
+        );
+        write_channel
+            .c1
+            .write("TestData".to_string(), &DataVersion::new(20))
+            .unwrap();
+        assert_eq!(read_channel.receiver.len(), 4);
+    }
+
+    #[test]
+    fn test_write_untyped_casts_to_channel_type() {
+        let (mut write_channel, read_channel) = create_write_channel();
+        let packet = Packet::new("TestData".to_string(), DataVersion::new(1)).to_untyped();
+        write_channel.write_untyped(&ChannelID::from("c1"), packet).unwrap();
+        assert_eq!(*read_channel.try_receive().unwrap().data, "TestData".to_string());
+
+        let packet = Packet::new(1_u32, DataVersion::new(2)).to_untyped();
+        assert!(matches!(
+            write_channel.write_untyped(&ChannelID::from("c1"), packet),
+            Err(ChannelError::PacketError(_))
+        ));
+
+        let packet = Packet::new("TestData".to_string(), DataVersion::new(3)).to_untyped();
+        assert_eq!(
+            write_channel.write_untyped(&ChannelID::from("c4"), packet),
+            Err(ChannelError::MissingChannel(ChannelID::from("c4")))
+        );
+    }
+
+    #[test]
+    fn test_send_to_writes_to_channel_by_id() {
+        let (mut write_channel, read_channel) = create_write_channel();
+        write_channel
+            .send_to(
+                &ChannelID::from("c1"),
+                Packet::new("TestData".to_string(), DataVersion::new(1)),
+            )
+            .unwrap();
+        assert_eq!(read_channel.try_receive().unwrap().version, DataVersion::new(1));
+
+        assert_eq!(
+            write_channel.send_to(
+                &ChannelID::from("c_1"),
+                Packet::new("TestData".to_string(), DataVersion::new(2)),
+            ),
+            Err(ChannelError::MissingChannel(ChannelID::from("c_1")))
+        );
+    }
+
+    #[test]
+    fn test_transactional_writes_are_sent_on_commit() {
+        let (mut write_channel, read_channel) = create_write_channel();
+        write_channel.set_transactional();
+
+        for timestamp in [1, 2] {
+            write_channel
+                .c1
+                .write("TestData".to_string(), &DataVersion::new(timestamp))
+                .unwrap();
+        }
+        assert_eq!(read_channel.receiver.len(), 0);
+        write_channel.commit().unwrap();
+        assert_eq!(read_channel.receiver.len(), 2);
+
+        write_channel
+            .c1
+            .write("TestData".to_string(), &DataVersion::new(3))
+            .unwrap();
+        write_channel.rollback();
+        write_channel.commit().unwrap();
+        assert_eq!(read_channel.receiver.len(), 2);
+    }
+
+    #[test]
+    fn test_write_skips_dropped_receivers() {
+        let (mut write_channel, existing_read_channel) = create_write_channel();
+        let channel = typed_channel::<String>();
+        write_channel.c1.channels.insert(0, channel.0);
+        drop(channel.1);
+
+        write_channel
+            .c1
+            .write("TestData".to_string(), &DataVersion::new(1))
+            .unwrap();
+
+        assert_eq!(write_channel.c1.channels.len(), 2);
+        assert_eq!(
+            *existing_read_channel.try_receive().unwrap().data,
+            "TestData".to_string()
+        );
+
+        write_channel.c1.unlink_dropped();
+        assert_eq!(write_channel.c1.channels.len(), 1);
+    }
+
     #[test]
     fn test_send_on_existing_channel_fans_out_to_all_receivers() {
         let (mut write_channel, _existing_read_channel) = create_write_channel();
@@ -107,7 +716,7 @@ mod tests {
 
         write_channel
             .c1
-            .write("TestData".to_string(), &DataVersion { timestamp_ns: 1 })
+            .write("TestData".to_string(), &DataVersion::new(1))
             .unwrap();
 
         for channel in read_channels {
@@ -1,8 +1,12 @@
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, Condvar, Mutex},
+    any::Any,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Condvar, Mutex, PoisonError, RwLock,
+    },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::channels::WriteChannelTrait;
@@ -10,41 +14,81 @@ use crate::channels::{typed_read_channel::NoBuffer, typed_write_channel::WriteCh
 use crate::{
     buffers::single_buffers::FixedSizeBuffer,
     channels::{
-        read_channel::{BufferReceiver, ChannelBuffer, InputGenerator},
+        read_channel::{BufferReceiver, ChannelBuffer, InputGenerator, ReadChannel},
         typed_channel,
-        typed_write_channel::{BufferWriter, TypedWriteChannel},
+        typed_write_channel::{BufferWriter, ReplayClock, TypedWriteChannel},
+        ChannelError, ChannelID, LinkId, ReceiverChannel, SenderChannel,
     },
     graph::{
         processor::Processors,
-        runtime::{read_channel_data, ConsumerThread},
+        runtime::{
+            read_channel_data, ConsumerThread, Executor, RuntimeConfig, SourceTracker, WorkerError,
+        },
     },
     RustedPipeError,
 };
-use crate::{channels::ReadChannelTrait, graph::metrics::ProfilerTag};
+use crate::{
+    channels::ReadChannelTrait,
+    graph::metrics::{ProfileEntry, ProfileRecord, ProfilerTag},
+};
 use atomic::{Atomic, Ordering};
 use crossbeam::channel::{unbounded, Receiver, Sender};
 use itertools::Itertools;
-use log::debug;
 use rusty_pool::ThreadPool;
+use thiserror::Error;
 
 use super::{
+    clock::Clock,
+    governor::{self, GovernorConfig},
     metrics::Metrics,
     processor::{Node, Nodes, SourceNode, TerminalNode},
     runtime::Wait,
+    topology::{NodeKind, Topology},
+    watchdog::{self, BufferProbe, ChannelsProbe, WatchdogConfig},
 };
 use crate::packet::work_queue::WorkQueue;
 
-pub struct Graph {
+pub struct Graph<E: Executor = ThreadPool> {
     running: Arc<Atomic<GraphStatus>>,
     thread_control: Vec<Wait>,
-    pool: ThreadPool,
+    pool: E,
     node_threads: HashMap<String, JoinHandle<()>>,
+    worker_status: HashMap<String, Arc<Atomic<WorkerStatus>>>,
+    processed: HashMap<String, Arc<AtomicUsize>>,
     read_threads: HashMap<String, JoinHandle<()>>,
+    reader_stops: HashMap<String, Arc<AtomicBool>>,
+    worker_stops: HashMap<String, Arc<AtomicBool>>,
+    buffer_probes: HashMap<String, Arc<dyn BufferProbe>>,
+    // The channels of the started nodes, linked to the nodes added with `add_node`.
+    node_inputs: HashMap<String, Arc<dyn InputLinks>>,
+    node_outputs: HashMap<String, Arc<dyn OutputLinks>>,
+    // Clocks pacing replayed sources, stopped with the graph so that writes do not block it.
+    replay_clocks: HashMap<String, Arc<ReplayClock>>,
+    watchdog: Option<JoinHandle<()>>,
+    governor: Option<JoinHandle<()>>,
     worker_done: (Sender<String>, Receiver<String>),
     reader_empty: (Sender<String>, Receiver<String>),
+    worker_errors: Option<Sender<WorkerError>>,
+    // Every failure of the workers, by node id, see `errors`.
+    worker_failures: (
+        Sender<(String, RustedPipeError)>,
+        Receiver<(String, RustedPipeError)>,
+    ),
+    failures: Mutex<Vec<(String, RustedPipeError)>>,
+    sources: SourceTracker,
     metrics: Metrics,
+    profile: ProfileRecord,
+    topology: Topology,
+    config: RuntimeConfig,
+    // False if the executor was passed to `with_executor`, it is then never replaced.
+    sized_executor: bool,
+    dedicated_pool_threads: Option<usize>,
 }
 
+/// Links `write` to `read` with a new typed channel.
+/// The data must be `'static`: the nodes run on the worker threads of the executor,
+/// so their packets cannot borrow from the caller. Use `typed_channel` directly to
+/// send borrowed data between scoped threads.
 pub fn link<U: Clone + 'static>(
     write: &mut BufferWriter<U>,
     read: &mut BufferReceiver<impl FixedSizeBuffer<Data = U>>,
@@ -56,56 +100,213 @@ pub fn link<U: Clone + 'static>(
     Ok(())
 }
 
+/// Links `read` to a new channel and returns its sender, to inject packets
+/// into the input of a node, e.g. in the integration tests of a live graph.
+pub fn inject<U: Clone + 'static>(
+    read: &mut BufferReceiver<impl FixedSizeBuffer<Data = U>>,
+) -> SenderChannel<U> {
+    let (channel_sender, channel_receiver) = typed_channel::<U>();
+    read.link(channel_receiver);
+    channel_sender
+}
+
+/// Links `write` to a new channel and returns its receiver, to read the output
+/// of a node. The packets are also sent to the channels already linked to `write`.
+pub fn tap<U: Clone + 'static>(
+    write: &mut BufferWriter<U>,
+    channel: ChannelID,
+) -> ReceiverChannel<U> {
+    let (channel_sender, channel_receiver) = typed_channel::<U>();
+    write.link(channel_sender);
+    channel_receiver.with_id(channel)
+}
+
 impl Graph {
     pub fn new(metrics_backend: Metrics) -> Self {
+        let mut graph = Graph::with_executor(metrics_backend, ThreadPool::default());
+        graph.sized_executor = true;
+        graph
+    }
+}
+
+impl<E: Executor> Graph<E> {
+    /// Creates a graph running the processors of its nodes on `executor`.
+    pub fn with_executor(metrics_backend: Metrics, executor: E) -> Self {
         Graph {
             running: Arc::new(Atomic::<GraphStatus>::new(GraphStatus::Running)),
             thread_control: vec![],
-            pool: ThreadPool::default(),
+            pool: executor,
             node_threads: Default::default(),
+            worker_status: Default::default(),
+            processed: Default::default(),
             read_threads: Default::default(),
+            reader_stops: Default::default(),
+            worker_stops: Default::default(),
+            buffer_probes: Default::default(),
+            node_inputs: Default::default(),
+            node_outputs: Default::default(),
+            replay_clocks: Default::default(),
+            watchdog: None,
+            governor: None,
             worker_done: unbounded::<String>(),
             reader_empty: unbounded::<String>(),
+            worker_errors: None,
+            worker_failures: unbounded(),
+            failures: Mutex::default(),
+            sources: SourceTracker::default(),
             metrics: metrics_backend,
+            profile: ProfileRecord::default(),
+            topology: Topology::default(),
+            config: RuntimeConfig::default(),
+            sized_executor: false,
+            dedicated_pool_threads: None,
+        }
+    }
+
+    /// Sets the timing configuration of the nodes started from now on.
+    pub fn with_runtime_config(mut self, config: RuntimeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Caps the threads running the processors, e.g. to the number of cores to
+    /// avoid oversubscribing them. The executor passed to `with_executor` is never
+    /// replaced, the setting is then ignored with a warning. Panics if `threads` is 0.
+    pub fn with_worker_threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "The pool needs at least one thread");
+        if !self.sized_executor {
+            tracing::warn!("The executor was set with with_executor, worker threads are ignored");
+            return self;
         }
+        match self.pool.sized(threads) {
+            Some(pool) => self.pool = pool,
+            None => tracing::warn!("The executor cannot be sized, worker threads are ignored"),
+        }
+        self
+    }
+
+    /// Runs the processor of each node started from now on on a dedicated pool of
+    /// `threads` threads, so that slow nodes do not delay the others. Ignored with a
+    /// warning if the executor was passed to `with_executor`. Panics if `threads` is 0.
+    pub fn with_dedicated_pools(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "The pool needs at least one thread");
+        if !self.sized_executor {
+            tracing::warn!("The executor was set with with_executor, dedicated pools are ignored");
+            return self;
+        }
+        self.dedicated_pool_threads = Some(threads);
+        self
+    }
+
+    /// Sends an error to `sender` every time the processor of a node started from
+    /// now on panics or exceeds its `handle_timeout`.
+    pub fn with_error_channel(mut self, sender: Sender<WorkerError>) -> Self {
+        self.worker_errors = Some(sender);
+        self
     }
 
-    fn track_node_thread(&mut self, id: String, handle: JoinHandle<()>) {
+    /// Checks the links between the started nodes, derived from the channels linked
+    /// with `link`. The nodes closing a cycle are also refused when they are started.
+    ///
+    /// * Returns
+    /// A `GraphError::CycleDetected` with the input channels of the cycle if the output
+    /// of a node flows back into its own input.
+    pub fn validate(&self) -> Result<(), GraphError> {
+        match self.topology_with(None).find_cycle() {
+            Some(cycle) => Err(GraphError::CycleDetected(cycle)),
+            None => Ok(()),
+        }
+    }
+
+    /// Renders the started nodes and the channels linking them in the Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        self.topology_with(None).to_dot()
+    }
+
+    /// The started nodes and the channels linking them, labelled with the input channel
+    /// they reach. `node` adds the links of a node about to start.
+    fn topology_with(&self, node: Option<(&str, NodePorts)>) -> Topology {
+        let mut topology = self.topology.clone();
+        let mut ports: BTreeMap<&str, NodePorts> = BTreeMap::new();
+        for (id, inputs) in self.node_inputs.iter() {
+            ports.entry(id.as_str()).or_default().inputs = inputs.links();
+        }
+        for (id, outputs) in self.node_outputs.iter() {
+            ports.entry(id.as_str()).or_default().outputs = outputs.links();
+        }
+        if let Some((id, node_ports)) = node {
+            ports.insert(id, node_ports);
+        }
+        let writers: HashMap<LinkId, &str> = ports
+            .iter()
+            .flat_map(|(id, node_ports)| node_ports.outputs.iter().map(move |link| (*link, *id)))
+            .collect();
+        for (id, node_ports) in ports.iter() {
+            for (channel, link) in node_ports.inputs.iter() {
+                if let Some(from) = writers.get(link) {
+                    topology.add_edge(from, id, channel.clone());
+                }
+            }
+        }
+        topology
+    }
+
+    fn track_node_thread(&mut self, id: String, stop: Arc<AtomicBool>, handle: JoinHandle<()>) {
         if self.read_threads.insert(id.clone(), handle).is_some() {
             panic!("Node {id} already exists");
         }
+        self.reader_stops.insert(id, stop);
     }
 
     fn get_worker<
-        INPUT: Send + InputGenerator + ChannelBuffer + 'static,
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
         OUTPUT: WriteChannelTrait + Send + 'static,
     >(
         &mut self,
         node: Nodes<INPUT, OUTPUT>,
+        status: &Arc<Atomic<WorkerStatus>>,
     ) -> (String, ProcessorWorker<INPUT, OUTPUT>) {
         let reading_running_thread = self.running.clone();
         match node {
             Nodes::Node(node) => {
-                let (id, work_queue, mut read_channel, handler, write_channel) = (
+                let (id, work_queue, mut read_channel, handler, mut write_channel) = (
                     node.id,
                     node.work_queue,
                     node.read_channel,
                     node.handler,
                     node.write_channel,
                 );
+                if handler.transactional_writes() {
+                    write_channel.writer.set_transactional();
+                }
+                let write_channel = Arc::new(Mutex::new(write_channel));
+                self.node_outputs.insert(id.clone(), write_channel.clone());
+                self.node_inputs
+                    .insert(id.clone(), read_channel.channels.clone());
                 read_channel.start(work_queue.clone());
+                read_channel.report_stalls(status.clone());
                 let done_channel = self.reader_empty.0.clone();
+                let failure_channel = self.worker_failures.0.clone();
                 let id_clone = id.clone();
+                self.buffer_probes.insert(
+                    id.clone(),
+                    Arc::new(ChannelsProbe(read_channel.channels.clone())),
+                );
+
+                let reader_stop = Arc::new(AtomicBool::new(false));
+                let reader_stop_clone = reader_stop.clone();
 
                 self.track_node_thread(
                     id.clone(),
+                    reader_stop,
                     thread::spawn(move || {
                         read_channel_data(
                             id_clone,
                             reading_running_thread,
                             read_channel,
                             done_channel,
-                            
+                            failure_channel,
+                            reader_stop_clone,
                         )
                     }),
                 );
@@ -115,30 +316,72 @@ impl Graph {
                     id,
                     ProcessorWorker::<INPUT, OUTPUT> {
                         work_queue: Some(work_queue_processor),
+                        priority: handler.priority(),
+                        rate_limit: None,
                         processor: Processors::Processor(handler),
                         write_channel: Some(write_channel),
+                        stop: Arc::default(),
+                        processed: Arc::default(),
+                        sources: None,
+                        clock: None,
+                    },
+                )
+            }
+            Nodes::SourceNode(mut node) => {
+                self.sources.add(&node.id);
+                if let Some(speed) = node.handler.replay_speed() {
+                    let clock = Arc::new(ReplayClock::new(speed));
+                    node.write_channel.writer.set_replay_clock(clock.clone());
+                    self.replay_clocks.insert(node.id.clone(), clock);
+                }
+                let write_channel = Arc::new(Mutex::new(node.write_channel));
+                self.node_outputs
+                    .insert(node.id.clone(), write_channel.clone());
+                (
+                    node.id.clone(),
+                    ProcessorWorker {
+                        work_queue: None,
+                        priority: node.handler.priority(),
+                        rate_limit: node.handler.rate_limit(),
+                        clock: node.handler.clock(),
+                        processor: Processors::SourceProcessor(node.handler),
+                        write_channel: Some(write_channel),
+                        stop: Arc::default(),
+                        processed: Arc::default(),
+                        sources: Some(self.sources.clone()),
                     },
                 )
             }
-            Nodes::SourceNode(node) => (
-                node.id.clone(),
-                ProcessorWorker {
-                    work_queue: None,
-                    processor: Processors::SourceProcessor(node.handler),
-                    write_channel: Some(node.write_channel),
-                },
-            ),
             Nodes::TerminalNode(node) => {
                 let (id, work_queue, mut read_channel, handler) =
                     (node.id, node.work_queue, node.read_channel, node.handler);
+                self.node_inputs
+                    .insert(id.clone(), read_channel.channels.clone());
                 read_channel.start(work_queue.clone());
+                read_channel.report_stalls(status.clone());
                 let done_channel = self.reader_empty.0.clone();
+                let failure_channel = self.worker_failures.0.clone();
                 let id_clone = id.clone();
+                self.buffer_probes.insert(
+                    id.clone(),
+                    Arc::new(ChannelsProbe(read_channel.channels.clone())),
+                );
+
+                let reader_stop = Arc::new(AtomicBool::new(false));
+                let reader_stop_clone = reader_stop.clone();
 
                 self.track_node_thread(
                     id.clone(),
+                    reader_stop,
                     thread::spawn(move || {
-                        read_channel_data(id, reading_running_thread, read_channel, done_channel)
+                        read_channel_data(
+                            id,
+                            reading_running_thread,
+                            read_channel,
+                            done_channel,
+                            failure_channel,
+                            reader_stop_clone,
+                        )
                     }),
                 );
 
@@ -147,8 +390,14 @@ impl Graph {
                     id_clone,
                     ProcessorWorker {
                         work_queue: Some(work_queue_processor),
+                        priority: handler.priority(),
+                        rate_limit: None,
                         processor: Processors::TerminalProcessor(handler),
                         write_channel: None,
+                        stop: Arc::default(),
+                        processed: Arc::default(),
+                        sources: None,
+                        clock: None,
                     },
                 )
             }
@@ -159,45 +408,176 @@ impl Graph {
         &mut self,
         node: SourceNode<OUTPUT>,
     ) {
-        self._start_node::<NoBuffer, OUTPUT>(Nodes::SourceNode(Box::new(node)));
+        self._start_node::<NoBuffer, OUTPUT>(Nodes::SourceNode(Box::new(node)), false)
+            .unwrap_or_else(|err| panic!("{err}"));
     }
 
+    /// Starts the source node unless its channels close a cycle.
+    ///
+    /// * Returns
+    /// A `GraphError::CycleDetected` with the input channels of the cycle, or a
+    /// `GraphError::InvalidRateLimit` if the rate limit of the source is not positive.
+    pub fn try_start_source_node<OUTPUT: WriteChannelTrait + Send + 'static>(
+        &mut self,
+        node: SourceNode<OUTPUT>,
+    ) -> Result<(), GraphError> {
+        self._start_node::<NoBuffer, OUTPUT>(Nodes::SourceNode(Box::new(node)), true)
+    }
+
+    /// Starts the node. Input channels not linked to a sender are only reported with
+    /// a warning, see `try_start_node` to refuse them.
     pub fn start_node<
-        INPUT: Send + InputGenerator + ChannelBuffer + 'static,
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
         OUTPUT: WriteChannelTrait + Send + 'static,
     >(
         &mut self,
         node: Node<INPUT, OUTPUT>,
     ) {
-        self._start_node::<INPUT, OUTPUT>(Nodes::Node(Box::new(node)));
+        self._start_node::<INPUT, OUTPUT>(Nodes::Node(Box::new(node)), false)
+            .unwrap_or_else(|err| panic!("{err}"));
     }
 
-    pub fn start_terminal_node<INPUT: Send + InputGenerator + ChannelBuffer + 'static>(
+    /// Starts the node unless one of its input channels is not linked to a sender
+    /// or its channels close a cycle.
+    ///
+    /// * Returns
+    /// A `GraphError::DanglingInput` with the first input channel no sender was linked to,
+    /// or a `GraphError::CycleDetected` with the input channels of the cycle.
+    pub fn try_start_node<
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
+        OUTPUT: WriteChannelTrait + Send + 'static,
+    >(
+        &mut self,
+        node: Node<INPUT, OUTPUT>,
+    ) -> Result<(), GraphError> {
+        self._start_node::<INPUT, OUTPUT>(Nodes::Node(Box::new(node)), true)
+    }
+
+    /// Starts the node. Input channels not linked to a sender are only reported with
+    /// a warning, see `try_start_terminal_node` to refuse them.
+    pub fn start_terminal_node<INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static>(
         &mut self,
         node: TerminalNode<INPUT>,
     ) {
-        self._start_node::<INPUT, WriteChannel1<String>>(Nodes::TerminalNode(Box::new(node)));
+        self._start_node::<INPUT, WriteChannel1<String>>(Nodes::TerminalNode(Box::new(node)), false)
+            .unwrap_or_else(|err| panic!("{err}"));
     }
 
+    /// Starts the node unless one of its input channels is not linked to a sender
+    /// or its channels close a cycle.
+    ///
+    /// * Returns
+    /// A `GraphError::DanglingInput` with the first input channel no sender was linked to,
+    /// or a `GraphError::CycleDetected` with the input channels of the cycle.
+    pub fn try_start_terminal_node<INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static>(
+        &mut self,
+        node: TerminalNode<INPUT>,
+    ) -> Result<(), GraphError> {
+        self._start_node::<INPUT, WriteChannel1<String>>(Nodes::TerminalNode(Box::new(node)), true)
+    }
+
+    // With `checked` the dangling inputs and the cycles are returned as errors,
+    // otherwise the dangling inputs are only logged.
     fn _start_node<
-        INPUT: Send + InputGenerator + ChannelBuffer + 'static,
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
         OUTPUT: WriteChannelTrait + Send + 'static,
     >(
         &mut self,
         processor: Nodes<INPUT, OUTPUT>,
-    ) {
-        self.running.swap(GraphStatus::Running, Ordering::Relaxed);
+        checked: bool,
+    ) -> Result<(), GraphError> {
+        let (id, unlinked_inputs, unlinked_outputs, ports) = match &processor {
+            Nodes::SourceNode(node) => (
+                &node.id,
+                vec![],
+                node.write_channel.writer.unlinked_channels(),
+                NodePorts {
+                    inputs: vec![],
+                    outputs: write_links(&*node.write_channel.writer),
+                },
+            ),
+            Nodes::Node(node) => (
+                &node.id,
+                unlinked_read_channels(&node.read_channel.channels),
+                node.write_channel.writer.unlinked_channels(),
+                NodePorts {
+                    inputs: read_links(&node.read_channel.channels),
+                    outputs: write_links(&*node.write_channel.writer),
+                },
+            ),
+            Nodes::TerminalNode(node) => (
+                &node.id,
+                unlinked_read_channels(&node.read_channel.channels),
+                vec![],
+                NodePorts {
+                    inputs: read_links(&node.read_channel.channels),
+                    outputs: vec![],
+                },
+            ),
+        };
+        // Checked here since the worker thread cannot report it.
+        if let Nodes::SourceNode(node) = &processor {
+            if let Some(rate) = node.handler.rate_limit() {
+                if rate <= 0.0 || !rate.is_finite() {
+                    return Err(GraphError::InvalidRateLimit(id.clone(), rate));
+                }
+            }
+        }
+        let synchronizer = match &processor {
+            Nodes::SourceNode(_) => Ok(()),
+            Nodes::Node(node) => check_synchronizer(&node.read_channel),
+            Nodes::TerminalNode(node) => check_synchronizer(&node.read_channel),
+        };
+        if let Err(reason) = synchronizer {
+            return Err(GraphError::InvalidSynchronizer(id.clone(), reason));
+        }
+        if checked {
+            if let Some(channel) = unlinked_inputs.first() {
+                return Err(GraphError::DanglingInput(channel.clone()));
+            }
+            if let Some(cycle) = self.topology_with(Some((id.as_str(), ports))).find_cycle() {
+                return Err(GraphError::CycleDetected(cycle));
+            }
+        }
+        for channel in unlinked_inputs {
+            tracing::warn!(node_id = %id, channel_id = %channel, "Input channel has no sender");
+        }
+        for channel in unlinked_outputs {
+            tracing::warn!(node_id = %id, channel_id = %channel, "Output channel has no consumer");
+        }
+
+        if self.running.load(Ordering::Relaxed) != GraphStatus::Paused {
+            self.running.swap(GraphStatus::Running, Ordering::Relaxed);
+        }
 
         let consume_running_thread = self.running.clone();
 
-        let (node_id, worker) = self.get_worker(processor);
+        let kind = match &processor {
+            Nodes::SourceNode(_) => NodeKind::Source,
+            Nodes::Node(_) => NodeKind::Processor,
+            Nodes::TerminalNode(_) => NodeKind::Terminal,
+        };
+        let status = Arc::new(Atomic::new(WorkerStatus::Idle));
+        let (node_id, worker) = self.get_worker(processor, &status);
+        self.worker_stops.insert(node_id.clone(), worker.stop.clone());
+        self.processed.insert(node_id.clone(), worker.processed.clone());
+        self.topology.add_node(&node_id, kind);
 
         let done_channel = self.worker_done.0.clone();
+        let error_channel = self.worker_errors.clone();
+        let failure_channel = self.worker_failures.0.clone();
 
         let wait = Arc::new((Mutex::new(WorkerStatus::Idle), Condvar::new()));
         let wait_clone = wait.clone();
-        let thread_clone = self.pool.clone();
+        let status_clone = status.clone();
+        let thread_clone = self
+            .dedicated_pool_threads
+            .and_then(|threads| self.pool.sized(threads))
+            .unwrap_or_else(|| self.pool.clone());
         let id_move = node_id.clone();
+        let config = self.config;
+        let processing_time = self.metrics.processing_time().cloned();
+        let profile = self.profile.clone();
 
         let profiler: Option<_> = self.metrics.profiler().as_ref().map(|profiler| profiler.profiler.tag_wrapper());
 
@@ -209,16 +589,22 @@ impl Graph {
                     let profiler_tag = match profiler {
                         Some(taggers) => ProfilerTag::from_tuple(taggers),
                         None => ProfilerTag::no_profiler(),
-                    };
+                    }
+                    .with_record(profile);
 
                     let mut consumer = ConsumerThread::new(
                         id_move,
                         consume_running_thread,
                         wait_clone,
+                        status_clone,
                         worker,
                         done_channel,
+                        error_channel,
+                        failure_channel,
                         thread_clone,
                         profiler_tag,
+                        config,
+                        processing_time,
                     );
                     consumer.consume();
                 }),
@@ -228,10 +614,247 @@ impl Graph {
         }
 
         self.thread_control.push(wait);
-        tracing::info!("Done Starting Node {node_id}");
+        self.worker_status.insert(node_id.clone(), status);
+        tracing::info!(node_id = %node_id, "Done starting node");
+        Ok(())
+    }
+
+    /// Starts `node` in a running graph. Its input channels are linked to the output
+    /// channels of the started nodes listed in `inputs`, and its output channels to the
+    /// input channels of the started nodes listed in `outputs`. Channels can also be
+    /// linked beforehand with `link`.
+    /// An input channel reads a single channel: linking an output to an input of a
+    /// started node replaces the channel it reads, e.g. the one of a node removed with
+    /// `remove_node`. The packets left in the replaced channel are still read.
+    ///
+    /// * Returns
+    /// A `RustedPipeError::MissingNodeError` if a node of the links was not started, a
+    /// `RustedPipeError::ChannelError` if a channel does not exist or the linked channels
+    /// carry different types, a `GraphError::DanglingInput` if an input of `node` is
+    /// left unlinked, a `GraphError::CycleDetected` if the links would form a cycle, or a
+    /// `GraphError::InvalidSynchronizer` if the synchronizer of `node` does not support its
+    /// channels. The started nodes are left unchanged on error.
+    pub fn add_node<
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
+        OUTPUT: WriteChannelTrait + Send + 'static,
+    >(
+        &mut self,
+        mut node: Node<INPUT, OUTPUT>,
+        inputs: &[NodeLink],
+        outputs: &[NodeLink],
+    ) -> Result<(), RustedPipeError> {
+        let missing = inputs
+            .iter()
+            .find(|link| !self.node_outputs.contains_key(&link.node))
+            .or_else(|| {
+                outputs
+                    .iter()
+                    .find(|link| !self.node_inputs.contains_key(&link.node))
+            });
+        if let Some(link) = missing {
+            return Err(RustedPipeError::MissingNodeError(link.node.clone()));
+        }
+
+        // Checked before linking the started nodes to a node that would not start.
+        check_synchronizer(&node.read_channel)
+            .map_err(|reason| GraphError::InvalidSynchronizer(node.id.clone(), reason))?;
+        let receivers = match self.link_new_node(&mut node, inputs, outputs) {
+            Ok(receivers) => receivers,
+            Err(err) => {
+                // The receivers linked to the node are dropped with it.
+                drop(node);
+                for link in inputs {
+                    self.node_outputs[&link.node].unlink_dropped();
+                }
+                return Err(err);
+            }
+        };
+        for (link, receiver) in outputs.iter().zip(receivers) {
+            self.node_inputs[&link.node].link_input(&link.channel, receiver)?;
+        }
+
+        self.try_start_node(node)?;
+        Ok(())
     }
 
-    pub fn stop(mut self, wait_for_data: bool, timeout: Option<Duration>) {
+    /// Links the inputs of `node` to the started nodes and checks the links of `add_node`,
+    /// without changing the inputs of the started nodes.
+    ///
+    /// * Returns
+    /// The receivers to link to the inputs of the started nodes listed in `outputs`,
+    /// or the error of the first link that cannot be made.
+    fn link_new_node<
+        INPUT: Send + Sync + InputGenerator + ChannelBuffer + 'static,
+        OUTPUT: WriteChannelTrait + Send + 'static,
+    >(
+        &self,
+        node: &mut Node<INPUT, OUTPUT>,
+        inputs: &[NodeLink],
+        outputs: &[NodeLink],
+    ) -> Result<Vec<Box<dyn Any>>, RustedPipeError> {
+        for link in inputs {
+            let receiver = self.node_outputs[&link.node].link_output(&link.channel)?;
+            node.read_channel
+                .channels
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .link_untyped(&link.local, receiver)?;
+        }
+        if let Some(channel) = unlinked_read_channels(&node.read_channel.channels)
+            .into_iter()
+            .next()
+        {
+            return Err(GraphError::DanglingInput(channel).into());
+        }
+        let ports = NodePorts {
+            inputs: read_links(&node.read_channel.channels),
+            outputs: write_links(&*node.write_channel.writer),
+        };
+        let mut topology = self.topology_with(Some((node.id.as_str(), ports)));
+        for link in outputs {
+            topology.relink(&node.id, &link.node, link.channel.clone());
+        }
+        if let Some(cycle) = topology.find_cycle() {
+            return Err(GraphError::CycleDetected(cycle).into());
+        }
+        let mut receivers = vec![];
+        for link in outputs {
+            let receiver = node.write_channel.writer.link_untyped(&link.local)?;
+            self.node_inputs[&link.node].check_link(&link.channel, receiver.as_ref())?;
+            receivers.push(receiver);
+        }
+        Ok(receivers)
+    }
+
+    /// Stops the node `id` while the rest of the graph keeps running. Its reader stops
+    /// first, then its worker processes the work already queued and its threads are joined.
+    /// The writers linked to the node drop its channels. Nodes can be
+    /// added to a running graph with `add_node`.
+    ///
+    /// * Returns
+    /// A `RustedPipeError::MissingNodeError` if the node was not started.
+    pub fn remove_node(&mut self, id: &str) -> Result<(), RustedPipeError> {
+        let node_thread = self
+            .node_threads
+            .remove(id)
+            .ok_or_else(|| RustedPipeError::MissingNodeError(id.to_string()))?;
+
+        if let Some(read_thread) = self.read_threads.remove(id) {
+            if let Some(stop) = self.reader_stops.remove(id) {
+                stop.store(true, Ordering::Relaxed);
+            }
+            tracing::info!(node_id = %id, "Waiting for reader to stop");
+            read_thread
+                .join()
+                .unwrap_or_else(|_| panic!("Cannot join thread {id}"));
+        }
+
+        if let Some(stop) = self.worker_stops.remove(id) {
+            stop.store(true, Ordering::Relaxed);
+        }
+        if let Some(clock) = self.replay_clocks.remove(id) {
+            clock.stop();
+        }
+        tracing::info!(node_id = %id, "Waiting for node to drain its work queue");
+        if let Some(status) = self.worker_status.get(id) {
+            // The set being processed completes before the worker exits.
+            while status.load(Ordering::Relaxed) == WorkerStatus::Running {
+                thread::sleep(self.config.poll_interval);
+            }
+        }
+        node_thread
+            .join()
+            .unwrap_or_else(|_| panic!("Cannot join thread {id}"));
+
+        self.sources.end(id, &self.running);
+        self.worker_status.remove(id);
+        self.processed.remove(id);
+        self.buffer_probes.remove(id);
+        self.node_inputs.remove(id);
+        self.node_outputs.remove(id);
+        self.topology.remove_node(id);
+        // The receivers of the node are dropped with it.
+        for outputs in self.node_outputs.values() {
+            outputs.unlink_dropped();
+        }
+        Ok(())
+    }
+
+    /// Returns a snapshot of the status of every started node, keyed by node id.
+    pub fn worker_status(&self) -> HashMap<String, WorkerStatus> {
+        self.worker_status
+            .iter()
+            .map(|(id, status)| (id.clone(), status.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Starts a thread reporting when no node has run for `config.stall_timeout` while
+    /// the buffers still hold the same data. Only the nodes started so far are watched.
+    pub fn start_watchdog(&mut self, config: WatchdogConfig) {
+        if self.watchdog.is_some() {
+            panic!("Watchdog already started!");
+        }
+        let running = self.running.clone();
+        let workers = self.worker_status.values().cloned().collect();
+        let probes = self.buffer_probes.clone();
+        let poll_interval = self.config.poll_interval;
+        self.watchdog = Some(thread::spawn(move || {
+            watchdog::watch(running, workers, probes, config, poll_interval)
+        }));
+    }
+
+    /// Starts a thread pausing the graph while the packet sets of the nodes started
+    /// so far wait too long in their work queues, see `GovernorConfig`.
+    pub fn start_governor(&mut self, config: GovernorConfig) {
+        if self.governor.is_some() {
+            panic!("Governor already started!");
+        }
+        let running = self.running.clone();
+        let sources = self.sources.clone();
+        let nodes = self.worker_status.keys().cloned().collect();
+        self.governor = Some(thread::spawn(move || {
+            governor::govern(running, sources, nodes, config)
+        }));
+    }
+
+    /// Time spent by the nodes in each profiled phase so far.
+    pub fn profile(&self) -> Vec<ProfileEntry> {
+        self.profile.snapshot()
+    }
+
+    /// Stops processing data until `resume` is called. Sources stop producing and
+    /// workers stop consuming, while data already received stays in the buffers.
+    /// Only a running graph is paused, a graph waiting for its data to be consumed
+    /// or terminating is left as it is.
+    pub fn pause(&self) {
+        let _ = self.running.compare_exchange(
+            GraphStatus::Running,
+            GraphStatus::Paused,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Resumes processing after a `pause`. If all the sources ended in the
+    /// meantime, the graph waits for the data to be consumed.
+    pub fn resume(&self) {
+        self.sources.resume(&self.running);
+    }
+
+    /// The current status of the graph.
+    pub fn status(&self) -> GraphStatus {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Terminates the graph and joins the threads of its nodes.
+    ///
+    /// * Arguments
+    /// `wait_for_data` - If true, waits first for the nodes to consume the data in their queues.
+    /// `timeout` - How long to wait for each node to be done, forever if None. Panics once elapsed.
+    ///
+    /// * Returns
+    /// The final status, number of processed packets and errors of every node.
+    pub fn stop(self, wait_for_data: bool, timeout: Option<Duration>) -> TerminationReport {
         let mut empty_set = HashSet::new();
         let mut empty_receiver_set = HashSet::new();
 
@@ -245,7 +868,7 @@ impl Graph {
                 .iter()
                 .all(|n| empty_set.contains(n.0.as_str()))
             {
-                debug!(
+                tracing::debug!(
                     "Waiting node threads received done from {} out of {}",
                     empty_set.len(),
                     self.node_threads.len()
@@ -263,7 +886,7 @@ impl Graph {
                 .iter()
                 .all(|n| empty_set.contains(n.0.as_str()))
             {
-                debug!(
+                tracing::debug!(
                     "Waiting reader threads received done from {} out of {}",
                     empty_receiver_set.len(),
                     self.read_threads.len()
@@ -277,23 +900,249 @@ impl Graph {
                 }
             }
         }
+        self.terminate()
+    }
+
+    /// Every error that terminated a processor, or a panic or timeout of a processor,
+    /// with the id of its node, in the order they occurred. `EndOfStream` is not an error.
+    /// Panics and timeouts are reported as `RustedPipeError::ProcessorError`.
+    pub fn errors(&self) -> Vec<(String, RustedPipeError)> {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        failures.extend(self.worker_failures.1.try_iter());
+        failures.clone()
+    }
+
+    /// Blocks until all the source nodes reached the end of their stream, or failed,
+    /// and the other nodes consumed the data in their queues, then terminates the graph.
+    /// Meant for batch jobs whose sources produce a finite amount of data.
+    /// The graph is not terminated, so that its state can still be inspected: call
+    /// `stop` afterwards to join its threads.
+    ///
+    /// * Returns
+    /// A `RustedPipeError::WorkerErrors` with every error of the processors since
+    /// the graph was created, as returned by `errors`, if any.
+    pub fn run_until_complete(&self) -> Result<(), RustedPipeError> {
+        while !self.sources.is_empty() {
+            thread::sleep(self.config.poll_interval);
+        }
+        tracing::info!("All sources terminated, waiting for data to be consumed");
+        self.running
+            .store(GraphStatus::WaitingForDataToTerminate, Ordering::Relaxed);
+        wait_done_until(&self.worker_done.1, &self.node_threads, None);
+        wait_done_until(&self.reader_empty.1, &self.read_threads, None);
+
+        let failures = self.errors();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(RustedPipeError::WorkerErrors(failures))
+        }
+    }
+
+    /// Waits up to `timeout` for the nodes to consume the data in their queues, then
+    /// terminates the graph even if some queues are still not empty. See `stop` for
+    /// the returned report.
+    pub fn shutdown(self, timeout: Duration) -> TerminationReport {
+        let deadline = Instant::now() + timeout;
+        self.running
+            .store(GraphStatus::WaitingForDataToTerminate, Ordering::Relaxed);
+        tracing::info!("Waiting up to {timeout:?} for data to be consumed");
+
+        let drained = wait_done_until(&self.worker_done.1, &self.node_threads, Some(deadline))
+            && wait_done_until(&self.reader_empty.1, &self.read_threads, Some(deadline));
+        if !drained {
+            tracing::warn!("Shutdown timeout reached, terminating graph before all data was consumed");
+        }
+        self.terminate()
+    }
+
+    fn terminate(mut self) -> TerminationReport {
         self.running
             .swap(GraphStatus::Terminating, Ordering::Relaxed);
+        for clock in self.replay_clocks.values() {
+            clock.stop();
+        }
 
-        
         let keys = self.node_threads.keys().cloned().collect_vec();
         for id in keys {
-            tracing::info!("Waiting for node {id} to stop");
+            tracing::info!(node_id = %id, "Waiting for node to stop");
             self.node_threads.remove(&id).expect("Thread ID not found").join().unwrap_or_else(|_| panic!("Cannot join thread {id}"));
         }
 
         let keys = self.read_threads.keys().cloned().collect_vec();
         for id in keys {
-            tracing::info!("Waiting for reader {id} to stop");
+            tracing::info!(node_id = %id, "Waiting for reader to stop");
             self.read_threads.remove(&id).expect("Thread ID not found").join().unwrap_or_else(|_| panic!("Cannot join thread {id}"));
         }
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.join().unwrap_or_else(|_| panic!("Cannot join watchdog thread"));
+        }
+        if let Some(governor) = self.governor.take() {
+            governor.join().unwrap_or_else(|_| panic!("Cannot join governor thread"));
+        }
         tracing::info!("Waiting for metrics to stop");
         self.metrics.stop();
+        self.report()
+    }
+
+    fn report(&self) -> TerminationReport {
+        let mut nodes: BTreeMap<_, _> = self
+            .worker_status
+            .iter()
+            .map(|(id, status)| {
+                let report = NodeReport {
+                    status: status.load(Ordering::Relaxed),
+                    processed: self
+                        .processed
+                        .get(id)
+                        .map_or(0, |processed| processed.load(Ordering::Relaxed)),
+                    errors: vec![],
+                };
+                (id.clone(), report)
+            })
+            .collect();
+        for (id, error) in self.errors() {
+            if let Some(report) = nodes.get_mut(&id) {
+                report.errors.push(error);
+            }
+        }
+        TerminationReport { nodes }
+    }
+}
+
+fn check_synchronizer<T: InputGenerator + ChannelBuffer + Send>(
+    read_channel: &ReadChannel<T>,
+) -> Result<(), String> {
+    let channels = read_channel
+        .channels
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    read_channel
+        .synch_strategy
+        .check_channels(&channels.available_channels())
+}
+
+fn unlinked_read_channels<T: ChannelBuffer>(channels: &RwLock<T>) -> Vec<ChannelID> {
+    channels
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .unlinked_channels()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+fn read_links<T: ChannelBuffer>(channels: &RwLock<T>) -> Vec<(ChannelID, LinkId)> {
+    channels
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .links()
+}
+
+fn write_links<T: WriteChannelTrait + ?Sized>(writer: &T) -> Vec<LinkId> {
+    writer.links().into_iter().map(|(_, link)| link).collect()
+}
+
+/// The channels linked to the inputs and outputs of a node, see `Graph::topology_with`.
+#[derive(Default)]
+struct NodePorts {
+    inputs: Vec<(ChannelID, LinkId)>,
+    outputs: Vec<LinkId>,
+}
+
+/// Collects done notifications until every thread has sent one or `deadline`, if any,
+/// is reached. Returns false on timeout.
+fn wait_done_until(
+    done: &Receiver<String>,
+    threads: &HashMap<String, JoinHandle<()>>,
+    deadline: Option<Instant>,
+) -> bool {
+    let mut done_set = HashSet::new();
+    while !threads.keys().all(|id| done_set.contains(id)) {
+        let received = match deadline {
+            Some(deadline) => done.recv_deadline(deadline).ok(),
+            None => done.recv().ok(),
+        };
+        match received {
+            Some(id) => {
+                done_set.insert(id);
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+/// A channel between a node added with `Graph::add_node` and a started node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeLink {
+    /// Id of the started node.
+    pub node: String,
+    /// The channel of the started node.
+    pub channel: ChannelID,
+    /// The channel of the added node.
+    pub local: ChannelID,
+}
+
+impl NodeLink {
+    pub fn new(node: &str, channel: &str, local: &str) -> Self {
+        Self {
+            node: node.to_string(),
+            channel: ChannelID::from(channel),
+            local: ChannelID::from(local),
+        }
+    }
+}
+
+/// The input channels of a started node, see `Graph::add_node`.
+trait InputLinks: Send + Sync {
+    fn link_input(&self, channel: &ChannelID, receiver: Box<dyn Any>) -> Result<(), ChannelError>;
+    fn check_link(&self, channel: &ChannelID, receiver: &dyn Any) -> Result<(), ChannelError>;
+    fn links(&self) -> Vec<(ChannelID, LinkId)>;
+}
+
+impl<T: ChannelBuffer + Send + Sync> InputLinks for RwLock<T> {
+    fn link_input(&self, channel: &ChannelID, receiver: Box<dyn Any>) -> Result<(), ChannelError> {
+        self.write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .link_untyped(channel, receiver)
+    }
+
+    fn check_link(&self, channel: &ChannelID, receiver: &dyn Any) -> Result<(), ChannelError> {
+        self.read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check_link(channel, receiver)
+    }
+
+    fn links(&self) -> Vec<(ChannelID, LinkId)> {
+        read_links(self)
+    }
+}
+
+/// The output channels of a started node, see `Graph::add_node`.
+trait OutputLinks: Send + Sync {
+    fn link_output(&self, channel: &ChannelID) -> Result<Box<dyn Any>, ChannelError>;
+    fn unlink_dropped(&self);
+    fn links(&self) -> Vec<LinkId>;
+}
+
+impl<T: WriteChannelTrait + Send> OutputLinks for Mutex<TypedWriteChannel<T>> {
+    fn link_output(&self, channel: &ChannelID) -> Result<Box<dyn Any>, ChannelError> {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .writer
+            .link_untyped(channel)
+    }
+
+    fn unlink_dropped(&self) {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .writer
+            .unlink_dropped();
+    }
+
+    fn links(&self) -> Vec<LinkId> {
+        write_links(&*self.lock().unwrap_or_else(PoisonError::into_inner).writer)
     }
 }
 
@@ -303,7 +1152,29 @@ pub(super) struct ProcessorWorker<
 > {
     pub work_queue: Option<WorkQueue<INPUT::INPUT>>,
     pub processor: Processors<INPUT, OUTPUT>,
-    pub write_channel: Option<TypedWriteChannel<OUTPUT>>,
+    pub priority: u8,
+    /// Maximum number of calls per second of a source processor.
+    pub rate_limit: Option<f64>,
+    pub write_channel: Option<Arc<Mutex<TypedWriteChannel<OUTPUT>>>>,
+    pub stop: Arc<AtomicBool>,
+    /// The sources of the graph, if the worker runs a source.
+    pub sources: Option<SourceTracker>,
+    /// Clock stepping a source processor.
+    pub clock: Option<Clock>,
+    /// Number of successful calls of the processor.
+    pub processed: Arc<AtomicUsize>,
+}
+
+#[derive(Debug, Error, PartialEq, Clone)]
+pub enum GraphError {
+    #[error("The graph contains a cycle through channels {0:?}")]
+    CycleDetected(Vec<ChannelID>),
+    #[error("No sender is linked to input channel {0:?}")]
+    DanglingInput(ChannelID),
+    #[error("Rate limit of node {0} must be positive, got {1}")]
+    InvalidRateLimit(String, f64),
+    #[error("The synchronizer of node {0} does not support its channels: {1}")]
+    InvalidSynchronizer(String, String),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -311,11 +1182,34 @@ pub enum GraphStatus {
     Running = 0,
     Terminating = 1,
     WaitingForDataToTerminate = 2,
+    Paused = 3,
+}
+
+/// What a node did until the graph was stopped.
+#[derive(Clone, Debug)]
+pub struct NodeReport {
+    /// Status of the worker once its thread was joined.
+    pub status: WorkerStatus,
+    /// Number of successful calls of the processor.
+    pub processed: usize,
+    /// Errors of the processor and of the reader of the node, see `Graph::errors`.
+    pub errors: Vec<RustedPipeError>,
+}
+
+/// Summary returned when the graph is stopped.
+#[derive(Clone, Debug, Default)]
+pub struct TerminationReport {
+    /// Report of every node running when the graph was stopped, by node id.
+    pub nodes: BTreeMap<String, NodeReport>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WorkerStatus {
+    /// Waiting for work, no set of packets is pending.
     Idle = 0,
     Running = 1,
     Terminating = 2,
+    /// Waiting for work while some but not all the input channels hold data,
+    /// so no set can be assembled yet.
+    Stalled = 3,
 }
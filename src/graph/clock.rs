@@ -0,0 +1,64 @@
+//! A logical clock stepping the source nodes, to run a graph one step at a time,
+//! e.g. in deterministic simulations.
+use std::{
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    time::Duration,
+};
+
+/// Releases the sources returning it from `SourceProcessor::clock`: each `tick`
+/// lets every one of them call `handle` exactly once.
+#[derive(Clone, Default)]
+pub struct Clock {
+    ticks: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the clock by one step, releasing the sources waiting on it.
+    pub fn tick(&self) {
+        let (ticks, ticked) = &*self.ticks;
+        *ticks.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        ticked.notify_all();
+    }
+
+    /// Number of ticks since the clock was created.
+    pub fn ticks(&self) -> u64 {
+        *self.ticks.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits up to `timeout` for the clock to go past `seen` ticks.
+    ///
+    /// * Returns
+    /// True if the clock ticked more than `seen` times.
+    pub fn wait_past(&self, seen: u64, timeout: Duration) -> bool {
+        let (ticks, ticked) = &*self.ticks;
+        let ticks = ticks.lock().unwrap_or_else(PoisonError::into_inner);
+        let (ticks, _) = ticked
+            .wait_timeout_while(ticks, timeout, |ticks| *ticks <= seen)
+            .unwrap_or_else(PoisonError::into_inner);
+        *ticks > seen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_tick_releases_waiting_thread() {
+        let clock = Clock::new();
+        assert!(!clock.wait_past(0, Duration::from_millis(10)));
+
+        let waiting = clock.clone();
+        let waiter = thread::spawn(move || waiting.wait_past(0, Duration::from_secs(5)));
+        clock.tick();
+        assert!(waiter.join().unwrap());
+        assert_eq!(clock.ticks(), 1);
+        assert!(!clock.wait_past(1, Duration::from_millis(10)));
+    }
+}
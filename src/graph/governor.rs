@@ -0,0 +1,207 @@
+//! Governor shedding load: it pauses the graph while the packet sets wait too long
+//! in the work queues of its nodes, as recorded by the `queue_wait_seconds`
+//! histograms, and resumes it after a cooldown.
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use atomic::{Atomic, Ordering};
+use prometheus::proto::MetricFamily;
+
+use super::build::GraphStatus;
+use super::runtime::SourceTracker;
+
+/// Histograms of the time the packet sets wait in the work queues, by node id.
+const QUEUE_WAIT_METRIC: &str = "queue_wait_seconds";
+
+/// Configuration of the governor started with `Graph::start_governor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GovernorConfig {
+    /// Quantile of the queue waits compared to `pause_above`, e.g. 0.95 for the p95.
+    pub quantile: f64,
+    /// The graph is paused once the quantile of the waits measured in a window exceeds it.
+    pub pause_above: Duration,
+    /// How long the graph stays paused. Once resumed, it is paused again if the
+    /// waits are still too long.
+    pub cooldown: Duration,
+    /// How often the waits of the packet sets processed since the previous
+    /// evaluation are measured.
+    pub window: Duration,
+}
+
+impl Default for GovernorConfig {
+    fn default() -> Self {
+        Self {
+            quantile: 0.95,
+            pause_above: Duration::from_secs(1),
+            cooldown: Duration::from_secs(5),
+            window: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Cumulative counts of the queue wait histograms of the governed nodes.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct WaitSnapshot {
+    /// Upper bound and cumulative count of each bucket, summed across nodes.
+    buckets: Vec<(f64, u64)>,
+    count: u64,
+}
+
+fn snapshot(families: &[MetricFamily], nodes: &HashSet<String>) -> WaitSnapshot {
+    let mut snapshot = WaitSnapshot::default();
+    let metrics = families
+        .iter()
+        .filter(|family| family.get_name() == QUEUE_WAIT_METRIC)
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| {
+            metric
+                .get_label()
+                .iter()
+                .any(|label| label.get_name() == "node_id" && nodes.contains(label.get_value()))
+        });
+    for metric in metrics {
+        let histogram = metric.get_histogram();
+        snapshot.count += histogram.get_sample_count();
+        for (index, bucket) in histogram.get_bucket().iter().enumerate() {
+            match snapshot.buckets.get_mut(index) {
+                Some((_, count)) => *count += bucket.get_cumulative_count(),
+                None => snapshot
+                    .buckets
+                    .push((bucket.get_upper_bound(), bucket.get_cumulative_count())),
+            }
+        }
+    }
+    snapshot
+}
+
+/// Estimates the `quantile` of the waits observed between the two snapshots with the
+/// upper bound of the bucket holding it.
+///
+/// * Returns
+/// None if nothing was observed, `Duration::MAX` if the quantile is beyond the last bucket.
+fn quantile_between(
+    previous: &WaitSnapshot,
+    current: &WaitSnapshot,
+    quantile: f64,
+) -> Option<Duration> {
+    let observed = current.count.saturating_sub(previous.count);
+    if observed == 0 {
+        return None;
+    }
+    let rank = ((quantile * observed as f64).ceil() as u64).max(1);
+    let wait = current
+        .buckets
+        .iter()
+        .enumerate()
+        .find(|(index, (_, count))| {
+            let before = previous.buckets.get(*index).map_or(0, |(_, count)| *count);
+            count.saturating_sub(before) >= rank
+        })
+        .map_or(Duration::MAX, |(_, (upper_bound, _))| {
+            Duration::from_secs_f64(*upper_bound)
+        });
+    Some(wait)
+}
+
+pub(super) fn govern(
+    running: Arc<Atomic<GraphStatus>>,
+    sources: SourceTracker,
+    nodes: HashSet<String>,
+    config: GovernorConfig,
+) {
+    let mut previous = snapshot(&prometheus::gather(), &nodes);
+    let mut paused_at: Option<Instant> = None;
+    while running.load(Ordering::Relaxed) != GraphStatus::Terminating {
+        thread::sleep(config.window);
+
+        let current = snapshot(&prometheus::gather(), &nodes);
+        let wait = quantile_between(&previous, &current, config.quantile);
+        previous = current;
+
+        match paused_at {
+            // The graph was resumed or stopped by someone else.
+            Some(_) if running.load(Ordering::Relaxed) != GraphStatus::Paused => paused_at = None,
+            Some(since) => {
+                if since.elapsed() >= config.cooldown {
+                    tracing::info!("Resuming the graph after the governor cooldown");
+                    sources.resume(&running);
+                    paused_at = None;
+                }
+            }
+            None => {
+                if !wait.is_some_and(|wait| wait > config.pause_above) {
+                    continue;
+                }
+                let paused = running.compare_exchange(
+                    GraphStatus::Running,
+                    GraphStatus::Paused,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+                if paused.is_ok() {
+                    tracing::warn!(
+                        quantile = config.quantile,
+                        wait = ?wait,
+                        "Pausing the graph, packet sets wait too long in the work queues"
+                    );
+                    paused_at = Some(Instant::now());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{histogram_opts, HistogramVec, Registry};
+
+    fn create_histograms() -> (Registry, HistogramVec) {
+        let registry = Registry::new();
+        let histograms = HistogramVec::new(
+            histogram_opts!(QUEUE_WAIT_METRIC, "Queue wait", vec![0.01, 0.1, 1.0]),
+            &["node_id"],
+        )
+        .unwrap();
+        registry.register(Box::new(histograms.clone())).unwrap();
+        (registry, histograms)
+    }
+
+    #[test]
+    fn test_quantile_is_measured_between_snapshots_of_governed_nodes() {
+        let (registry, histograms) = create_histograms();
+        let nodes = HashSet::from(["n1".to_string(), "n2".to_string()]);
+        for _ in 0..10 {
+            histograms.with_label_values(&["n1"]).observe(0.5);
+        }
+        let previous = snapshot(&registry.gather(), &nodes);
+
+        for _ in 0..9 {
+            histograms.with_label_values(&["n1"]).observe(0.005);
+        }
+        histograms.with_label_values(&["n2"]).observe(0.05);
+        histograms.with_label_values(&["other"]).observe(5.0);
+        let current = snapshot(&registry.gather(), &nodes);
+
+        assert_eq!(
+            quantile_between(&previous, &current, 0.5),
+            Some(Duration::from_secs_f64(0.01))
+        );
+        assert_eq!(
+            quantile_between(&previous, &current, 0.95),
+            Some(Duration::from_secs_f64(0.1))
+        );
+        assert_eq!(quantile_between(&current, &current, 0.95), None);
+
+        histograms.with_label_values(&["n2"]).observe(5.0);
+        let beyond = snapshot(&registry.gather(), &nodes);
+        assert_eq!(
+            quantile_between(&current, &beyond, 0.95),
+            Some(Duration::MAX)
+        );
+    }
+}
@@ -1,10 +1,22 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
 use prometheus::core::GenericGauge;
 use prometheus_exporter::Exporter;
 use pyroscope::pyroscope::PyroscopeAgentRunning;
 use pyroscope::PyroscopeAgent;
 use pyroscope_pprofrs::{pprof_backend, PprofConfig};
 use lazy_static::lazy_static;
-use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use serde::Serialize;
+
+use crate::buffers::synchronizers::SyncDropReason;
+use prometheus::{
+    histogram_opts, register_int_counter_vec, register_int_gauge_vec, Encoder, HistogramVec,
+    IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
 
 lazy_static! {
     static ref SIZE_METRIC: IntGaugeVec = register_int_gauge_vec!(
@@ -12,6 +24,58 @@ lazy_static! {
         &["node_id", "channel_id"]
     )
     .expect("Cannot create queue_size metrics");
+    static ref EVICTION_METRIC: IntCounterVec = register_int_counter_vec!(
+        "buffer_evictions_total",
+        "Number of packets dropped by a buffer before being consumed",
+        &["node_id", "channel_id"]
+    )
+    .expect("Cannot create buffer_evictions_total metrics");
+    static ref SENT_METRIC: IntCounterVec = register_int_counter_vec!(
+        "packets_sent_total",
+        "Number of packets sent on a channel",
+        &["channel_id"]
+    )
+    .expect("Cannot create packets_sent_total metrics");
+    static ref RECEIVED_METRIC: IntCounterVec = register_int_counter_vec!(
+        "packets_received_total",
+        "Number of packets received from a channel",
+        &["channel_id"]
+    )
+    .expect("Cannot create packets_received_total metrics");
+    static ref SYNC_ASSEMBLED_METRIC: IntCounterVec = register_int_counter_vec!(
+        "sync_sets_assembled_total",
+        "Number of packet sets assembled by the ReadChannel of a node",
+        &["node_id"]
+    )
+    .expect("Cannot create sync_sets_assembled_total metrics");
+    static ref SYNC_DROPPED_METRIC: IntCounterVec = register_int_counter_vec!(
+        "sync_sets_dropped_total",
+        "Number of packet sets given up before reaching the processor of a node",
+        &["node_id", "reason"]
+    )
+    .expect("Cannot create sync_sets_dropped_total metrics");
+}
+
+/// Creates the `processing_time` histogram with `buckets` and registers it. A histogram
+/// with the same name can only be registered once per process: if it already was, e.g.
+/// by another graph, it is only reported with a warning and its timings are not exported.
+///
+/// * Returns
+/// An error if the buckets are empty or not increasing.
+pub(crate) fn processing_time_histogram(buckets: Vec<f64>) -> Result<HistogramVec, prometheus::Error> {
+    if buckets.is_empty() || buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(prometheus::Error::Msg(format!(
+            "Processing time buckets must be increasing, got {buckets:?}"
+        )));
+    }
+    let histogram = HistogramVec::new(
+        histogram_opts!("processing_time", "Timing for a single processor run.", buckets),
+        &["node_id"],
+    )?;
+    if let Err(err) = prometheus::register(Box::new(histogram.clone())) {
+        tracing::warn!("Processing times are not exported: {err}");
+    }
+    Ok(histogram)
 }
 
 pub const MACOS_DOCKER_ADDRESS: &str = "host.docker.internal";
@@ -24,6 +88,64 @@ pub struct Profiler {
 pub struct ProfilerTag {
     pub add_fn: Box<dyn Fn(String, String) -> pyroscope::Result<()>>,
     pub remove_fn: Box<dyn Fn(String, String) -> pyroscope::Result<()>>,
+    pub record: ProfileRecord,
+}
+
+/// Time a node spent with a profiler tag set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileEntry {
+    pub tag: String,
+    pub node_id: String,
+    /// Total time the tag was set, including the time since it was last added if it is still set.
+    pub duration: Duration,
+}
+
+#[derive(Default)]
+struct TagTiming {
+    added_at: Option<Instant>,
+    total: Duration,
+}
+
+/// Records how long profiler tags are set. Clones share the same timings.
+#[derive(Default, Clone)]
+pub struct ProfileRecord {
+    timings: Arc<Mutex<HashMap<(String, String), TagTiming>>>,
+}
+
+impl ProfileRecord {
+    fn add(&self, tag: &str, node_id: &str) {
+        let mut timings = self.timings.lock().unwrap_or_else(PoisonError::into_inner);
+        timings
+            .entry((tag.to_string(), node_id.to_string()))
+            .or_default()
+            .added_at
+            .get_or_insert_with(Instant::now);
+    }
+
+    fn remove(&self, tag: &str, node_id: &str) {
+        let mut timings = self.timings.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(timing) = timings.get_mut(&(tag.to_string(), node_id.to_string())) {
+            if let Some(added_at) = timing.added_at.take() {
+                timing.total += added_at.elapsed();
+            }
+        }
+    }
+
+    /// Time spent with each tag by each node, sorted by tag and node id.
+    pub fn snapshot(&self) -> Vec<ProfileEntry> {
+        let timings = self.timings.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut entries: Vec<_> = timings
+            .iter()
+            .map(|((tag, node_id), timing)| ProfileEntry {
+                tag: tag.clone(),
+                node_id: node_id.clone(),
+                duration: timing.total
+                    + timing.added_at.map_or(Duration::ZERO, |added_at| added_at.elapsed()),
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.tag, &a.node_id).cmp(&(&b.tag, &b.node_id)));
+        entries
+    }
 }
 
 unsafe impl Send for ProfilerTag {}
@@ -39,21 +161,36 @@ impl ProfilerTag {
         ProfilerTag {
             add_fn: Box::new(add),
             remove_fn: Box::new(remove),
+            record: ProfileRecord::default(),
         }
     }
 
+    /// Records the timings of the tags into `record`.
+    pub fn with_record(mut self, record: ProfileRecord) -> Self {
+        self.record = record;
+        self
+    }
+
     pub fn add(&self, key: String, value: String) {
+        self.record.add(&key, &value);
         let _ = (self.add_fn)(key, value);
     }
 
     pub fn remove(&self, key: String, value: String) {
+        self.record.remove(&key, &value);
         let _ = (self.remove_fn)(key, value);
     }
 
+    /// Time spent with each tag so far, see `ProfileRecord::snapshot`.
+    pub fn snapshot(&self) -> Vec<ProfileEntry> {
+        self.record.snapshot()
+    }
+
     pub fn no_profiler() -> Self {
         ProfilerTag {
             add_fn: Box::new(|_: String, _: String| Ok(())),
             remove_fn: Box::new(|_: String, _: String| Ok(())),
+            record: ProfileRecord::default(),
         }
     }
 }
@@ -71,6 +208,7 @@ pub fn default_pyroscope_address() -> String {
 pub struct Metrics {
     metrics_server: Option<MetricsServer>,
     pyroscope_agent: Option<Profiler>,
+    processing_time: Option<HistogramVec>,
 }
 
 impl Metrics {
@@ -82,6 +220,12 @@ impl Metrics {
         &self.metrics_server
     }
 
+    /// The `processing_time` histogram set with `with_processing_time_buckets`, None
+    /// for the one with the default buckets.
+    pub(crate) fn processing_time(&self) -> Option<&HistogramVec> {
+        self.processing_time.as_ref()
+    }
+
     pub fn stop(self) {
         if let Some(server) = self.metrics_server {
             server.stop()
@@ -92,6 +236,7 @@ impl Metrics {
         Metrics {
             metrics_server: None,
             pyroscope_agent: None,
+            processing_time: None,
         }
     }
 
@@ -99,6 +244,7 @@ impl Metrics {
         Metrics {
             metrics_server: None,
             pyroscope_agent: None,
+            processing_time: None,
         }
     }
 
@@ -106,13 +252,28 @@ impl Metrics {
         Metrics {
             metrics_server: self.metrics_server,
             pyroscope_agent: Some(create_profiler_agent(pyroscope_server_addr)),
+            processing_time: self.processing_time,
         }
     }
 
+    /// Sets the upper bounds, in seconds, of the buckets of the `processing_time`
+    /// histogram of the graph, e.g. `prometheus::exponential_buckets(0.0001, 2.0, 12)`
+    /// for fast processors. See `processing_time_histogram` for its export.
+    ///
+    /// * Returns
+    /// An error if the buckets are empty or not increasing.
+    pub fn with_processing_time_buckets(self, buckets: Vec<f64>) -> Result<Self, prometheus::Error> {
+        Ok(Metrics {
+            processing_time: Some(processing_time_histogram(buckets)?),
+            ..self
+        })
+    }
+
     pub fn with_prometheus(self, prometheus_addr: &str) -> Self {
         Metrics {
             metrics_server: Some(spawn_metrics_server(prometheus_addr)),
             pyroscope_agent: self.pyroscope_agent,
+            processing_time: self.processing_time,
         }
     }
 }
@@ -162,9 +323,48 @@ pub fn spawn_metrics_server(prometheus_addr: &str) -> MetricsServer {
     }
 }
 
+/// Pushes the metrics of the default registry to a Prometheus Pushgateway, replacing
+/// the ones previously pushed for `job`. Useful for runs ending before being scraped.
+///
+/// Args
+/// - gateway_url: Address of the Pushgateway, as `http://host:port`.
+/// - job: Name of the job grouping the metrics. It must be a valid URL path segment.
+pub fn push_metrics(gateway_url: &str, job: &str) -> io::Result<()> {
+    let address = gateway_url
+        .strip_prefix("http://")
+        .unwrap_or(gateway_url)
+        .trim_end_matches('/');
+
+    let encoder = TextEncoder::new();
+    let mut body = vec![];
+    encoder
+        .encode(&prometheus::gather(), &mut body)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut stream = TcpStream::connect(address)?;
+    write!(
+        stream,
+        "PUT /metrics/job/{job} HTTP/1.1\r\nHost: {address}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        encoder.format_type(),
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "Pushgateway rejected the metrics: {}",
+            status_line.trim()
+        ))),
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct BufferMonitor {
-    metrics: Option<GenericGauge<prometheus::core::AtomicI64>>
+    metrics: Option<GenericGauge<prometheus::core::AtomicI64>>,
+    evictions: Option<IntCounter>,
 }
 
 
@@ -200,8 +400,10 @@ impl BufferMonitorBuilder {
 impl BufferMonitor {
     pub fn new(node_id: &str, channel_id: &str) -> Self {
         let metrics = SIZE_METRIC.with_label_values(&[node_id, channel_id]);
+        let evictions = EVICTION_METRIC.with_label_values(&[node_id, channel_id]);
         Self {
-            metrics: Some(metrics)
+            metrics: Some(metrics),
+            evictions: Some(evictions),
         }
     }
 
@@ -222,4 +424,209 @@ impl BufferMonitor {
             metrics.dec();
         }
     }
+
+    /// Counts a packet dropped by the buffer before being consumed, because the
+    /// buffer was full or the packet was skipped during synchronization.
+    pub fn evicted(&self) {
+        if let Some(evictions) = self.evictions.as_ref() {
+            evictions.inc();
+        }
+    }
+}
+
+/// Counts the packets going through one end of a channel.
+#[derive(Default, Clone, Debug)]
+pub struct ThroughputMonitor {
+    metrics: Option<IntCounter>,
+}
+
+impl ThroughputMonitor {
+    /// Creates a monitor for the packets sent on `channel_id`.
+    pub fn sent(channel_id: &str) -> Self {
+        Self {
+            metrics: Some(SENT_METRIC.with_label_values(&[channel_id])),
+        }
+    }
+
+    /// Creates a monitor for the packets received from `channel_id`.
+    pub fn received(channel_id: &str) -> Self {
+        Self {
+            metrics: Some(RECEIVED_METRIC.with_label_values(&[channel_id])),
+        }
+    }
+
+    pub fn inc(&self) {
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.inc();
+        }
+    }
+
+    /// Number of packets counted so far, 0 if not monitoring.
+    pub fn get(&self) -> u64 {
+        self.metrics.as_ref().map_or(0, |metrics| metrics.get())
+    }
+}
+
+/// Counts the sets of packets assembled and dropped by the ReadChannel of a node,
+/// to measure the hit rate of its synchronization.
+#[derive(Default, Clone, Debug)]
+pub struct SyncMonitor {
+    node_id: Option<String>,
+}
+
+impl SyncMonitor {
+    pub fn new(node_id: &str) -> Self {
+        Self {
+            node_id: Some(node_id.to_string()),
+        }
+    }
+
+    pub fn assembled(&self) {
+        if let Some(node_id) = self.node_id.as_ref() {
+            SYNC_ASSEMBLED_METRIC.with_label_values(&[node_id]).inc();
+        }
+    }
+
+    pub fn dropped(&self, reason: SyncDropReason) {
+        if let Some(node_id) = self.node_id.as_ref() {
+            SYNC_DROPPED_METRIC
+                .with_label_values(&[node_id, reason.as_str()])
+                .inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Metric;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn serve_once(listener: TcpListener, status_line: &'static str) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(stream, "{status_line}\r\nContent-Length: 0\r\n\r\n").unwrap();
+            request
+        })
+    }
+
+    #[test]
+    fn test_buffer_monitor_counts_evictions() {
+        use crate::buffers::single_buffers::{FixedSizeBuffer, RtRingBuffer};
+        use crate::channels::Packet;
+        use crate::DataVersion;
+
+        let monitor = BufferMonitor::new("eviction_test", "c1");
+        let mut buffer = RtRingBuffer::<u32>::new(1, false, monitor);
+        for i in 0..3 {
+            buffer.insert(Packet::new(i, DataVersion::new(i as u128))).unwrap();
+        }
+        let evictions = EVICTION_METRIC.with_label_values(&["eviction_test", "c1"]);
+        assert_eq!(evictions.get(), 2);
+        let size = SIZE_METRIC.with_label_values(&["eviction_test", "c1"]);
+        assert_eq!(size.get(), 1);
+    }
+
+    #[test]
+    fn test_sync_monitor_counts_sets_by_reason() {
+        let monitor = SyncMonitor::new("sync_test");
+        monitor.assembled();
+        monitor.assembled();
+        monitor.dropped(SyncDropReason::QuorumNotMet);
+
+        let assembled = SYNC_ASSEMBLED_METRIC.with_label_values(&["sync_test"]);
+        assert_eq!(assembled.get(), 2);
+        let dropped = SYNC_DROPPED_METRIC.with_label_values(&["sync_test", "quorum_not_met"]);
+        assert_eq!(dropped.get(), 1);
+        let evicted = SYNC_DROPPED_METRIC.with_label_values(&["sync_test", "evicted"]);
+        assert_eq!(evicted.get(), 0);
+        SyncMonitor::default().assembled();
+    }
+
+    #[test]
+    fn test_processing_time_buckets_must_be_increasing() {
+        assert!(Metrics::no_metrics()
+            .with_processing_time_buckets(vec![0.001, 0.0005])
+            .is_err());
+        assert!(Metrics::no_metrics().with_processing_time_buckets(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_processing_time_histogram_uses_configured_buckets() {
+        let metrics = Metrics::no_metrics()
+            .with_processing_time_buckets(vec![0.001, 0.01])
+            .unwrap();
+        let histogram = metrics
+            .processing_time()
+            .unwrap()
+            .with_label_values(&["buckets_test"]);
+        histogram.observe(0.005);
+
+        let metric = histogram.metric();
+        let buckets = metric.get_histogram().get_bucket();
+        let bounds: Vec<f64> = buckets.iter().map(|bucket| bucket.get_upper_bound()).collect();
+        let counts: Vec<u64> = buckets
+            .iter()
+            .map(|bucket| bucket.get_cumulative_count())
+            .collect();
+        assert_eq!(bounds, vec![0.001, 0.01]);
+        assert_eq!(counts, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_profiler_tag_snapshot_accumulates_time() {
+        let tag = ProfilerTag::no_profiler();
+        tag.add("consumer".to_string(), "node".to_string());
+        thread::sleep(Duration::from_millis(10));
+        tag.remove("consumer".to_string(), "node".to_string());
+        thread::sleep(Duration::from_millis(100));
+
+        let snapshot = tag.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].tag, "consumer");
+        assert_eq!(snapshot[0].node_id, "node");
+        assert!(snapshot[0].duration >= Duration::from_millis(10));
+        assert!(snapshot[0].duration < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_push_metrics_puts_registry_to_job() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_once(listener, "HTTP/1.1 200 OK");
+
+        push_metrics(&format!("http://{addr}/"), "test_job").unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("PUT /metrics/job/test_job HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: text/plain; version=0.0.4"));
+    }
+
+    #[test]
+    fn test_push_metrics_fails_if_gateway_rejects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_once(listener, "HTTP/1.1 400 Bad Request");
+
+        assert!(push_metrics(&format!("http://{addr}"), "test_job").is_err());
+        server.join().unwrap();
+    }
 }
@@ -1,20 +1,56 @@
 pub mod build;
+pub mod clock;
+pub mod governor;
 pub mod metrics;
 pub mod processor;
 pub mod runtime;
+pub mod single_thread;
+pub mod topology;
+pub mod watchdog;
 
 #[cfg(test)]
 mod tests {
+    use super::build::inject;
     use super::build::link;
+    use super::build::NodeLink;
+    use super::build::tap;
     use super::build::Graph;
+    use super::build::GraphError;
+    use super::build::GraphStatus;
+    use super::build::WorkerStatus;
+    use super::clock::Clock;
+    use super::governor::GovernorConfig;
     use super::metrics::BufferMonitor;
     use super::metrics::Metrics;
+    use super::processor::collector::CollectorProcessor;
+    use super::processor::map::MapProcessor;
+    use super::processor::merge::MergeProcessor;
+    use super::processor::control_channel;
+    use super::processor::ControlReceiver;
+    use super::processor::Node;
+    use super::processor::Router;
+    use super::processor::RouterProcessor;
+    use super::processor::PacketRouter;
+    use super::processor::Processor;
+    use super::processor::ProcessorWriter;
     use super::processor::SourceNode;
     use super::processor::SourceProcessor;
     use super::processor::TerminalNode;
+    use super::processor::RestartPolicy;
     use super::processor::TerminalProcessor;
+    use super::runtime::RuntimeConfig;
+    use super::runtime::WorkerError;
+    use super::runtime::WorkerErrorKind;
+    use super::single_thread::SingleThreadRuntime;
+    use super::watchdog::StalledChannel;
+    use super::watchdog::WatchdogConfig;
+    use crate::buffers::single_buffers::LenTrait;
+    use crate::channels::ChannelID;
+    use crate::channels::Packet;
+    use crate::channels::WaterMarks;
     use crate::channels::WriteChannelTrait;
 
+    use std::sync::Arc;
     use std::sync::PoisonError;
     use std::thread;
 
@@ -30,11 +66,16 @@ mod tests {
 
     use crate::buffers::single_buffers::RtRingBuffer;
     use crate::buffers::synchronizers::timestamp::TimestampSynchronizer;
+    use crate::channels::typed_read_channel::ReadChannel1;
     use crate::channels::typed_read_channel::ReadChannel2;
     use crate::channels::typed_write_channel::WriteChannel1;
+    use crate::channels::typed_write_channel::WriteChannel2;
 
+    use crate::packet::typed::ReadChannel1PacketSet;
     use crate::packet::typed::ReadChannel2PacketSet;
     use crate::DataVersion;
+    use crate::channels::UntypedPacket;
+    use crate::channels::UntypedPacketCast;
 
     use crossbeam::channel::RecvTimeoutError;
     use std::sync::MutexGuard;
@@ -77,9 +118,7 @@ mod tests {
                 .c1()
                 .write(
                     "Test".to_string(),
-                    &DataVersion {
-                        timestamp_ns: self.counter as u128,
-                    },
+                    &DataVersion::new(self.counter as u128),
                 )
                 .unwrap();
             let e = SystemTime::now().duration_since(s).unwrap();
@@ -189,6 +228,22 @@ mod tests {
         node1: TestNodeProducer,
         consume_time_ms: u64,
         consumer_queue_strategy: WorkQueue<ReadChannel2PacketSet<String, String>>,
+    ) -> (Graph, Receiver<ReadChannel2PacketSet<String, String>>) {
+        setup_default_test_with_graph(
+            setup_test(),
+            node0,
+            node1,
+            consume_time_ms,
+            consumer_queue_strategy,
+        )
+    }
+
+    fn setup_default_test_with_graph(
+        mut graph: Graph,
+        node0: TestNodeProducer,
+        node1: TestNodeProducer,
+        consume_time_ms: u64,
+        consumer_queue_strategy: WorkQueue<ReadChannel2PacketSet<String, String>>,
     ) -> (Graph, Receiver<ReadChannel2PacketSet<String, String>>) {
         let mut node0 = create_source_node(node0);
         let mut node1 = create_source_node(node1);
@@ -210,7 +265,6 @@ mod tests {
         )
         .expect("Cannot link channels");
 
-        let mut graph = setup_test();
         graph.start_source_node(node0);
         graph.start_source_node(node1);
         graph.start_terminal_node(process_terminal);
@@ -264,6 +318,92 @@ mod tests {
         graph.stop(false, None);
     }
 
+    #[test]
+    fn test_linked_nodes_with_custom_poll_interval() {
+        let max_packets = 20;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 3, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 3, max_packets);
+
+        let graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let (graph, output_check) =
+            setup_default_test_with_graph(graph, node0, node1, 0, WorkQueue::default());
+
+        let deadline = Instant::now() + Duration::from_millis(700);
+        let results = (0..max_packets)
+            .map_while(|_| output_check.recv_deadline(deadline).ok())
+            .collect::<Vec<_>>();
+        check_results(&results, max_packets);
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_paused_graph_stops_processing_until_resumed() {
+        let max_packets = 30;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 3, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 3, max_packets);
+
+        let graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let (graph, output_check) =
+            setup_default_test_with_graph(graph, node0, node1, 0, WorkQueue::default());
+
+        graph.pause();
+        thread::sleep(Duration::from_millis(50));
+        let mut results = output_check.try_iter().collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(100));
+        assert!(output_check.try_recv().is_err());
+
+        graph.resume();
+        let deadline = Instant::now() + Duration::from_millis(700);
+        while results.len() < max_packets {
+            match output_check.recv_deadline(deadline) {
+                Ok(data) => results.push(data),
+                Err(_) => break,
+            }
+        }
+        check_results(&results, max_packets);
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_governor_pauses_graph_while_queue_waits_are_long() {
+        let mut source = create_source_node(TestNodeProducer::new(
+            "governed_producer".to_string(),
+            1,
+            100,
+        ));
+        let (consumer, _output_check) = create_version_consumer("governed_consumer", 20, 100);
+        link(
+            source.write_channel.writer.c1(),
+            consumer.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(source);
+        graph.start_terminal_node(consumer);
+        graph.start_governor(GovernorConfig {
+            pause_above: Duration::from_millis(5),
+            cooldown: Duration::from_secs(10),
+            window: Duration::from_millis(50),
+            ..GovernorConfig::default()
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while graph.status() != GraphStatus::Paused && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(graph.status(), GraphStatus::Paused);
+        graph.stop(false, None);
+    }
+
     #[test]
     fn test_graph_waits_for_data_if_stop_flag() {
         let max_packets = 100;
@@ -298,6 +438,554 @@ mod tests {
         check_results(&results, max_packets);
     }
 
+    #[test]
+    fn test_graph_shutdown_terminates_after_timeout() {
+        let max_packets = 100;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 1, max_packets);
+
+        // Consuming all the packets would take at least 5 seconds.
+        let (graph, output_check) = setup_default_test(node0, node1, 50, WorkQueue::default());
+        thread::sleep(Duration::from_millis(150));
+
+        let start = Instant::now();
+        graph.shutdown(Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_millis(1000));
+
+        let results = output_check.try_iter().collect::<Vec<_>>();
+        assert!(results.len() < max_packets);
+    }
+
+    #[test]
+    fn test_graph_runs_until_sources_complete() {
+        let max_packets = 20;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 1, max_packets);
+
+        let graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let (graph, output_check) =
+            setup_default_test_with_graph(graph, node0, node1, 2, WorkQueue::default());
+        graph.run_until_complete().expect("Graph did not complete");
+        graph.stop(false, None);
+
+        let results = output_check.try_iter().collect::<Vec<_>>();
+        check_results(&results, max_packets);
+    }
+
+    #[test]
+    fn test_graph_waits_for_data_once_all_sources_ended() {
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, 5);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 10, 50);
+
+        let graph = setup_test();
+        let (graph, _output_check) =
+            setup_default_test_with_graph(graph, node0, node1, 0, WorkQueue::default());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(graph.status(), GraphStatus::Running);
+
+        thread::sleep(Duration::from_millis(700));
+        assert_eq!(graph.status(), GraphStatus::WaitingForDataToTerminate);
+        graph.pause();
+        assert_eq!(graph.status(), GraphStatus::WaitingForDataToTerminate);
+        graph.stop(true, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_injected_packets_reach_node_and_tapped_output_is_read() {
+        let mut graph = setup_test();
+        let mut source = create_source_node(TestNodeProducer::new("producer".to_string(), 1, 3));
+        let output = tap(source.write_channel.writer.c1(), ChannelID::from("c1"));
+
+        let (consumer_output, output_check) = unbounded();
+        let consumer = create_consumer_node(
+            TestNodeConsumer::new(consumer_output, 0),
+            WorkQueue::default(),
+            10,
+            false,
+        );
+        let (c1, c2) = {
+            let mut channels = consumer
+                .read_channel
+                .channels
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            (inject(channels.c1()), inject(channels.c2()))
+        };
+        graph.start_source_node(source);
+        graph.start_terminal_node(consumer);
+
+        for channel in [&c1, &c2] {
+            channel
+                .send(Packet::new("Injected".to_string(), DataVersion::new(7)))
+                .unwrap();
+        }
+        let set = output_check
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Injected packets were not processed");
+        assert_eq!(set.c1().unwrap().data, "Injected");
+        assert_eq!(set.c2().unwrap().version, DataVersion::new(7));
+
+        let packet = output
+            .try_receive_timeout(Duration::from_millis(500))
+            .expect("Nothing written on the tapped channel");
+        assert_eq!(packet.data, "Test");
+        assert_eq!(packet.version, DataVersion::new(0));
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_graph_collects_worker_errors() {
+        let mut graph = setup_test();
+        graph.start_source_node(SourceNode::create_common(
+            "producer1".to_string(),
+            Box::new(PanickingProducer { calls: 0 }),
+        ));
+        let producer = FlakyProducer {
+            producer: TestNodeProducer::new("producer2".to_string(), 1, 10),
+            calls: 0,
+            restart_policy: RestartPolicy::Never,
+        };
+        graph.start_source_node(SourceNode::create_common(
+            "producer2".to_string(),
+            Box::new(producer),
+        ));
+        thread::sleep(Duration::from_millis(200));
+
+        let mut errors = graph
+            .errors()
+            .into_iter()
+            .map(|(node_id, error)| (node_id, error.to_string()))
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "producer1".to_string(),
+                    RustedPipeError::ProcessorError("Producer failed at call 1".to_string())
+                        .to_string()
+                ),
+                (
+                    "producer2".to_string(),
+                    RustedPipeError::ProcessorError("Transient error".to_string()).to_string()
+                ),
+            ]
+        );
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_stop_returns_termination_report() {
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, 5);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 1, 5);
+        let (graph, _output_check) = setup_default_test(node0, node1, 0, WorkQueue::default());
+        thread::sleep(Duration::from_millis(300));
+
+        let report = graph.stop(false, None);
+        assert_eq!(
+            report.nodes.keys().collect::<Vec<_>>(),
+            vec!["consumer", "producer1", "producer2"]
+        );
+        for producer in ["producer1", "producer2"] {
+            assert_eq!(report.nodes[producer].status, WorkerStatus::Terminating);
+            assert_eq!(report.nodes[producer].processed, 5);
+        }
+        assert_eq!(report.nodes["consumer"].processed, 5);
+        assert!(report.nodes.values().all(|node| node.errors.is_empty()));
+    }
+
+    #[test]
+    fn test_collector_processor_collects_received_packets() {
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 5));
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        graph.start_source_node(source);
+        graph.start_terminal_node(collector);
+        graph.run_until_complete().expect("Graph did not complete");
+        graph.stop(false, None);
+
+        let packets = packets.lock().unwrap();
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| packet.version.timestamp_ns)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert!(packets.iter().all(|packet| packet.data == "Test"));
+    }
+
+    #[test]
+    fn test_terminal_node_records_end_to_end_latency() {
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 3));
+        let (collector, _) =
+            CollectorProcessor::<String>::create_node("latency_collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        graph.start_source_node(source);
+        graph.start_terminal_node(collector);
+        graph.run_until_complete().expect("Graph did not complete");
+        graph.stop(false, None);
+
+        let latency = prometheus::gather()
+            .into_iter()
+            .find(|family| family.get_name() == "end_to_end_latency_seconds")
+            .expect("Latency metric is not registered");
+        let collector = latency
+            .get_metric()
+            .iter()
+            .find(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_value() == "latency_collector")
+            })
+            .expect("No latency recorded for the terminal node");
+        assert_eq!(collector.get_histogram().get_sample_count(), 3);
+    }
+
+    #[test]
+    fn test_run_until_complete_returns_worker_errors() {
+        let producer = FlakyProducer {
+            producer: TestNodeProducer::new("producer1".to_string(), 1, 10),
+            calls: 0,
+            restart_policy: RestartPolicy::Never,
+        };
+        let mut source = SourceNode::create_common("producer1".to_string(), Box::new(producer));
+        let (consumer, _output_check) = create_version_consumer("consumer", 0, 100);
+        link(
+            source.write_channel.writer.c1(),
+            consumer.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(source);
+        graph.start_terminal_node(consumer);
+
+        match graph.run_until_complete() {
+            Err(RustedPipeError::WorkerErrors(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, "producer1");
+                assert!(matches!(errors[0].1, RustedPipeError::ProcessorError(_)));
+            }
+            result => panic!("Expected worker errors, got {result:?}"),
+        }
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_graph_profiles_consumer_phase() {
+        let max_packets = 5;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 1, max_packets);
+
+        let (graph, output_check) = setup_default_test(node0, node1, 10, WorkQueue::default());
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let received = (0..max_packets)
+            .map_while(|_| output_check.recv_deadline(deadline).ok())
+            .count();
+        assert_eq!(received, max_packets);
+
+        let profile = graph.profile();
+        let consumer = profile
+            .iter()
+            .find(|entry| entry.tag == "consumer" && entry.node_id == "consumer")
+            .expect("Consumer was not profiled");
+        assert!(consumer.duration >= Duration::from_millis(10 * (max_packets as u64 - 1)));
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_graph_reports_worker_status() {
+        let node0 = TestNodeProducer::new("producer1".to_string(), 1, 0);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 1, 0);
+
+        let (graph, _output_check) = setup_default_test(node0, node1, 0, WorkQueue::default());
+        thread::sleep(Duration::from_millis(100));
+
+        let status = graph.worker_status();
+        assert_eq!(status.len(), 3);
+        assert_eq!(status["producer1"], WorkerStatus::Terminating);
+        assert_eq!(status["producer2"], WorkerStatus::Terminating);
+        assert_eq!(status["consumer"], WorkerStatus::Idle);
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_worker_is_stalled_while_only_some_inputs_have_data() {
+        let mut graph = setup_test();
+        let (consumer_output, _output_check) = unbounded();
+        let consumer = create_consumer_node(
+            TestNodeConsumer::new(consumer_output, 0),
+            WorkQueue::default(),
+            10,
+            false,
+        );
+        let (c1, c2) = {
+            let mut channels = consumer
+                .read_channel
+                .channels
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            (inject(channels.c1()), inject(channels.c2()))
+        };
+        graph.start_terminal_node(consumer);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(graph.worker_status()["consumer"], WorkerStatus::Idle);
+
+        c1.send(Packet::new("Test".to_string(), DataVersion::new(1)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(graph.worker_status()["consumer"], WorkerStatus::Stalled);
+
+        c2.send(Packet::new("Test".to_string(), DataVersion::new(1)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(graph.worker_status()["consumer"], WorkerStatus::Idle);
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_graph_validation_detects_cycles() {
+        let mut graph = setup_test();
+        let forward = |packet: Packet<String>| Some(packet);
+        let mut first = MapProcessor::create_node("first".to_string(), 10, forward);
+        let mut second = MapProcessor::create_node("second".to_string(), 10, forward);
+        link(
+            first.write_channel.writer.c1(),
+            second.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            second.write_channel.writer.c1(),
+            first.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        graph.try_start_node(first).expect("First node closes no cycle");
+        assert_eq!(graph.validate(), Ok(()));
+        assert_eq!(
+            graph.try_start_node(second),
+            Err(GraphError::CycleDetected(vec![
+                ChannelID::from("c1"),
+                ChannelID::from("c1")
+            ]))
+        );
+        assert_eq!(graph.validate(), Ok(()));
+
+        let mut looping = MapProcessor::create_node("looping".to_string(), 10, forward);
+        link(
+            looping.write_channel.writer.c1(),
+            looping.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        assert_eq!(
+            graph.try_start_node(looping),
+            Err(GraphError::CycleDetected(vec![ChannelID::from("c1")]))
+        );
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_dropped_packets_are_not_reported_as_worker_failures() {
+        let (output, _output_check) = unbounded();
+        let consumer = create_consumer_node(
+            TestNodeConsumer::new(output, 0),
+            WorkQueue::default(),
+            10,
+            true,
+        );
+        let c1 = inject(consumer.read_channel.channels.write().unwrap().c1());
+        let _c2 = inject(consumer.read_channel.channels.write().unwrap().c2());
+
+        let mut graph = setup_test();
+        graph.start_terminal_node(consumer);
+        c1.send(Packet::new("Test".to_string(), DataVersion::new(2)))
+            .unwrap();
+        c1.send(Packet::new("Test".to_string(), DataVersion::new(1)))
+            .unwrap();
+        // c2 never receives data, so no set is assembled and the buffer of c1 stays full.
+        for i in 3..5000 {
+            c1.send(Packet::new("Test".to_string(), DataVersion::new(i)))
+                .unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while c1.len() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(c1.len(), 0);
+
+        let errors = graph.errors();
+        graph.stop(false, None);
+        assert!(errors.is_empty(), "Unexpected errors {errors:?}");
+    }
+
+    #[test]
+    fn test_graph_does_not_start_node_with_dangling_input() {
+        let mut node0 = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 1));
+        let (output, _output_check) = unbounded();
+        let process_terminal = create_consumer_node(
+            TestNodeConsumer::new(output, 0),
+            WorkQueue::default(),
+            10,
+            false,
+        );
+        link(
+            node0.write_channel.writer.c1(),
+            process_terminal.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test();
+        assert_eq!(
+            graph.try_start_terminal_node(process_terminal),
+            Err(GraphError::DanglingInput(ChannelID::from("c2")))
+        );
+        assert!(graph.worker_status().is_empty());
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_graph_does_not_start_node_without_required_channel() {
+        let (output, _output_check) = unbounded();
+        let mut consumer = create_consumer_node(
+            TestNodeConsumer::new(output, 0),
+            WorkQueue::default(),
+            10,
+            false,
+        );
+        consumer.read_channel.synch_strategy = Box::new(
+            TimestampSynchronizer::default()
+                .with_optional_channels(&[ChannelID::from("c1"), ChannelID::from("c2")]),
+        );
+        let _c1 = inject(consumer.read_channel.channels.write().unwrap().c1());
+        let _c2 = inject(consumer.read_channel.channels.write().unwrap().c2());
+
+        let mut graph = setup_test();
+        assert!(matches!(
+            graph.try_start_terminal_node(consumer),
+            Err(GraphError::InvalidSynchronizer(..))
+        ));
+        assert!(graph.worker_status().is_empty());
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_node_is_removed_from_running_graph() {
+        let max_packets = 1000;
+        let node0 = TestNodeProducer::new("producer1".to_string(), 2, max_packets);
+        let node1 = TestNodeProducer::new("producer2".to_string(), 2, max_packets);
+
+        let (mut graph, output_check) = setup_default_test(node0, node1, 5, WorkQueue::default());
+        output_check
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Consumer did not receive data");
+
+        graph.remove_node("consumer").expect("Cannot remove node");
+        let status = graph.worker_status();
+        assert!(!status.contains_key("consumer"));
+        assert!(matches!(
+            graph.remove_node("consumer"),
+            Err(RustedPipeError::MissingNodeError(_))
+        ));
+
+        // The producers keep running without the consumer.
+        let _ = output_check.try_iter().count();
+        thread::sleep(Duration::from_millis(50));
+        assert!(output_check.try_recv().is_err());
+        assert_ne!(graph.worker_status()["producer1"], WorkerStatus::Terminating);
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_node_is_added_to_running_graph() {
+        let mut graph = setup_test();
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 2, 1000));
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        graph.start_source_node(source);
+        graph.start_terminal_node(collector);
+
+        let map = MapProcessor::create_node("map".to_string(), 10, |packet: Packet<String>| {
+            Some(packet.map(|data| format!("Mapped {data}")))
+        });
+        assert!(matches!(
+            graph.add_node(map, &[NodeLink::new("missing", "c1", "c1")], &[]),
+            Err(RustedPipeError::MissingNodeError(_))
+        ));
+
+        let map = MapProcessor::create_node("map".to_string(), 10, |packet: Packet<String>| {
+            Some(packet.map(|data| format!("Mapped {data}")))
+        });
+        assert!(matches!(
+            graph.add_node(
+                map,
+                &[NodeLink::new("producer1", "c1", "c1")],
+                &[NodeLink::new("collector", "missing", "c1")],
+            ),
+            Err(RustedPipeError::ChannelError(_))
+        ));
+        assert!(!graph.to_dot().contains("\"map\""));
+
+        let map = MapProcessor::create_node("map".to_string(), 10, |packet: Packet<String>| {
+            Some(packet.map(|data| format!("Mapped {data}")))
+        });
+        graph
+            .add_node(
+                map,
+                &[NodeLink::new("producer1", "c1", "c1")],
+                &[NodeLink::new("collector", "c1", "c1")],
+            )
+            .expect("Cannot add node");
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline
+            && !packets
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|packet| packet.data == "Mapped Test")
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        graph.stop(false, None);
+
+        assert!(packets
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|packet| packet.data == "Mapped Test"));
+        assert!(graph.to_dot().contains("\"producer1\" -> \"map\""));
+        assert!(graph.to_dot().contains("\"map\" -> \"collector\""));
+    }
+
     #[test]
     #[should_panic]
     fn test_graph_starting_same_node_id_panics() {
@@ -469,6 +1157,918 @@ mod tests {
         }
     }
 
+    struct FlakyProducer {
+        producer: TestNodeProducer,
+        calls: usize,
+        restart_policy: RestartPolicy,
+    }
+
+    impl SourceProcessor for FlakyProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            self.calls += 1;
+            if self.calls % 3 == 0 {
+                return Err(RustedPipeError::ProcessorError("Transient error".to_string()));
+            }
+            self.producer.handle(output_channel)
+        }
+
+        fn restart_policy(&self) -> RestartPolicy {
+            self.restart_policy
+        }
+    }
+
+    struct RateLimitedProducer {
+        producer: TestNodeProducer,
+        rate_limit: f64,
+    }
+
+    impl SourceProcessor for RateLimitedProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            self.producer.handle(output_channel)
+        }
+
+        fn rate_limit(&self) -> Option<f64> {
+            Some(self.rate_limit)
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_source_paces_handle_calls() {
+        let max_packets = 10;
+        let producer = RateLimitedProducer {
+            producer: TestNodeProducer::new("producer1".to_string(), 0, max_packets),
+            rate_limit: 100.0,
+        };
+        let mut source = SourceNode::create_common("producer1".to_string(), Box::new(producer));
+        let (consumer, output_check) = create_version_consumer("consumer", 0, 100);
+        link(
+            source.write_channel.writer.c1(),
+            consumer.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        let start = Instant::now();
+        graph.start_source_node(source);
+        graph.start_terminal_node(consumer);
+
+        let deadline = start + Duration::from_millis(700);
+        let versions = (0..max_packets)
+            .map_while(|_| output_check.recv_deadline(deadline).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(versions.len(), max_packets);
+        // 10 calls at 100 per second, the first one is not delayed.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_source_with_invalid_rate_limit_is_not_started() {
+        for rate_limit in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let producer = RateLimitedProducer {
+                producer: TestNodeProducer::new("producer1".to_string(), 0, 1),
+                rate_limit,
+            };
+            let source = SourceNode::create_common("producer1".to_string(), Box::new(producer));
+
+            let mut graph = setup_test();
+            assert!(matches!(
+                graph.try_start_source_node(source),
+                Err(GraphError::InvalidRateLimit(id, _)) if id == "producer1"
+            ));
+            assert!(graph.worker_status().is_empty());
+            graph.run_until_complete().unwrap();
+            graph.stop(false, None);
+        }
+    }
+
+    struct ClockedProducer {
+        producer: TestNodeProducer,
+        clock: Clock,
+    }
+
+    impl SourceProcessor for ClockedProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            self.producer.handle(output_channel)
+        }
+
+        fn clock(&self) -> Option<Clock> {
+            Some(self.clock.clone())
+        }
+    }
+
+    #[test]
+    fn test_clocked_source_produces_once_per_tick() {
+        let clock = Clock::new();
+        let producer = ClockedProducer {
+            producer: TestNodeProducer::new("producer1".to_string(), 0, 10),
+            clock: clock.clone(),
+        };
+        let mut source = SourceNode::create_common("producer1".to_string(), Box::new(producer));
+        let output = tap(source.write_channel.writer.c1(), ChannelID::from("c1"));
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(source);
+        thread::sleep(Duration::from_millis(50));
+        assert!(output.try_receive().is_err());
+
+        clock.tick();
+        clock.tick();
+        thread::sleep(Duration::from_millis(50));
+        let versions = output
+            .iter()
+            .map(|packet| packet.version.timestamp_ns)
+            .collect::<Vec<_>>();
+        assert_eq!(versions, vec![0, 1]);
+
+        graph.stop(false, None);
+    }
+
+    struct ControlledProducer {
+        control: ControlReceiver<String>,
+        mode: String,
+        counter: u128,
+    }
+
+    impl SourceProcessor for ControlledProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            mut output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            while let Some(message) = self.control.try_receive() {
+                self.mode = message.data;
+            }
+            output_channel
+                .writer
+                .c1()
+                .write(self.mode.clone(), &DataVersion::new(self.counter))?;
+            self.counter += 1;
+            thread::sleep(Duration::from_millis(5));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_source_reacts_to_control_messages() {
+        let (control, control_receiver) = control_channel();
+        let producer = ControlledProducer {
+            control: control_receiver,
+            mode: "slow".to_string(),
+            counter: 0,
+        };
+        let mut source = SourceNode::create_common("producer1".to_string(), Box::new(producer));
+        let output = tap(source.write_channel.writer.c1(), ChannelID::from("c1"));
+
+        let mut graph = setup_test();
+        graph.start_source_node(source);
+        let first = output
+            .try_receive_timeout(Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(first.data, "slow");
+
+        control
+            .send(Packet::new("fast".to_string(), DataVersion::new(0)))
+            .unwrap();
+        let switched = (0..100)
+            .map_while(|_| output.try_receive_timeout(Duration::from_millis(500)).ok())
+            .any(|packet| packet.data == "fast");
+        assert!(switched);
+
+        graph.stop(false, None);
+    }
+
+    fn test_failing_source_is_restarted_with_policy(restart_policy: RestartPolicy) -> usize {
+        let max_packets = 10;
+        let producer = FlakyProducer {
+            producer: TestNodeProducer::new("producer1".to_string(), 1, max_packets),
+            calls: 0,
+            restart_policy,
+        };
+        let mut node = SourceNode {
+            handler: Box::new(producer),
+            write_channel: TypedWriteChannel {
+                writer: Box::new(WriteChannel1::<String>::create()),
+            },
+            id: "producer1".to_string(),
+        };
+
+        let (output, output_check) = unbounded();
+        let process_terminal = TestNodeConsumer::new(output, 0);
+        let process_terminal =
+            create_consumer_node(process_terminal, WorkQueue::default(), 100, false);
+        link(
+            node.write_channel.writer.c1(),
+            process_terminal.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            node.write_channel.writer.c1(),
+            process_terminal.read_channel.channels.write().unwrap().c2(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(node);
+        graph.start_terminal_node(process_terminal);
+
+        let deadline = Instant::now() + Duration::from_millis(300);
+        let received = (0..max_packets)
+            .map_while(|_| output_check.recv_deadline(deadline).ok())
+            .count();
+        graph.stop(false, None);
+        received
+    }
+
+    #[test]
+    fn test_failing_source_is_retried_on_error() {
+        let received =
+            test_failing_source_is_restarted_with_policy(RestartPolicy::OnError { max_retries: 1 });
+        assert_eq!(received, 10);
+    }
+
+    #[test]
+    fn test_failing_source_is_terminated_without_restart_policy() {
+        let received = test_failing_source_is_restarted_with_policy(RestartPolicy::Never);
+        assert_eq!(received, 2);
+    }
+
+    struct FailingProducer;
+
+    impl SourceProcessor for FailingProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            _output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            Err(RustedPipeError::ProcessorError("Persistent error".to_string()))
+        }
+
+        fn restart_policy(&self) -> RestartPolicy {
+            RestartPolicy::Always
+        }
+    }
+
+    #[test]
+    fn test_always_failing_source_does_not_block_stop() {
+        let mut node = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 1));
+        node.handler = Box::new(FailingProducer);
+        let _output = tap(node.write_channel.writer.c1(), ChannelID::from("c1"));
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(node);
+        thread::sleep(Duration::from_millis(500));
+
+        let start = Instant::now();
+        graph.stop(false, None);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_restart_policy_limits_retries() {
+        assert!(!RestartPolicy::Never.should_retry(0));
+        assert!(RestartPolicy::OnError { max_retries: 2 }.should_retry(1));
+        assert!(!RestartPolicy::OnError { max_retries: 2 }.should_retry(2));
+        assert!(RestartPolicy::Always.should_retry(100));
+    }
+
+    struct PanickingProducer {
+        calls: usize,
+    }
+
+    impl SourceProcessor for PanickingProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            _output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            self.calls += 1;
+            if self.calls == 1 {
+                panic!("Producer failed at call {}", self.calls);
+            }
+            Err(RustedPipeError::EndOfStream())
+        }
+    }
+
+    #[test]
+    fn test_worker_panic_is_sent_on_error_channel() {
+        let (error_sender, error_receiver) = unbounded();
+        let mut graph = setup_test().with_error_channel(error_sender);
+        graph.start_source_node(SourceNode {
+            handler: Box::new(PanickingProducer { calls: 0 }),
+            write_channel: TypedWriteChannel {
+                writer: Box::new(WriteChannel1::<String>::create()),
+            },
+            id: "producer1".to_string(),
+        });
+
+        let error = error_receiver
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Panic was not reported");
+        assert_eq!(
+            error,
+            WorkerError {
+                node_id: "producer1".to_string(),
+                kind: WorkerErrorKind::Panic,
+                message: "Producer failed at call 1".to_string(),
+                dropped: vec![],
+            }
+        );
+        graph.stop(false, None);
+    }
+
+    struct StuckProducer {}
+
+    impl SourceProcessor for StuckProducer {
+        type OUTPUT = WriteChannel1<String>;
+        fn handle(
+            &mut self,
+            _output_channel: MutexGuard<TypedWriteChannel<Self::OUTPUT>>,
+        ) -> Result<(), RustedPipeError> {
+            thread::sleep(Duration::from_millis(1000));
+            Ok(())
+        }
+
+        fn handle_timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(50))
+        }
+    }
+
+    #[test]
+    fn test_stuck_worker_is_terminated_after_timeout() {
+        let (error_sender, error_receiver) = unbounded();
+        let mut graph = setup_test().with_error_channel(error_sender);
+        graph.start_source_node(SourceNode::create_common(
+            "producer1".to_string(),
+            Box::new(StuckProducer {}),
+        ));
+
+        let error = error_receiver
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Timeout was not reported");
+        assert_eq!(error.node_id, "producer1");
+        assert_eq!(error.kind, WorkerErrorKind::Timeout);
+        assert_eq!(graph.worker_status()["producer1"], WorkerStatus::Terminating);
+        graph.stop(false, None);
+    }
+
+    struct FailingConsumer {}
+
+    impl TerminalProcessor for FailingConsumer {
+        type INPUT = ReadChannel1<String>;
+        fn handle(
+            &mut self,
+            _input: ReadChannel1PacketSet<String>,
+        ) -> Result<(), RustedPipeError> {
+            thread::sleep(Duration::from_millis(100));
+            Err(RustedPipeError::ProcessorError("Consumer failed".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_packets_queued_for_failed_worker_are_reported() {
+        let (error_sender, error_receiver) = unbounded();
+        let mut graph = setup_test().with_error_channel(error_sender);
+        let consumer = TerminalNode::create_common(
+            "consumer".to_string(),
+            Box::new(FailingConsumer {}),
+            false,
+            10,
+            10,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        let input = inject(consumer.read_channel.channels.write().unwrap().c1());
+        graph.start_terminal_node(consumer);
+
+        for timestamp in 1..=5 {
+            input
+                .send(Packet::new("Test".to_string(), DataVersion::new(timestamp)))
+                .unwrap();
+        }
+        let error = error_receiver
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Dropped packets were not reported");
+        assert_eq!(error.node_id, "consumer");
+        assert_eq!(error.kind, WorkerErrorKind::DroppedPackets);
+        assert!(!error.dropped.is_empty());
+        assert!(error.dropped.iter().all(|version| version.timestamp_ns > 1));
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_watchdog_reports_channels_that_never_synchronize() {
+        let max_packets = 5;
+        let mut node0 = create_source_node(TestNodeProducer::new(
+            "producer1".to_string(),
+            1,
+            max_packets,
+        ));
+        let (output, output_check) = unbounded();
+        let process_terminal = TestNodeConsumer::new(output, 0);
+        let process_terminal =
+            create_consumer_node(process_terminal, WorkQueue::default(), 100, false);
+
+        // The second channel never receives data, so no packet set can be assembled.
+        let mut idle_writer = WriteChannel1::<String>::create();
+        link(
+            node0.write_channel.writer.c1(),
+            process_terminal.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            idle_writer.c1(),
+            process_terminal.read_channel.channels.write().unwrap().c2(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(node0);
+        graph.start_terminal_node(process_terminal);
+
+        let (stall_sender, stall_receiver) = unbounded();
+        graph.start_watchdog(WatchdogConfig {
+            stall_timeout: Duration::from_millis(50),
+            on_stall: Some(Arc::new(move |stalled: &[StalledChannel]| {
+                let _ = stall_sender.send(stalled.to_vec());
+            })),
+        });
+
+        let stalled = stall_receiver
+            .recv_timeout(Duration::from_millis(1000))
+            .expect("Stall was not reported");
+        assert_eq!(stalled.len(), 1);
+        assert_eq!(stalled[0].node_id, "consumer");
+        assert_eq!(stalled[0].occupancy, max_packets);
+        assert!(output_check.try_recv().is_err());
+
+        graph.stop(false, None);
+        drop(idle_writer);
+    }
+
+    #[test]
+    fn test_graph_runs_with_dedicated_pools() {
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 3));
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test().with_worker_threads(2).with_dedicated_pools(1);
+        graph.start_source_node(source);
+        graph.start_terminal_node(collector);
+        thread::sleep(Duration::from_millis(300));
+        graph.stop(false, None);
+
+        assert_eq!(packets.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_higher_priority_polls_more_often() {
+        let config = RuntimeConfig {
+            poll_interval: Duration::from_millis(100),
+        };
+        assert_eq!(config.poll_interval_for(0), Duration::from_millis(100));
+        assert_eq!(config.poll_interval_for(1), Duration::from_millis(50));
+        assert_eq!(config.poll_interval_for(3), Duration::from_millis(25));
+    }
+
+    struct TestRouter {}
+
+    impl RouterProcessor for TestRouter {
+        type INPUT = ReadChannel1<String>;
+        type OUTPUT = WriteChannel2<String, String>;
+        fn handle(
+            &mut self,
+            mut input: ReadChannel1PacketSet<String>,
+            mut output: PacketRouter<Self::OUTPUT>,
+        ) -> Result<(), RustedPipeError> {
+            if let Some(packet) = input.c1_owned() {
+                output.send(packet.to_untyped())?;
+            }
+            Ok(())
+        }
+    }
+
+    struct TestVersionConsumer {
+        output: Sender<u128>,
+        consume_time_ms: u64,
+    }
+
+    impl TerminalProcessor for TestVersionConsumer {
+        type INPUT = ReadChannel1<String>;
+        fn handle(&mut self, input: ReadChannel1PacketSet<String>) -> Result<(), RustedPipeError> {
+            let packet = input.c1().expect("Packet set without data");
+            self.output.send(packet.version.timestamp_ns).unwrap();
+            thread::sleep(Duration::from_millis(self.consume_time_ms));
+            Ok(())
+        }
+    }
+
+    fn create_version_consumer(
+        id: &str,
+        consume_time_ms: u64,
+        process_buffer_size: usize,
+    ) -> (TerminalNode<ReadChannel1<String>>, Receiver<u128>) {
+        let (output, output_check) = unbounded();
+        let node = TerminalNode::create_common(
+            id.to_string(),
+            Box::new(TestVersionConsumer {
+                output,
+                consume_time_ms,
+            }),
+            false,
+            100,
+            process_buffer_size,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        (node, output_check)
+    }
+
+    struct SplittingProcessor {
+        calls: usize,
+    }
+
+    impl Processor for SplittingProcessor {
+        type INPUT = ReadChannel1<String>;
+        type OUTPUT = WriteChannel1<String>;
+
+        fn handle(
+            &mut self,
+            input: ReadChannel1PacketSet<String>,
+            mut output: ProcessorWriter<Self::OUTPUT>,
+        ) -> Result<(), RustedPipeError> {
+            self.calls += 1;
+            let timestamp = input.c1().unwrap().version.timestamp_ns * 10;
+            for offset in 0..2 {
+                output
+                    .writer
+                    .c1()
+                    .write("Split".to_string(), &DataVersion::new(timestamp + offset))?;
+            }
+            if self.calls == 2 {
+                return Err(RustedPipeError::ProcessorError("Split failed".to_string()));
+            }
+            Ok(())
+        }
+
+        fn transactional_writes(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_transactional_writes_are_dropped_if_handle_fails() {
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 1, 3));
+        let mut splitter = Node::create_common(
+            "splitter".to_string(),
+            Box::new(SplittingProcessor { calls: 0 }),
+            false,
+            10,
+            10,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("split_collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            splitter.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            splitter.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test();
+        graph.start_source_node(source);
+        graph.start_node(splitter);
+        graph.start_terminal_node(collector);
+        thread::sleep(Duration::from_millis(500));
+        graph.stop(false, None);
+
+        let packets = packets.lock().unwrap();
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| packet.version.timestamp_ns)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_single_thread_runtime_runs_graph_to_completion() {
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 0, 5));
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut runtime = SingleThreadRuntime::new();
+        runtime.add_source_node(source);
+        runtime.add_terminal_node(collector);
+
+        assert!(runtime.step().unwrap());
+        assert_eq!(packets.lock().unwrap().len(), 1);
+        runtime.run_until_complete().unwrap();
+
+        let packets = packets.lock().unwrap();
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| packet.version.timestamp_ns)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_map_processor_converts_and_drops_packets() {
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 0, 5));
+        let mut map = MapProcessor::create_node("map".to_string(), 10, |packet: Packet<String>| {
+            (packet.version.timestamp_ns % 2 == 0).then(|| packet.map(|data| data.len()))
+        });
+        let (collector, packets) =
+            CollectorProcessor::<usize>::create_node("map_collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            map.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            map.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut runtime = SingleThreadRuntime::new();
+        runtime.add_source_node(source);
+        runtime.add_node(map);
+        runtime.add_terminal_node(collector);
+        runtime.run_until_complete().unwrap();
+
+        let packets = packets.lock().unwrap();
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| (packet.version.timestamp_ns, packet.data))
+                .collect::<Vec<_>>(),
+            vec![(0, 4), (2, 4), (4, 4)]
+        );
+    }
+
+    #[test]
+    fn test_single_thread_runtime_returns_failing_node() {
+        let mut source = create_source_node(TestNodeProducer::new("producer1".to_string(), 0, 3));
+        let mut splitter = Node::create_common(
+            "splitter".to_string(),
+            Box::new(SplittingProcessor { calls: 0 }),
+            false,
+            10,
+            10,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        let (collector, packets) =
+            CollectorProcessor::<String>::create_node("split_collector".to_string(), 10);
+        link(
+            source.write_channel.writer.c1(),
+            splitter.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            splitter.write_channel.writer.c1(),
+            collector.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut runtime = SingleThreadRuntime::new();
+        runtime.add_source_node(source);
+        runtime.add_node(splitter);
+        runtime.add_terminal_node(collector);
+
+        match runtime.run_until_complete() {
+            Err(RustedPipeError::WorkerErrors(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, "splitter");
+            }
+            other => panic!("Unexpected result {other:?}"),
+        }
+        assert_eq!(packets.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_router_sends_packets_to_routed_channel() {
+        let max_packets = 10;
+        let mut source = create_source_node(TestNodeProducer::new(
+            "producer1".to_string(),
+            1,
+            max_packets,
+        ));
+        let router = Router::new(TestRouter {}, |packet: &UntypedPacket| {
+            if packet.version.timestamp_ns % 2 == 0 {
+                ChannelID::from("c1")
+            } else {
+                ChannelID::from("c2")
+            }
+        });
+        let mut router = Node::create_common(
+            "router".to_string(),
+            Box::new(router),
+            false,
+            100,
+            100,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        let (even, even_check) = create_version_consumer("even", 0, 100);
+        let (odd, odd_check) = create_version_consumer("odd", 0, 100);
+
+        link(
+            source.write_channel.writer.c1(),
+            router.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            router.write_channel.writer.c1(),
+            even.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        link(
+            router.write_channel.writer.c2(),
+            odd.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test();
+        graph.start_source_node(source);
+        graph.start_node(router);
+        graph.start_terminal_node(even);
+        graph.start_terminal_node(odd);
+
+        let deadline = Instant::now() + Duration::from_millis(700);
+        let even_versions = (0..max_packets / 2)
+            .map_while(|_| even_check.recv_deadline(deadline).ok())
+            .collect::<Vec<_>>();
+        let odd_versions = (0..max_packets / 2)
+            .map_while(|_| odd_check.recv_deadline(deadline).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(even_versions, vec![0, 2, 4, 6, 8]);
+        assert_eq!(odd_versions, vec![1, 3, 5, 7, 9]);
+
+        graph.stop(false, None);
+    }
+
+    #[test]
+    fn test_congested_consumer_stalls_source() {
+        let max_packets = 1000;
+        let mut source = create_source_node(TestNodeProducer::new(
+            "producer1".to_string(),
+            1,
+            max_packets,
+        ));
+        // Without backpressure the work queue would drop most of the packets.
+        let (consumer, output_check) = create_version_consumer("consumer", 10, 8);
+        link(
+            source.write_channel.writer.c1(),
+            consumer.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+        source
+            .write_channel
+            .writer
+            .c1()
+            .set_backpressure(consumer.work_queue.clone(), WaterMarks { high: 4, low: 2 });
+
+        let mut graph = setup_test().with_runtime_config(RuntimeConfig {
+            poll_interval: Duration::from_millis(5),
+        });
+        graph.start_source_node(source);
+        graph.start_terminal_node(consumer);
+        thread::sleep(Duration::from_millis(300));
+        graph.stop(false, None);
+
+        let versions = output_check.try_iter().collect::<Vec<_>>();
+        assert!(versions.len() > 10);
+        assert_eq!(versions, (0..versions.len() as u128).collect::<Vec<_>>());
+    }
+
+    struct TestMergeConsumer {
+        output: Sender<Vec<Packet<String>>>,
+    }
+
+    impl TerminalProcessor for TestMergeConsumer {
+        type INPUT = ReadChannel1<Vec<Packet<String>>>;
+        fn handle(
+            &mut self,
+            mut input: ReadChannel1PacketSet<Vec<Packet<String>>>,
+        ) -> Result<(), RustedPipeError> {
+            let packet = input.c1_owned().expect("Packet set without data");
+            self.output.send(packet.data).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_merge_processor_merges_synchronized_inputs() {
+        let max_packets = 10;
+        let mut node0 = create_source_node(TestNodeProducer::new(
+            "producer1".to_string(),
+            1,
+            max_packets,
+        ));
+        let mut node1 = create_source_node(TestNodeProducer::new(
+            "producer2".to_string(),
+            1,
+            max_packets,
+        ));
+        let left = ChannelID::from("left");
+        let right = ChannelID::from("right");
+        let mut merge = MergeProcessor::<String, 2>::create_node(
+            "merge".to_string(),
+            [left.clone(), right.clone()],
+            false,
+            100,
+            100,
+            Box::<TimestampSynchronizer>::default(),
+        )
+        .expect("Cannot create merge node");
+        let (output, output_check) = unbounded();
+        let consumer = TerminalNode::create_common(
+            "consumer".to_string(),
+            Box::new(TestMergeConsumer { output }),
+            false,
+            100,
+            100,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+
+        {
+            let mut channels = merge.read_channel.channels.write().unwrap();
+            link(node0.write_channel.writer.c1(), channels.channel(&left).unwrap())
+                .expect("Cannot link channels");
+            link(node1.write_channel.writer.c1(), channels.channel(&right).unwrap())
+                .expect("Cannot link channels");
+        }
+        link(
+            merge.write_channel.writer.c1(),
+            consumer.read_channel.channels.write().unwrap().c1(),
+        )
+        .expect("Cannot link channels");
+
+        let mut graph = setup_test();
+        graph.start_source_node(node0);
+        graph.start_source_node(node1);
+        graph.start_node(merge);
+        graph.start_terminal_node(consumer);
+
+        let deadline = Instant::now() + Duration::from_millis(700);
+        let results = (0..max_packets)
+            .map_while(|_| output_check.recv_deadline(deadline).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), max_packets);
+        for (i, merged) in results.iter().enumerate() {
+            assert_eq!(merged.len(), 2, "At packet {}", i);
+            assert!(merged.iter().all(|packet| packet.version.timestamp_ns == i as u128));
+        }
+
+        graph.stop(false, None);
+    }
+
     macro_rules! param_test {
         ($name:ident, ($($block:ident),+)) => {
             $(
@@ -0,0 +1,156 @@
+//! A SourceProcessor wrapper interleaving recorded and live sources: the timestamps
+//! of the recorded streams are shifted to line up with the live ones, e.g. to feed a
+//! known good recording to a fusion node while its other inputs stay live.
+use std::time::Duration;
+
+use crate::channels::typed_write_channel::{AlignmentRole, TimeAlignment};
+use crate::graph::clock::Clock;
+use crate::RustedPipeError;
+
+use super::{ProcessorWriter, RestartPolicy, SourceProcessor};
+
+/// How long a recorded source waits for the first live packet in a `handle` call.
+const ANCHOR_WAIT: Duration = Duration::from_millis(100);
+
+/// Runs `source` with the versions it writes anchoring or shifted by a `TimeAlignment`
+/// shared by the live and recorded sources. Recorded sources do not produce anything
+/// until a live source wrote its first packet, which is aligned with the first
+/// recorded one.
+pub struct AlignedSource<S: SourceProcessor> {
+    source: S,
+    alignment: TimeAlignment,
+    role: AlignmentRole,
+    configured: bool,
+}
+
+impl<S: SourceProcessor> AlignedSource<S> {
+    /// Wraps a live source, whose first packet anchors `alignment`.
+    pub fn live(source: S, alignment: TimeAlignment) -> Self {
+        Self::new(source, alignment, AlignmentRole::Live)
+    }
+
+    /// Wraps a recorded source, whose packets are shifted by `alignment`.
+    pub fn recorded(source: S, alignment: TimeAlignment) -> Self {
+        Self::new(source, alignment, AlignmentRole::Recorded)
+    }
+
+    fn new(source: S, alignment: TimeAlignment, role: AlignmentRole) -> Self {
+        Self {
+            source,
+            alignment,
+            role,
+            configured: false,
+        }
+    }
+}
+
+impl<S: SourceProcessor> SourceProcessor for AlignedSource<S> {
+    type OUTPUT = S::OUTPUT;
+
+    fn handle(&mut self, mut output: ProcessorWriter<Self::OUTPUT>) -> Result<(), RustedPipeError> {
+        if !self.configured {
+            output.writer.set_time_alignment(&self.alignment, self.role);
+            self.configured = true;
+        }
+        if self.role == AlignmentRole::Recorded && !self.alignment.wait_anchored(ANCHOR_WAIT) {
+            return Ok(());
+        }
+        self.source.handle(output)
+    }
+
+    fn restart_policy(&self) -> RestartPolicy {
+        self.source.restart_policy()
+    }
+
+    fn priority(&self) -> u8 {
+        self.source.priority()
+    }
+
+    fn handle_timeout(&self) -> Option<Duration> {
+        self.source.handle_timeout()
+    }
+
+    fn rate_limit(&self) -> Option<f64> {
+        self.source.rate_limit()
+    }
+
+    fn replay_speed(&self) -> Option<f64> {
+        self.source.replay_speed()
+    }
+
+    fn clock(&self) -> Option<Clock> {
+        self.source.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::channels::typed_channel;
+    use crate::channels::typed_write_channel::{TypedWriteChannel, WriteChannel1};
+    use crate::channels::WriteChannelTrait;
+    use crate::DataVersion;
+
+    struct VersionsProducer {
+        timestamps: Vec<u128>,
+    }
+
+    impl SourceProcessor for VersionsProducer {
+        type OUTPUT = WriteChannel1<String>;
+
+        fn handle(
+            &mut self,
+            mut output: ProcessorWriter<Self::OUTPUT>,
+        ) -> Result<(), RustedPipeError> {
+            let timestamp = self.timestamps.remove(0);
+            output
+                .writer
+                .c1()
+                .write("Data".to_string(), &DataVersion::new(timestamp))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recorded_source_is_aligned_to_first_live_packet() {
+        let alignment = TimeAlignment::new();
+        let mut live = AlignedSource::live(
+            VersionsProducer {
+                timestamps: vec![1000],
+            },
+            alignment.clone(),
+        );
+        let mut recorded = AlignedSource::recorded(
+            VersionsProducer {
+                timestamps: vec![10, 25],
+            },
+            alignment.clone(),
+        );
+        let live_output = Mutex::new(TypedWriteChannel {
+            writer: Box::new(WriteChannel1::<String>::create()),
+        });
+        let recorded_output = Mutex::new(TypedWriteChannel {
+            writer: Box::new(WriteChannel1::<String>::create()),
+        });
+        let (sender, receiver) = typed_channel::<String>();
+        recorded_output.lock().unwrap().writer.c1().link(sender);
+
+        recorded.handle(recorded_output.lock().unwrap()).unwrap();
+        assert!(receiver.try_receive().is_err());
+
+        live.handle(live_output.lock().unwrap()).unwrap();
+        recorded.handle(recorded_output.lock().unwrap()).unwrap();
+        recorded.handle(recorded_output.lock().unwrap()).unwrap();
+        assert_eq!(alignment.offset_ns(), Some(990));
+        assert_eq!(
+            receiver.try_receive().unwrap().version,
+            DataVersion::new(1000)
+        );
+        assert_eq!(
+            receiver.try_receive().unwrap().version,
+            DataVersion::new(1015)
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! A ready made TerminalProcessor collecting every packet it receives, to inspect
+//! the output of a graph in tests and small tools.
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::buffers::synchronizers::timestamp::TimestampSynchronizer;
+use crate::channels::typed_read_channel::ReadChannel1;
+use crate::channels::Packet;
+use crate::packet::typed::ReadChannel1PacketSet;
+use crate::RustedPipeError;
+
+use super::{TerminalNode, TerminalProcessor};
+
+/// The packets received by a CollectorProcessor, in the order they were processed.
+pub type Collected<T> = Arc<Mutex<Vec<Packet<T>>>>;
+
+/// Pushes each packet received on its `c1` input into a shared `Vec`.
+pub struct CollectorProcessor<T> {
+    packets: Collected<T>,
+}
+
+impl<T: Clone + Send + 'static> CollectorProcessor<T> {
+    /// Creates a collector with the vector its packets are pushed into.
+    pub fn new() -> (Self, Collected<T>) {
+        let packets = Collected::<T>::default();
+        (
+            Self {
+                packets: packets.clone(),
+            },
+            packets,
+        )
+    }
+
+    /// Creates a Node running a CollectorProcessor.
+    ///
+    /// * Arguments
+    /// `id` - Id of the node. It must be unique in the graph.
+    /// `buffer_size` - The size of the buffer of the ReadChannel and of the work queue.
+    ///
+    /// * Returns
+    /// The node and the vector of the packets it collects.
+    pub fn create_node(
+        id: String,
+        buffer_size: usize,
+    ) -> (TerminalNode<ReadChannel1<T>>, Collected<T>) {
+        let (collector, packets) = Self::new();
+        let node = TerminalNode::create_common(
+            id,
+            Box::new(collector),
+            false,
+            buffer_size,
+            buffer_size,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        );
+        (node, packets)
+    }
+}
+
+impl<T: Clone + Send + 'static> TerminalProcessor for CollectorProcessor<T> {
+    type INPUT = ReadChannel1<T>;
+
+    fn handle(&mut self, mut input: ReadChannel1PacketSet<T>) -> Result<(), RustedPipeError> {
+        if let Some(packet) = input.c1_owned() {
+            self.packets
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(packet);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! A ready made Processor applying a closure to each packet, for the trivial
+//! transforms, e.g. unit conversions or downsampling, not worth a processor of their own.
+use crate::buffers::synchronizers::timestamp::TimestampSynchronizer;
+use crate::channels::typed_read_channel::ReadChannel1;
+use crate::channels::typed_write_channel::WriteChannel1;
+use crate::channels::Packet;
+use crate::packet::typed::ReadChannel1PacketSet;
+use crate::RustedPipeError;
+
+use super::{Node, Processor, ProcessorWriter};
+
+/// Maps a packet of its `c1` input to a packet of its `c1` output, or drops it.
+pub type MapFn<I, O> = Box<dyn Fn(Packet<I>) -> Option<Packet<O>> + Send + Sync>;
+
+/// Writes the packet returned by its closure for each packet received on its `c1`
+/// input to its `c1` output. Packets for which the closure returns None are dropped.
+pub struct MapProcessor<I, O> {
+    map: MapFn<I, O>,
+}
+
+impl<I: Clone + Send + 'static, O: Clone + 'static> MapProcessor<I, O> {
+    pub fn new(map: impl Fn(Packet<I>) -> Option<Packet<O>> + Send + Sync + 'static) -> Self {
+        Self { map: Box::new(map) }
+    }
+
+    /// Creates a Node running a MapProcessor.
+    ///
+    /// * Arguments
+    /// `id` - Id of the node. It must be unique in the graph.
+    /// `buffer_size` - The size of the buffer of the ReadChannel and of the work queue.
+    /// `map` - Maps each input packet to the output packet, None to drop it.
+    pub fn create_node(
+        id: String,
+        buffer_size: usize,
+        map: impl Fn(Packet<I>) -> Option<Packet<O>> + Send + Sync + 'static,
+    ) -> Node<ReadChannel1<I>, WriteChannel1<O>> {
+        Node::create_common(
+            id,
+            Box::new(Self::new(map)),
+            false,
+            buffer_size,
+            buffer_size,
+            Box::<TimestampSynchronizer>::default(),
+            false,
+        )
+    }
+}
+
+impl<I: Clone + Send + 'static, O: Clone + 'static> Processor for MapProcessor<I, O> {
+    type INPUT = ReadChannel1<I>;
+    type OUTPUT = WriteChannel1<O>;
+
+    fn handle(
+        &mut self,
+        mut input: ReadChannel1PacketSet<I>,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        if let Some(packet) = input.c1_owned().and_then(|packet| (self.map)(packet)) {
+            output.writer.c1().write_packet(packet)?;
+        }
+        Ok(())
+    }
+}
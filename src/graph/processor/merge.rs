@@ -0,0 +1,100 @@
+//! A ready made Processor merging N input channels carrying the same type.
+//!
+//! Each time the synchronizer matches a set of packets across the inputs, the
+//! node writes them, in the order of the input channels, as one `Vec` on its `c1` output.
+use std::marker::PhantomData;
+
+use crate::buffers::single_buffers::RtRingBuffer;
+use crate::buffers::synchronizers::PacketSynchronizer;
+use crate::buffers::BufferError;
+use crate::channels::merge_read_channel::MergeReadChannel;
+use crate::channels::read_channel::ReadChannel;
+use crate::channels::typed_write_channel::WriteChannel1;
+use crate::channels::{ChannelID, Packet, WriteChannelTrait};
+use crate::graph::metrics::BufferMonitor;
+use crate::packet::typed::MergePacketSet;
+use crate::packet::work_queue::WorkQueue;
+use crate::RustedPipeError;
+
+use super::{Node, Processor, ProcessorWriter};
+
+/// The output of a MergeProcessor: the merged packets of a synchronized set.
+pub type MergeOutput<T> = WriteChannel1<Vec<Packet<T>>>;
+
+/// Merges the packets of N channels of type `T` into a `Vec<Packet<T>>`. The
+/// merged packets are written with the most recent version of the set. Channels
+/// without data in a set are left out.
+pub struct MergeProcessor<T, const N: usize> {
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T, const N: usize> Default for MergeProcessor<T, N> {
+    fn default() -> Self {
+        Self { _data: PhantomData }
+    }
+}
+
+impl<T: Clone + Send + 'static, const N: usize> MergeProcessor<T, N> {
+    /// Creates a Node running a MergeProcessor.
+    ///
+    /// * Arguments
+    /// `id` - Id of the node. It must be unique in the graph.
+    /// `channel_ids` - Names of the input channels, in the order their packets are merged.
+    /// `block_channel_full` - If true, the ReadChannel will stop adding data to their buffers when full.
+    /// `channel_buffer_size` - The size of each of the buffers of the ReadChannel.
+    /// `process_buffer_size` - The size of the work queue. It will drop stuff to process when full.
+    /// `synchronizer_type` -  A synchronizer that matches data in the ReadChannel.
+    ///
+    /// * Returns
+    /// `BufferError::DuplicateChannelError` if a channel id is used twice.
+    pub fn create_node(
+        id: String,
+        channel_ids: [ChannelID; N],
+        block_channel_full: bool,
+        channel_buffer_size: usize,
+        process_buffer_size: usize,
+        synchronizer_type: Box<dyn PacketSynchronizer>,
+    ) -> Result<Node<MergeReadChannel<T, N>, MergeOutput<T>>, BufferError> {
+        let channels = MergeReadChannel::<T, N>::create(std::array::from_fn(|_| {
+            RtRingBuffer::new(
+                channel_buffer_size,
+                block_channel_full,
+                BufferMonitor::default(),
+            )
+        }))
+        .with_channel_ids(channel_ids)?;
+        let read_channel = ReadChannel::new(
+            synchronizer_type,
+            Some(WorkQueue::new(
+                process_buffer_size,
+                BufferMonitor::default(),
+            )),
+            channels,
+        );
+        Ok(Node::create(
+            id,
+            Box::new(Self::default()),
+            read_channel,
+            MergeOutput::<T>::create(),
+        ))
+    }
+}
+
+impl<T: Clone + Send + 'static, const N: usize> Processor for MergeProcessor<T, N> {
+    type INPUT = MergeReadChannel<T, N>;
+    type OUTPUT = MergeOutput<T>;
+
+    fn handle(
+        &mut self,
+        input: MergePacketSet<T>,
+        mut output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        let packets = input.into_packets();
+        let version = match packets.iter().map(|packet| packet.version).max() {
+            Some(version) => version,
+            None => return Ok(()),
+        };
+        output.writer.c1().write(packets, &version)?;
+        Ok(())
+    }
+}
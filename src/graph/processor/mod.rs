@@ -11,10 +11,17 @@
 /// TerminalProcessors do not have an output channel or type.
 /// SourceProcessors do not have an input channel or type.
 /// Processor has both.
-use std::{fmt, sync::MutexGuard};
+///
+/// A RouterProcessor is a Processor whose packets are sent to an output channel picked by a
+/// route function. It runs in a Node once wrapped in a Router.
+use std::{fmt, sync::MutexGuard, time::Duration};
 
 use crate::buffers::synchronizers::PacketSynchronizer;
-use crate::channels::WriteChannelTrait;
+use crate::channels::{
+    typed_channel, ChannelID, Packet, ReceiverChannel, SenderChannel, UntypedPacket,
+    WriteChannelTrait,
+};
+use crate::graph::clock::Clock;
 use crate::packet::work_queue::WorkQueue;
 use crate::{
     channels::{
@@ -25,6 +32,11 @@ use crate::{
     RustedPipeError,
 };
 
+pub mod align;
+pub mod collector;
+pub mod map;
+pub mod merge;
+
 /// A collection of the three node types that. Even though typed both INPUT and OUTPUT, some nodes
 /// do not have those types. For those, use NoInput or NoOutput.
 pub enum Nodes<INPUT: InputGenerator + ChannelBuffer + Send, OUTPUT: WriteChannelTrait + 'static> {
@@ -189,6 +201,15 @@ impl<OUTPUT: WriteChannelTrait + 'static> SourceNode<OUTPUT> {
             write_channel,
         }
     }
+
+    /// Makes the node fail with a `ChannelError::OutOfOrderVersion` if the processor
+    /// writes a packet older than the previous one on the same channel, for instance
+    /// when replaying a corrupted recording. Live sources reordering their data on
+    /// purpose should not enable it.
+    pub fn with_version_validation(mut self) -> Self {
+        self.write_channel.writer.validate_version_order();
+        self
+    }
 }
 
 /// Terminal node structure. This node expects data matched by its ReadChannel
@@ -278,6 +299,64 @@ impl<INPUT: InputGenerator + ChannelBuffer + Send, OUTPUT: WriteChannelTrait> fm
     }
 }
 
+/// What to do when the `handle` method of a processor returns an error,
+/// other than `RustedPipeError::EndOfStream`. Retries wait for a backoff
+/// starting at the poll interval of the worker and doubling at each attempt.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// The node is terminated.
+    #[default]
+    Never,
+    /// The same input is processed again, up to `max_retries` consecutive times
+    /// before terminating the node.
+    OnError { max_retries: usize },
+    /// The same input is processed again until it succeeds, or until the graph
+    /// or the node is stopped.
+    Always,
+}
+
+impl RestartPolicy {
+    /// True if the processor can be retried after `failures` consecutive errors.
+    pub fn should_retry(&self, failures: usize) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnError { max_retries } => failures < *max_retries,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+impl<INPUT: InputGenerator + ChannelBuffer, OUTPUT: WriteChannelTrait + 'static>
+    Processors<INPUT, OUTPUT>
+{
+    /// The restart policy of the wrapped processor.
+    pub fn restart_policy(&self) -> RestartPolicy {
+        match self {
+            Processors::SourceProcessor(proc) => proc.restart_policy(),
+            Processors::Processor(proc) => proc.restart_policy(),
+            Processors::TerminalProcessor(proc) => proc.restart_policy(),
+        }
+    }
+
+    /// The scheduling priority of the wrapped processor.
+    pub fn priority(&self) -> u8 {
+        match self {
+            Processors::SourceProcessor(proc) => proc.priority(),
+            Processors::Processor(proc) => proc.priority(),
+            Processors::TerminalProcessor(proc) => proc.priority(),
+        }
+    }
+
+    /// The maximum duration of a call of the wrapped processor.
+    pub fn handle_timeout(&self) -> Option<Duration> {
+        match self {
+            Processors::SourceProcessor(proc) => proc.handle_timeout(),
+            Processors::Processor(proc) => proc.handle_timeout(),
+            Processors::TerminalProcessor(proc) => proc.handle_timeout(),
+        }
+    }
+}
+
 /// SourceProcessor trait. This trait must be implemented for all those nodes
 /// in your graph that produce data without any input. (ie. frame readers, sensor readers, etc.).
 /// `handle` is called continuously by the Graph but sequentially, so once at the time.
@@ -296,6 +375,69 @@ pub trait SourceProcessor: Sync + Send {
         &mut self,
         output: ProcessorWriter<Self::OUTPUT>,
     ) -> Result<(), RustedPipeError>;
+
+    /// What to do if `handle` fails. The node is terminated by default.
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
+
+    /// Scheduling priority of the node. Workers with a higher priority poll for
+    /// work more often. Defaults to 0, the lowest priority.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// Maximum duration of a `handle` call. Once exceeded, the node is declared dead:
+    /// it is terminated and a `WorkerErrorKind::Timeout` error is reported, although
+    /// the call itself cannot be interrupted. Defaults to None, no timeout.
+    fn handle_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Maximum number of `handle` calls per second. Defaults to None, `handle`
+    /// is called again as soon as it returns.
+    fn rate_limit(&self) -> Option<f64> {
+        None
+    }
+
+    /// Replays the written packets at the pace of their `DataVersion` timestamps,
+    /// times `replay_speed`: 2.0 replays twice as fast, 0.5 at half the speed.
+    /// Writes block until the packet is due. Defaults to None, packets are written
+    /// as soon as they are produced.
+    fn replay_speed(&self) -> Option<f64> {
+        None
+    }
+
+    /// A clock stepping the source: `handle` is only called once per tick of the clock.
+    /// Defaults to None, `handle` is called again as soon as it returns.
+    fn clock(&self) -> Option<Clock> {
+        None
+    }
+}
+
+/// Receives control messages sent to a processor, e.g. commands changing the mode
+/// of a source. A SourceProcessor can hold one and read the pending messages in
+/// each `handle` call.
+pub struct ControlReceiver<T: Send> {
+    receiver: ReceiverChannel<T>,
+}
+
+impl<T: Send> ControlReceiver<T> {
+    pub fn new(receiver: ReceiverChannel<T>) -> Self {
+        Self { receiver }
+    }
+
+    /// The oldest pending message, without blocking. None if there is none
+    /// or if the sender was dropped.
+    pub fn try_receive(&self) -> Option<Packet<T>> {
+        self.receiver.try_receive().ok()
+    }
+}
+
+/// Creates the channel sending control messages of type `T` to a processor.
+pub fn control_channel<T: Send>() -> (SenderChannel<T>, ControlReceiver<T>) {
+    let (sender, receiver) = typed_channel();
+    (sender, ControlReceiver::new(receiver))
 }
 
 /// A locked WriteChannel to allow writing data from a Processor.
@@ -324,6 +466,144 @@ pub trait Processor: Sync + Send {
         input: <Self::INPUT as InputGenerator>::INPUT,
         output: ProcessorWriter<Self::OUTPUT>,
     ) -> Result<(), RustedPipeError>;
+
+    /// What to do if `handle` fails. The node is terminated by default.
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
+
+    /// Scheduling priority of the node. Workers with a higher priority poll for
+    /// work more often. Defaults to 0, the lowest priority.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// Maximum duration of a `handle` call. Once exceeded, the node is declared dead:
+    /// it is terminated and a `WorkerErrorKind::Timeout` error is reported, although
+    /// the call itself cannot be interrupted. Defaults to None, no timeout.
+    fn handle_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// If true, all the packets written during a `handle` call are sent once it returns
+    /// Ok, in the order they were written, and dropped if it returns an error: the
+    /// nodes downstream never see the partial output of a failed call. Defaults to
+    /// false, packets are sent as soon as they are written.
+    fn transactional_writes(&self) -> bool {
+        false
+    }
+}
+
+/// Picks the output channel of a packet sent by a RouterProcessor.
+pub type RouteFn = Box<dyn Fn(&UntypedPacket) -> ChannelID + Send + Sync>;
+
+/// Output of a RouterProcessor. It sends each packet to the channel picked by the route.
+pub struct PacketRouter<'a, OUTPUT: WriteChannelTrait> {
+    output: ProcessorWriter<'a, OUTPUT>,
+    route: &'a RouteFn,
+}
+
+impl<'a, OUTPUT: WriteChannelTrait> PacketRouter<'a, OUTPUT> {
+    /// Sends `packet` to the output channel picked by the route.
+    ///
+    /// * Returns
+    /// The channel the packet was sent to, or an error if the channel does not exist
+    /// or the packet data is not of the channel type.
+    pub fn send(&mut self, packet: UntypedPacket) -> Result<ChannelID, RustedPipeError> {
+        let channel = (self.route)(&packet);
+        self.output.writer.write_untyped(&channel, packet)?;
+        Ok(channel)
+    }
+}
+
+/// RouterProcessor trait for nodes whose output goes to different channels depending on
+/// the data, so that downstream nodes do not need to filter it. Instead of writing to the
+/// output channels directly, `handle` sends untyped packets to a PacketRouter.
+pub trait RouterProcessor: Sync + Send {
+    /// Trait object that gives access to the matched packet data.
+    type INPUT: InputGenerator;
+    /// The output channels the packets are routed to.
+    type OUTPUT: WriteChannelTrait;
+    /// Called when data is matched, like `Processor::handle`.
+    ///
+    /// * Arguments
+    /// `input` - Reference to input channels for reading data from the ReadChannel.
+    /// `output` - Router sending the packets to the channel picked by the route.
+    fn handle(
+        &mut self,
+        input: <Self::INPUT as InputGenerator>::INPUT,
+        output: PacketRouter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError>;
+
+    /// What to do if `handle` fails. The node is terminated by default.
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
+
+    /// Scheduling priority of the node. Workers with a higher priority poll for
+    /// work more often. Defaults to 0, the lowest priority.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// Maximum duration of a `handle` call. Once exceeded, the node is declared dead:
+    /// it is terminated and a `WorkerErrorKind::Timeout` error is reported, although
+    /// the call itself cannot be interrupted. Defaults to None, no timeout.
+    fn handle_timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A Processor running a RouterProcessor with the route deciding where its packets go.
+pub struct Router<P: RouterProcessor> {
+    processor: P,
+    route: RouteFn,
+}
+
+impl<P: RouterProcessor> Router<P> {
+    /// * Arguments
+    /// `processor` - The processor producing the packets.
+    /// `route` - Picks the output channel of each packet, by channel name (`c1`, `c2`, ...).
+    pub fn new(
+        processor: P,
+        route: impl Fn(&UntypedPacket) -> ChannelID + Send + Sync + 'static,
+    ) -> Self {
+        Router {
+            processor,
+            route: Box::new(route),
+        }
+    }
+}
+
+impl<P: RouterProcessor> Processor for Router<P> {
+    type INPUT = P::INPUT;
+    type OUTPUT = P::OUTPUT;
+
+    fn handle(
+        &mut self,
+        input: <Self::INPUT as InputGenerator>::INPUT,
+        output: ProcessorWriter<Self::OUTPUT>,
+    ) -> Result<(), RustedPipeError> {
+        self.processor.handle(
+            input,
+            PacketRouter {
+                output,
+                route: &self.route,
+            },
+        )
+    }
+
+    fn restart_policy(&self) -> RestartPolicy {
+        self.processor.restart_policy()
+    }
+
+    fn priority(&self) -> u8 {
+        self.processor.priority()
+    }
+
+    fn handle_timeout(&self) -> Option<Duration> {
+        self.processor.handle_timeout()
+    }
 }
 
 /// TerminalProcessor trait for data processing that produces no output. This can link your data
@@ -339,4 +619,22 @@ pub trait TerminalProcessor: Sync + Send {
         &mut self,
         input: <Self::INPUT as InputGenerator>::INPUT,
     ) -> Result<(), RustedPipeError>;
+
+    /// What to do if `handle` fails. The node is terminated by default.
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
+
+    /// Scheduling priority of the node. Workers with a higher priority poll for
+    /// work more often. Defaults to 0, the lowest priority.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// Maximum duration of a `handle` call. Once exceeded, the node is declared dead:
+    /// it is terminated and a `WorkerErrorKind::Timeout` error is reported, although
+    /// the call itself cannot be interrupted. Defaults to None, no timeout.
+    fn handle_timeout(&self) -> Option<Duration> {
+        None
+    }
 }
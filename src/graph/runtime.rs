@@ -1,11 +1,17 @@
 use super::{
     build::{ProcessorWorker, WorkerStatus},
-    metrics::ProfilerTag,
-    processor::Processors,
+    clock::Clock,
+    metrics::{processing_time_histogram, ProfilerTag},
+    processor::{Processors, RestartPolicy},
 };
+use crate::buffers::single_buffers::LenTrait;
+use crate::buffers::BufferError;
+use crate::channels::ChannelError;
 use crate::channels::ReadChannelTrait;
 use crate::channels::WriteChannelTrait;
 use crate::graph::build::GraphStatus;
+use crate::packet::typed::PacketSetTrait;
+use crate::DataVersion;
 use crate::{
     channels::read_channel::{ChannelBuffer, InputGenerator},
     RustedPipeError,
@@ -15,118 +21,471 @@ use crate::{
     packet::work_queue::WorkQueue,
 };
 use atomic::{Atomic, Ordering};
-use crossbeam::channel::Sender;
+use crossbeam::channel::{bounded, RecvTimeoutError, Sender};
 use lazy_static::lazy_static;
-use log::{debug, warn};
 use prometheus::{histogram_opts, register_histogram_vec};
 use prometheus::{Histogram, HistogramVec};
 use rusty_pool::ThreadPool;
 use std::{
-    sync::{Arc, Condvar, Mutex, PoisonError},
+    any::Any,
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 lazy_static! {
-    static ref METRICS_TIMER: HistogramVec = register_histogram_vec!(
+    static ref METRICS_TIMER: HistogramVec =
+        processing_time_histogram(prometheus::DEFAULT_BUCKETS.to_vec())
+            .expect("Cannot create processing_time metrics");
+    static ref QUEUE_WAIT_TIMER: HistogramVec = register_histogram_vec!(
         histogram_opts!(
-            "processing_time",
-            format!("Timing for a single processor run."),
+            "queue_wait_seconds",
+            format!("Time a packet set waited in the work queue before being processed."),
         ),
         &["node_id"]
     )
-    .expect("Cannot create processing_time metrics");
+    .expect("Cannot create queue_wait_seconds metrics");
+    static ref END_TO_END_LATENCY: HistogramVec = register_histogram_vec!(
+        histogram_opts!(
+            "end_to_end_latency_seconds",
+            format!("Time from the oldest version of a packet set to its processing by a terminal node."),
+        ),
+        &["node_id"]
+    )
+    .expect("Cannot create end_to_end_latency_seconds metrics");
+}
+
+/// Timing configuration of the threads running the nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeConfig {
+    /// How long a worker waits for new work, or sleeps while its processor
+    /// is busy, before checking the graph status again. Lower values reduce
+    /// latency, higher values reduce CPU usage.
+    pub poll_interval: Duration,
+}
+
+impl RuntimeConfig {
+    /// Poll interval of a worker with `priority`. Higher priorities divide the
+    /// configured interval, so their workers check for work and status changes sooner.
+    pub fn poll_interval_for(&self, priority: u8) -> Duration {
+        self.poll_interval / (priority as u32 + 1)
+    }
 }
 
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// How the processor of a node failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WorkerErrorKind {
+    /// The processor panicked.
+    Panic,
+    /// A call of the processor took longer than its `handle_timeout`.
+    Timeout,
+    /// The worker terminated while packet sets were still waiting in its work queue.
+    DroppedPackets,
+}
+
+/// Error raised by the processor of a node while the graph is running.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct WorkerError {
+    /// Id of the node whose processor failed.
+    pub node_id: String,
+    /// How the processor failed.
+    pub kind: WorkerErrorKind,
+    /// Message of the panic raised by the processor, of its timeout, or
+    /// counting the dropped packets.
+    pub message: String,
+    /// Version of every packet dropped from the work queue, only set for
+    /// `WorkerErrorKind::DroppedPackets`.
+    pub dropped: Vec<DataVersion>,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic payload".to_string()
+    }
+}
+
+/// Waits before retrying a processor after `attempt` consecutive errors. The delay
+/// starts at the poll interval and doubles at each attempt, up to 64 poll intervals.
+///
+/// * Returns
+/// False if the graph terminates or the worker is stopped meanwhile, and the
+/// processor should not be retried.
+fn wait_before_retry(
+    attempt: usize,
+    poll_interval: Duration,
+    running: &Atomic<GraphStatus>,
+    stop: &AtomicBool,
+) -> bool {
+    let backoff = poll_interval * (1 << attempt.saturating_sub(1).min(6));
+    let deadline = Instant::now() + backoff;
+    loop {
+        if running.load(Ordering::Relaxed) == GraphStatus::Terminating
+            || stop.load(Ordering::Relaxed)
+        {
+            return false;
+        }
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(wait) if !wait.is_zero() => thread::sleep(wait.min(poll_interval)),
+            _ => return true,
+        }
+    }
+}
+
+/// Minimum time between two reports, or two logs, of the read errors of a node.
+const READ_ERROR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// True for the packets a buffer refuses by design, e.g. because it's full or
+/// the packet is out of order. They are counted and logged, not reported.
+fn is_dropped_packet(err: &ChannelError) -> bool {
+    matches!(
+        err,
+        ChannelError::ErrorInBuffer(
+            BufferError::BufferFull
+                | BufferError::OutOfOrder(..)
+                | BufferError::DuplicateDataVersionError(_)
+                | BufferError::VersionRegression(..)
+        )
+    )
+}
+
+/// Aggregates the read errors of a node, so that a persistently failing reader
+/// reports at most one failure per `READ_ERROR_INTERVAL`.
+#[derive(Default)]
+struct ReadErrors {
+    dropped: u64,
+    failed: u64,
+    last_log: Option<Instant>,
+    last_report: Option<Instant>,
+}
+
+impl ReadErrors {
+    /// Counts the error.
+    ///
+    /// * Returns
+    /// The error if it should be reported as a failure of the node.
+    fn record(&mut self, id: &str, err: ChannelError, now: Instant) -> Option<ChannelError> {
+        let due = |last: Option<Instant>| {
+            last.is_none_or(|last| now.duration_since(last) >= READ_ERROR_INTERVAL)
+        };
+        if is_dropped_packet(&err) {
+            self.dropped += 1;
+            if due(self.last_log) {
+                tracing::warn!(node_id = %id, dropped = self.dropped, error = %err, "Input buffers are dropping packets");
+                self.last_log = Some(now);
+            }
+            return None;
+        }
+        self.failed += 1;
+        if !due(self.last_report) {
+            return None;
+        }
+        tracing::error!(node_id = %id, failed = self.failed, error = %err, "Error while reading data");
+        self.last_report = Some(now);
+        Some(err)
+    }
+}
+
+/// Reads the input channels of the node `id` until the graph terminates or the reader
+/// is stopped. The errors of the reads are sent to `failure_notification`, at most
+/// once per `READ_ERROR_INTERVAL`, except for the packets dropped by the buffers.
 pub(super) fn read_channel_data<T: InputGenerator + ChannelBuffer + Send>(
     id: String,
     running: Arc<Atomic<GraphStatus>>,
     mut read_channel: ReadChannel<T>,
     done_notification: Sender<String>,
+    failure_notification: Sender<(String, RustedPipeError)>,
+    stop: Arc<AtomicBool>,
 ) where
     T: ChannelBuffer + 'static,
 {
     let id = id;
-    while running.load(Ordering::Relaxed) != GraphStatus::Terminating {
-        read_channel.read(id.clone(), done_notification.clone());
+    let mut errors = ReadErrors::default();
+    while running.load(Ordering::Relaxed) != GraphStatus::Terminating && !stop.load(Ordering::Relaxed)
+    {
+        if let Err(err) = read_channel.read(id.clone(), done_notification.clone()) {
+            if let Some(err) = errors.record(&id, err, Instant::now()) {
+                let _ = failure_notification.send((id.clone(), err.into()));
+            }
+        }
     }
     read_channel.stop();
 }
 
+/// Runs the processing of the nodes. Implement it to control the threads
+/// running the processors, for instance to pin them to specific cores.
+pub trait Executor: Clone + Send + 'static {
+    /// Handle to wait for a task to complete.
+    type Handle: TaskHandle;
+    /// Starts running a task.
+    fn evaluate<F: FnOnce() + Send + 'static>(&self, task: F) -> Self::Handle;
+    /// Creates an executor of the same kind running on `threads` threads, see
+    /// `Graph::with_worker_threads`. Defaults to None, the executor cannot be sized.
+    fn sized(&self, _threads: usize) -> Option<Self> {
+        None
+    }
+}
+
+/// Handle of a task started by an `Executor`.
+pub trait TaskHandle {
+    /// Blocks until the task is completed.
+    ///
+    /// * Returns
+    /// The payload of the panic if the task panicked.
+    fn await_complete(self) -> thread::Result<()>;
+}
+
+impl Executor for ThreadPool {
+    type Handle = rusty_pool::JoinHandle<thread::Result<()>>;
+
+    /// The panics are caught in the task, the pool drops their payload otherwise.
+    fn evaluate<F: FnOnce() + Send + 'static>(&self, task: F) -> Self::Handle {
+        ThreadPool::evaluate(self, move || panic::catch_unwind(AssertUnwindSafe(task)))
+    }
+
+    fn sized(&self, threads: usize) -> Option<Self> {
+        Some(ThreadPool::new(threads, threads, Duration::from_secs(60)))
+    }
+}
+
+impl TaskHandle for rusty_pool::JoinHandle<thread::Result<()>> {
+    fn await_complete(self) -> thread::Result<()> {
+        self.try_await_complete()
+            .unwrap_or_else(|_| Err(Box::new("Task was cancelled by the thread pool")))
+    }
+}
+
 pub(super) type Wait = Arc<(Mutex<WorkerStatus>, Condvar)>;
 
-pub(super) struct ConsumerThread<INPUT, OUTPUT>
+/// Tracks the source nodes that did not reach the end of their stream yet. Once
+/// the last one ends, the graph starts waiting for the data to be consumed.
+#[derive(Clone, Default)]
+pub(super) struct SourceTracker(Arc<Mutex<HashSet<String>>>);
+
+impl SourceTracker {
+    pub fn add(&self, id: &str) {
+        self.lock().insert(id.to_string());
+    }
+
+    /// Marks the source as ended. If it was the last active one, a running graph
+    /// moves to `GraphStatus::WaitingForDataToTerminate`.
+    pub fn end(&self, id: &str, running: &Atomic<GraphStatus>) {
+        let mut sources = self.lock();
+        if sources.remove(id) && sources.is_empty() {
+            tracing::info!(node_id = %id, "Last source ended, waiting for data to be consumed");
+            let _ = running.compare_exchange(
+                GraphStatus::Running,
+                GraphStatus::WaitingForDataToTerminate,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Resumes a paused graph. If all the sources ended in the meantime, the graph
+    /// waits for the data to be consumed.
+    pub fn resume(&self, running: &Atomic<GraphStatus>) {
+        let status = if self.is_empty() {
+            GraphStatus::WaitingForDataToTerminate
+        } else {
+            GraphStatus::Running
+        };
+        let _ = running.compare_exchange(
+            GraphStatus::Paused,
+            status,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<HashSet<String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub(super) struct ConsumerThread<INPUT, OUTPUT, E = ThreadPool>
 where
     INPUT: InputGenerator + ChannelBuffer + Send + 'static,
     OUTPUT: WriteChannelTrait + 'static + Send,
+    E: Executor,
 {
     id: String,
     running: Arc<Atomic<GraphStatus>>,
     _free: Wait,
     done_notification: Sender<String>,
-    thread_pool: ThreadPool,
+    error_notification: Option<Sender<WorkerError>>,
+    // Receives the errors terminating the processor, its panics and timeouts.
+    failure_notification: Sender<(String, RustedPipeError)>,
+    thread_pool: E,
     metrics_timer: Histogram,
+    queue_wait_timer: Histogram,
+    latency_timer: Histogram,
     profiler: Arc<ProfilerTag>,
     shared_writer: Option<Arc<Mutex<TypedWriteChannel<OUTPUT>>>>,
     shared_processor: Arc<Mutex<Processors<INPUT, OUTPUT>>>,
     status: Arc<Atomic<WorkerStatus>>,
     work_queue: Option<WorkQueue<INPUT::INPUT>>,
+    // Set when the node is removed, the worker exits once its queue is drained.
+    stop: Arc<AtomicBool>,
+    poll_interval: Duration,
+    restart_policy: RestartPolicy,
+    // Consecutive errors of the processor, reset on success.
+    failures: Arc<AtomicUsize>,
+    // Successful calls of the processor.
+    processed: Arc<AtomicUsize>,
+    handle_timeout: Option<Duration>,
+    // Set for source nodes only.
+    sources: Option<SourceTracker>,
+    // Minimum time between two calls of the processor, set by its rate limit.
+    min_interval: Option<Duration>,
+    last_call: Option<Instant>,
+    // Clock stepping a source, with the number of its ticks already used.
+    clock: Option<Clock>,
+    clock_seen: u64,
 }
 
-impl<INPUT, OUTPUT> ConsumerThread<INPUT, OUTPUT>
+impl<INPUT, OUTPUT, E> ConsumerThread<INPUT, OUTPUT, E>
 where
     INPUT: InputGenerator + ChannelBuffer + Send + 'static,
     OUTPUT: WriteChannelTrait + 'static + Send,
+    E: Executor,
 {
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
         id: String,
         running: Arc<Atomic<GraphStatus>>,
         free: Wait,
+        status: Arc<Atomic<WorkerStatus>>,
         worker: ProcessorWorker<INPUT, OUTPUT>,
         done_notification: Sender<String>,
-        thread_pool: ThreadPool,
+        error_notification: Option<Sender<WorkerError>>,
+        failure_notification: Sender<(String, RustedPipeError)>,
+        thread_pool: E,
         profiler: ProfilerTag,
+        config: RuntimeConfig,
+        processing_time: Option<HistogramVec>,
     ) -> Self {
-        let metrics_timer = METRICS_TIMER.with_label_values(&[&id]);
+        let metrics_timer = processing_time
+            .as_ref()
+            .unwrap_or(&*METRICS_TIMER)
+            .with_label_values(&[&id]);
+        let queue_wait_timer = QUEUE_WAIT_TIMER.with_label_values(&[&id]);
+        let latency_timer = END_TO_END_LATENCY.with_label_values(&[&id]);
 
-        let mut shared_writer = None;
-        if let Some(channel) = worker.write_channel {
-            shared_writer = Some(Arc::new(Mutex::new(channel)));
-        }
+        let shared_writer = worker.write_channel;
 
+        let restart_policy = worker.processor.restart_policy();
+        let handle_timeout = worker.processor.handle_timeout();
+        let poll_interval = config.poll_interval_for(worker.priority);
         let shared_processor = Arc::new(Mutex::new(worker.processor));
-        let status = Arc::new(Atomic::new(WorkerStatus::Idle));
         let work_queue = worker.work_queue;
+        let stop = worker.stop;
+        let sources = worker.sources;
+        let clock = worker.clock;
+        let processed = worker.processed;
+        let min_interval = worker.rate_limit.map(|rate| {
+            if rate <= 0.0 || !rate.is_finite() {
+                panic!("Rate limit of node {id} must be positive, got {rate}");
+            }
+            Duration::from_secs_f64(1.0 / rate)
+        });
         Self {
             id,
             running,
             _free: free,
             done_notification,
+            error_notification,
+            failure_notification,
             thread_pool,
             metrics_timer,
+            queue_wait_timer,
+            latency_timer,
             profiler: Arc::new(profiler),
             shared_writer,
             shared_processor,
             status,
             work_queue,
+            stop,
+            poll_interval,
+            restart_policy,
+            failures: Arc::new(AtomicUsize::new(0)),
+            processed,
+            handle_timeout,
+            sources,
+            min_interval,
+            last_call: None,
+            clock,
+            clock_seen: 0,
         }
     }
 
     pub(super) fn consume(&mut self) {
         while self.running.load(Ordering::Relaxed) != GraphStatus::Terminating {
-            if self.status.load(Ordering::Relaxed) == WorkerStatus::Idle {
+            let stopping = self.stop.load(Ordering::Relaxed);
+            let status = self.status.load(Ordering::Relaxed);
+            // A set still being processed in the pool is waited for before exiting.
+            if stopping
+                && (status == WorkerStatus::Terminating
+                    || (status != WorkerStatus::Running
+                        && self.work_queue.iter().all(|queue| queue.is_empty())))
+            {
+                break;
+            }
+            if !stopping && self.running.load(Ordering::Relaxed) == GraphStatus::Paused {
+                thread::sleep(self.poll_interval);
+                continue;
+            }
+            if matches!(
+                self.status.load(Ordering::Relaxed),
+                WorkerStatus::Idle | WorkerStatus::Stalled
+            ) {
+                if self.work_queue.is_none() && self.is_congested() {
+                    thread::sleep(self.poll_interval);
+                    continue;
+                }
+                if let Some(wait) = self.rate_limit_wait() {
+                    thread::sleep(wait.min(self.poll_interval));
+                    continue;
+                }
+                if !self.wait_for_tick() {
+                    continue;
+                }
                 let lock_status = self.status.clone();
 
                 let mut packet = None;
                 if let Some(work_queue) = self.work_queue.as_mut() {
-                    let task = work_queue.get(Some(Duration::from_millis(100)));
+                    let task = work_queue.get(Some(self.poll_interval));
                     if let Ok(read_event) = task {
+                        self.queue_wait_timer
+                            .observe(read_event.enqueued_at.elapsed().as_secs_f64());
                         packet = Some(read_event.packet_data);
                     } else {
                         if self.running.load(Ordering::Relaxed)
                             == GraphStatus::WaitingForDataToTerminate
                         {
-                            debug!("Sending done {}", self.id);
+                            tracing::debug!(node_id = %self.id, "Sending done");
                             let _ = self.done_notification.send(self.id.clone());
                         }
 
@@ -134,6 +493,7 @@ where
                     }
                 }
                 self.status.store(WorkerStatus::Running, Ordering::Relaxed);
+                self.last_call = Some(Instant::now());
 
                 let processor_clone = self.shared_processor.clone();
                 let profiler_clone = self.profiler.clone();
@@ -141,78 +501,250 @@ where
                 let arc_write_channel = self.shared_writer.clone();
                 let done_clone = self.done_notification.clone();
                 let metrics_clone = self.metrics_timer.clone();
+                let latency_clone = self.latency_timer.clone();
+                let restart_policy = self.restart_policy;
+                let failures = self.failures.clone();
+                let processed = self.processed.clone();
+                let failure_clone = self.failure_notification.clone();
 
                 let future = move || {
+                    let span = tracing::info_span!("handle", node_id = %id_thread);
+                    let _entered = span.enter();
                     profiler_clone.add("consumer".to_string(), id_thread.clone());
                     let timer = metrics_clone.start_timer();
-                    let result = match &mut *processor_clone
-                        .lock()
-                        .unwrap_or_else(PoisonError::into_inner)
-                    {
-                        Processors::Processor(proc) => {
-                            if let Some(packet) = packet {
-                                let write_channel = arc_write_channel.expect(&format!(
+                    let result = loop {
+                        // Keep a copy of the input only if it may be processed again.
+                        let input = if restart_policy == RestartPolicy::Never {
+                            packet.take()
+                        } else {
+                            packet.clone()
+                        };
+                        // The processor is locked for one attempt at a time.
+                        let mut processor = processor_clone
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner);
+                        let result = match &mut *processor {
+                            Processors::Processor(proc) => {
+                                if let Some(packet) = input {
+                                    let write_channel = arc_write_channel.as_ref().expect(&format!(
+                                        "Consumer thread for node {} was created without write channel",
+                                        id_thread
+                                    ));
+                                    let result = proc.handle(
+                                        packet,
+                                        write_channel.lock().unwrap_or_else(PoisonError::into_inner),
+                                    );
+
+                                    // Sends or drops the packets staged by transactional writes.
+                                    let mut output =
+                                        write_channel.lock().unwrap_or_else(PoisonError::into_inner);
+                                    match result {
+                                        Ok(()) => output.writer.commit().map_err(RustedPipeError::from),
+                                        Err(err) => {
+                                            output.writer.rollback();
+                                            Err(err)
+                                        }
+                                    }
+                                } else {
+                                    tracing::warn!("Packet is None, not processing");
+                                    return;
+                                }
+                            }
+                            Processors::TerminalProcessor(proc) => {
+                                if let Some(packet) = input {
+                                    let oldest = packet.versions().into_iter().min();
+                                    let result = proc.handle(packet);
+                                    if let (Ok(_), Some(oldest)) = (&result, oldest) {
+                                        let age = DataVersion::from_now().duration_since(&oldest);
+                                        latency_clone.observe(age.as_secs_f64());
+                                    }
+                                    result
+                                } else {
+                                    tracing::warn!("Packet is None, not processing");
+                                    return;
+                                }
+                            }
+                            Processors::SourceProcessor(proc) => {
+                                let write_channel = arc_write_channel.as_ref().expect(&format!(
                                     "Consumer thread for node {} was created without write channel",
                                     id_thread
                                 ));
                                 let write_channel =
                                     write_channel.lock().unwrap_or_else(PoisonError::into_inner);
 
-                                proc.handle(packet, write_channel)
-                            } else {
-                                warn!("Packet is None, not processing");
-                                return;
+                                proc.handle(write_channel)
                             }
-                        }
-                        Processors::TerminalProcessor(proc) => {
-                            if let Some(packet) = packet {
-                                proc.handle(packet)
-                            } else {
-                                warn!("Packet is None, not processing");
-                                return;
+                        };
+                        drop(processor);
+                        match result {
+                            Err(RustedPipeError::EndOfStream()) => break result,
+                            Err(err) if restart_policy.should_retry(failures.load(Ordering::Relaxed)) => {
+                                let attempt = failures.fetch_add(1, Ordering::Relaxed) + 1;
+                                tracing::warn!(attempt, error = ?err, "Retrying worker after error");
+                                if !wait_before_retry(attempt, poll_interval, &running, &stop) {
+                                    break Err(err);
+                                }
                             }
-                        }
-                        Processors::SourceProcessor(proc) => {
-                            let write_channel = arc_write_channel.expect(&format!(
-                                "Consumer thread for node {} was created without write channel",
-                                id_thread
-                            ));
-                            let write_channel =
-                                write_channel.lock().unwrap_or_else(PoisonError::into_inner);
-
-                            proc.handle(write_channel)
+                            _ => break result,
                         }
                     };
 
                     profiler_clone.remove("consumer".to_string(), id_thread.clone());
                     timer.observe_duration();
+                    if result.is_ok() {
+                        failures.store(0, Ordering::Relaxed);
+                        processed.fetch_add(1, Ordering::Relaxed);
+                    }
                     match result {
-                        Ok(_) => lock_status.store(WorkerStatus::Idle, Ordering::Relaxed),
+                        // A worker that timed out stays terminated.
+                        Ok(_) => {
+                            let _ = lock_status.compare_exchange(
+                                WorkerStatus::Running,
+                                WorkerStatus::Idle,
+                                Ordering::Relaxed,
+                                Ordering::Relaxed,
+                            );
+                        }
                         Err(RustedPipeError::EndOfStream()) => {
-                            tracing::error!("Terminating worker {id_thread:?}");
+                            tracing::info!(worker_status = ?WorkerStatus::Terminating, "End of stream, terminating worker");
                             lock_status.store(WorkerStatus::Terminating, Ordering::Relaxed);
                             let _ = done_clone.send(id_thread.clone());
                         }
                         Err(err) => {
-                            tracing::error!("Error in worker {id_thread:?}: {err:?}");
+                            tracing::error!(worker_status = ?WorkerStatus::Terminating, error = ?err, "Error in worker");
                             lock_status.store(WorkerStatus::Terminating, Ordering::Relaxed);
+                            let _ = failure_clone.send((id_thread.clone(), err));
                         }
                     };
                 };
 
-                let handle = self.thread_pool.evaluate(future);
-                if handle.try_await_complete().is_err() {
-                    tracing::error!("Thread panicked in worker {:?}", self.id.clone());
+                let (completed_sender, completed_receiver) = bounded::<()>(1);
+                let task = move || {
+                    future();
+                    let _ = completed_sender.send(());
+                };
+
+                let handle = self.thread_pool.evaluate(task);
+                if let Some(timeout) = self.handle_timeout {
+                    if completed_receiver.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                        // The call cannot be interrupted, the worker is abandoned to it.
+                        self.status.store(WorkerStatus::Terminating, Ordering::Relaxed);
+                        tracing::error!(node_id = %self.id, timeout = ?timeout, "Processor timed out, terminating worker");
+                        self.report(WorkerError {
+                            node_id: self.id.clone(),
+                            kind: WorkerErrorKind::Timeout,
+                            message: format!("Processor did not complete within {timeout:?}"),
+                            dropped: vec![],
+                        });
+                        self.terminated();
+                        continue;
+                    }
+                }
+                if let Err(payload) = handle.await_complete() {
+                    let message = panic_message(payload);
+                    tracing::error!(node_id = %self.id, panic = %message, "Thread panicked in worker");
+                    self.report(WorkerError {
+                        node_id: self.id.clone(),
+                        kind: WorkerErrorKind::Panic,
+                        message,
+                        dropped: vec![],
+                    });
                     self.status.store(WorkerStatus::Idle, Ordering::Relaxed);
                 }
+                if self.status.load(Ordering::Relaxed) == WorkerStatus::Terminating {
+                    self.terminated();
+                }
             } else {
-                thread::sleep(Duration::from_millis(100));
+                thread::sleep(self.poll_interval);
                 if self.running.load(Ordering::Relaxed) == GraphStatus::WaitingForDataToTerminate {
-                    debug!("Sending done {}", self.id);
+                    tracing::debug!(node_id = %self.id, "Sending done");
                     let _ = self.done_notification.send(self.id.clone());
                 }
             }
         }
-        tracing::info!("Worker {} exited", self.id);
+        tracing::info!(node_id = %self.id, "Worker exited");
+    }
+
+    /// Ends the node once its worker is terminating: a source is marked as ended,
+    /// and the packet sets left in the work queue of other nodes are dropped and reported.
+    fn terminated(&mut self) {
+        self.end_source();
+        let work_queue = match self.work_queue.as_mut() {
+            Some(work_queue) => work_queue,
+            None => return,
+        };
+        let mut sets = 0;
+        let mut dropped = vec![];
+        while let Ok(event) = work_queue.get(Some(Duration::ZERO)) {
+            sets += 1;
+            dropped.extend(event.packet_data.versions());
+        }
+        if sets == 0 {
+            return;
+        }
+        tracing::warn!(node_id = %self.id, sets, dropped = dropped.len(), "Dropping queued packets");
+        if let Some(error_notification) = &self.error_notification {
+            let _ = error_notification.send(WorkerError {
+                node_id: self.id.clone(),
+                kind: WorkerErrorKind::DroppedPackets,
+                message: format!(
+                    "Dropped {} packets of {} sets left in the work queue",
+                    dropped.len(),
+                    sets
+                ),
+                dropped,
+            });
+        }
+    }
+
+    /// Marks the node as ended if it is a source.
+    fn end_source(&self) {
+        if let Some(sources) = self.sources.as_ref() {
+            sources.end(&self.id, &self.running);
+        }
+    }
+
+    /// Sends a panic or a timeout of the processor to the error channels.
+    fn report(&self, error: WorkerError) {
+        let _ = self.failure_notification.send((
+            error.node_id.clone(),
+            RustedPipeError::ProcessorError(error.message.clone()),
+        ));
+        if let Some(error_notification) = &self.error_notification {
+            let _ = error_notification.send(error);
+        }
+    }
+
+    /// Waits up to the poll interval for the clock of a source to tick. Each tick
+    /// is used once, so true is returned once per tick, or always without a clock.
+    fn wait_for_tick(&mut self) -> bool {
+        let clock = match self.clock.as_ref() {
+            Some(clock) => clock,
+            None => return true,
+        };
+        let ticked = clock.wait_past(self.clock_seen, self.poll_interval);
+        if ticked {
+            self.clock_seen += 1;
+        }
+        ticked
+    }
+
+    /// How long to wait before calling the processor again without exceeding its rate limit.
+    fn rate_limit_wait(&self) -> Option<Duration> {
+        let next_call = self.last_call? + self.min_interval?;
+        next_call
+            .checked_duration_since(Instant::now())
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// True if a queue downstream of the output channels is above its high water mark.
+    fn is_congested(&self) -> bool {
+        self.shared_writer.as_ref().is_some_and(|writer| {
+            writer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .writer
+                .is_congested()
+        })
     }
 }
@@ -0,0 +1,257 @@
+//! A runtime stepping the nodes of a graph one after the other in the calling
+//! thread, without sleeps or timeouts, to test the behavior of whole graphs
+//! reproducibly.
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::channels::read_channel::{ChannelBuffer, InputGenerator, ReadChannel};
+use crate::channels::typed_write_channel::{TypedWriteChannel, WriteChannel1};
+use crate::channels::{ChannelError, WriteChannelTrait};
+use crate::packet::work_queue::WorkQueue;
+use crate::RustedPipeError;
+
+use super::processor::{Node, Processors, SourceNode, SourceProcessor, TerminalNode};
+
+/// A node with inputs, drained by the runtime.
+trait Consumer {
+    fn id(&self) -> &str;
+
+    /// Reads all the packets waiting in the input channels and processes the sets
+    /// assembled from them.
+    ///
+    /// * Returns
+    /// True if a packet was read or a set was processed.
+    fn drain(&mut self) -> Result<bool, RustedPipeError>;
+}
+
+struct Source<OUTPUT: WriteChannelTrait + 'static> {
+    id: String,
+    handler: Box<dyn SourceProcessor<OUTPUT = OUTPUT>>,
+    write_channel: Mutex<TypedWriteChannel<OUTPUT>>,
+    ended: bool,
+}
+
+impl<OUTPUT: WriteChannelTrait + 'static> Source<OUTPUT> {
+    /// Calls the processor once, unless it reached the end of its stream.
+    fn produce(&mut self) -> Result<(), RustedPipeError> {
+        if self.ended {
+            return Ok(());
+        }
+        let write_channel = self
+            .write_channel
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match self.handler.handle(write_channel) {
+            Err(RustedPipeError::EndOfStream()) => {
+                tracing::info!(node_id = %self.id, "End of stream");
+                self.ended = true;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+struct Worker<INPUT, OUTPUT>
+where
+    INPUT: InputGenerator + ChannelBuffer + Send + 'static,
+    OUTPUT: WriteChannelTrait + 'static,
+{
+    id: String,
+    read_channel: ReadChannel<INPUT>,
+    work_queue: WorkQueue<INPUT::INPUT>,
+    processor: Processors<INPUT, OUTPUT>,
+    write_channel: Option<Mutex<TypedWriteChannel<OUTPUT>>>,
+}
+
+impl<INPUT, OUTPUT> Worker<INPUT, OUTPUT>
+where
+    INPUT: InputGenerator + ChannelBuffer + Send + 'static,
+    OUTPUT: WriteChannelTrait + 'static,
+{
+    /// Moves one packet from the input channels to the buffers.
+    ///
+    /// * Returns
+    /// False if no packet is waiting.
+    fn receive(&mut self) -> Result<bool, ChannelError> {
+        let mut channels = self
+            .read_channel
+            .channels
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !channels.wait_for_data(Duration::ZERO)? {
+            return Ok(false);
+        }
+        match channels.try_receive(Duration::ZERO) {
+            Ok(_) => Ok(true),
+            // The sender of the channel was dropped, nothing else will arrive.
+            Err(ChannelError::ReceiveError(_) | ChannelError::Disconnected(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn handle(&mut self, set: INPUT::INPUT) -> Result<(), RustedPipeError> {
+        match &mut self.processor {
+            Processors::Processor(proc) => {
+                let write_channel = self
+                    .write_channel
+                    .as_ref()
+                    .expect("Processor node was added without write channel");
+                let result = proc.handle(
+                    set,
+                    write_channel.lock().unwrap_or_else(PoisonError::into_inner),
+                );
+                let mut output = write_channel.lock().unwrap_or_else(PoisonError::into_inner);
+                match result {
+                    Ok(()) => output.writer.commit().map_err(RustedPipeError::from),
+                    Err(err) => {
+                        output.writer.rollback();
+                        Err(err)
+                    }
+                }
+            }
+            Processors::TerminalProcessor(proc) => proc.handle(set),
+            Processors::SourceProcessor(_) => unreachable!("Sources have no input"),
+        }
+    }
+}
+
+impl<INPUT, OUTPUT> Consumer for Worker<INPUT, OUTPUT>
+where
+    INPUT: InputGenerator + ChannelBuffer + Send + 'static,
+    OUTPUT: WriteChannelTrait + 'static,
+{
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn drain(&mut self) -> Result<bool, RustedPipeError> {
+        let mut progress = false;
+        while self.receive()? {
+            progress = true;
+            self.read_channel.synchronize();
+        }
+        while let Ok(event) = self.work_queue.get(Some(Duration::ZERO)) {
+            progress = true;
+            self.handle(event.packet_data)?;
+        }
+        Ok(progress)
+    }
+}
+
+/// Runs the nodes of a graph in the calling thread. Each `step` calls every source
+/// once, then drains the other nodes in the order they were added until none of
+/// them has data left. Adding the nodes in topological order, the data of a step
+/// flows through the whole graph in a single pass.
+///
+/// Unlike `Graph`, nothing depends on the timing of threads: rate limits, replay
+/// speeds, clocks and handle timeouts of the processors are ignored.
+#[derive(Default)]
+pub struct SingleThreadRuntime {
+    ids: HashSet<String>,
+    sources: Vec<Box<dyn FnMut() -> Result<bool, RustedPipeError>>>,
+    consumers: Vec<Box<dyn Consumer>>,
+}
+
+impl SingleThreadRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_id(&mut self, id: &str) {
+        if !self.ids.insert(id.to_string()) {
+            panic!("Node {id} already exists");
+        }
+    }
+
+    pub fn add_source_node<OUTPUT: WriteChannelTrait + 'static>(
+        &mut self,
+        node: SourceNode<OUTPUT>,
+    ) {
+        self.add_id(&node.id);
+        let mut source = Source {
+            id: node.id,
+            handler: node.handler,
+            write_channel: Mutex::new(node.write_channel),
+            ended: false,
+        };
+        self.sources.push(Box::new(move || {
+            source
+                .produce()
+                .map_err(|err| RustedPipeError::WorkerErrors(vec![(source.id.clone(), err)]))?;
+            Ok(!source.ended)
+        }));
+    }
+
+    pub fn add_node<INPUT, OUTPUT>(&mut self, node: Node<INPUT, OUTPUT>)
+    where
+        INPUT: InputGenerator + ChannelBuffer + Send + 'static,
+        OUTPUT: WriteChannelTrait + 'static,
+    {
+        self.add_id(&node.id);
+        let mut write_channel = node.write_channel;
+        if node.handler.transactional_writes() {
+            write_channel.writer.set_transactional();
+        }
+        self.consumers.push(Box::new(Worker {
+            id: node.id,
+            read_channel: node.read_channel,
+            work_queue: node.work_queue,
+            processor: Processors::Processor(node.handler),
+            write_channel: Some(Mutex::new(write_channel)),
+        }));
+    }
+
+    pub fn add_terminal_node<INPUT>(&mut self, node: TerminalNode<INPUT>)
+    where
+        INPUT: InputGenerator + ChannelBuffer + Send + 'static,
+    {
+        self.add_id(&node.id);
+        self.consumers
+            .push(Box::new(Worker::<INPUT, WriteChannel1<String>> {
+                id: node.id,
+                read_channel: node.read_channel,
+                work_queue: node.work_queue,
+                processor: Processors::TerminalProcessor(node.handler),
+                write_channel: None,
+            }));
+    }
+
+    /// Calls each source that did not reach the end of its stream once, then drains
+    /// the other nodes.
+    ///
+    /// * Returns
+    /// True if a source did not reach the end of its stream yet, or a
+    /// `RustedPipeError::WorkerErrors` with the first processor failing.
+    pub fn step(&mut self) -> Result<bool, RustedPipeError> {
+        let mut active = false;
+        for source in self.sources.iter_mut() {
+            active |= source()?;
+        }
+        self.drain()?;
+        Ok(active)
+    }
+
+    /// Steps until all the sources reached the end of their stream.
+    pub fn run_until_complete(&mut self) -> Result<(), RustedPipeError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Drains the nodes with inputs, in the order they were added, until none of them
+    /// has data left.
+    fn drain(&mut self) -> Result<(), RustedPipeError> {
+        loop {
+            let mut progress = false;
+            for consumer in self.consumers.iter_mut() {
+                progress |= consumer.drain().map_err(|err| {
+                    RustedPipeError::WorkerErrors(vec![(consumer.id().to_string(), err)])
+                })?;
+            }
+            if !progress {
+                return Ok(());
+            }
+        }
+    }
+}
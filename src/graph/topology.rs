@@ -0,0 +1,209 @@
+//! The wiring of the nodes of a graph, derived from the channels linked between them.
+//! It is used to refuse the nodes closing a cycle and to render the graph.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::channels::ChannelID;
+
+/// A channel carrying data from the node `from` to the node `to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub channel: ChannelID,
+}
+
+/// The kind of processor run by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Source,
+    Processor,
+    Terminal,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Topology {
+    nodes: BTreeMap<String, NodeKind>,
+    edges: Vec<Edge>,
+}
+
+fn escape(id: &str) -> String {
+    id.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", escape(id))
+}
+
+impl Topology {
+    pub fn add_node(&mut self, id: &str, kind: NodeKind) {
+        self.nodes.insert(id.to_string(), kind);
+    }
+
+    pub fn add_edge(&mut self, from: &str, to: &str, channel: ChannelID) {
+        self.edges.push(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            channel,
+        });
+    }
+
+    /// Links `to` to `from` through its input `channel`, in place of the edge
+    /// reaching that input, if any.
+    pub fn relink(&mut self, from: &str, to: &str, channel: ChannelID) {
+        self.edges
+            .retain(|edge| edge.to != to || edge.channel != channel);
+        self.add_edge(from, to, channel);
+    }
+
+    /// Removes the node and the channels connected to it.
+    pub fn remove_node(&mut self, id: &str) {
+        self.nodes.remove(id);
+        self.edges.retain(|edge| edge.from != id && edge.to != id);
+    }
+
+    /// The nodes and the kind of their processor, sorted by id.
+    pub fn nodes(&self) -> impl Iterator<Item = (&str, NodeKind)> {
+        self.nodes.iter().map(|(id, kind)| (id.as_str(), *kind))
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Renders the nodes, labeled with their id and kind, and the channels
+    /// connecting them in the Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph {\n".to_string();
+        for (id, kind) in self.nodes.iter() {
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\\n{kind:?}\"];",
+                quote(id),
+                escape(id)
+            );
+        }
+        for edge in self.edges.iter() {
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label={}];",
+                quote(&edge.from),
+                quote(&edge.to),
+                quote(edge.channel.id())
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the channels forming a cycle, in the order data flows through them,
+    /// or None if the nodes form a directed acyclic graph.
+    pub fn find_cycle(&self) -> Option<Vec<ChannelID>> {
+        let nodes: BTreeSet<&str> = self.edges.iter().map(|edge| edge.from.as_str()).collect();
+        let mut visits = HashMap::new();
+        let mut path = vec![];
+        nodes.into_iter().find_map(|node| {
+            if visits.contains_key(node) {
+                return None;
+            }
+            self.visit(node, &mut visits, &mut path)
+        })
+    }
+
+    fn visit<'a>(
+        &'a self,
+        node: &'a str,
+        visits: &mut HashMap<&'a str, Visit>,
+        path: &mut Vec<&'a Edge>,
+    ) -> Option<Vec<ChannelID>> {
+        visits.insert(node, Visit::InProgress);
+        for edge in self.edges.iter().filter(|edge| edge.from == node) {
+            match visits.get(edge.to.as_str()) {
+                Some(Visit::Done) => {}
+                Some(Visit::InProgress) => {
+                    path.push(edge);
+                    let start = path
+                        .iter()
+                        .position(|on_path| on_path.from == edge.to)
+                        .expect("Node in progress must be on the path");
+                    return Some(path[start..].iter().map(|e| e.channel.clone()).collect());
+                }
+                None => {
+                    path.push(edge);
+                    if let Some(cycle) = self.visit(&edge.to, visits, path) {
+                        return Some(cycle);
+                    }
+                    path.pop();
+                }
+            }
+        }
+        visits.insert(node, Visit::Done);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acyclic_topology_has_no_cycle() {
+        let mut topology = Topology::default();
+        topology.add_edge("source", "processor", ChannelID::from("a"));
+        topology.add_edge("source", "terminal", ChannelID::from("b"));
+        topology.add_edge("processor", "terminal", ChannelID::from("c"));
+        assert_eq!(topology.find_cycle(), None);
+    }
+
+    #[test]
+    fn test_cycle_is_reported_in_data_flow_order() {
+        let mut topology = Topology::default();
+        topology.add_edge("source", "p1", ChannelID::from("a"));
+        topology.add_edge("p1", "p2", ChannelID::from("b"));
+        topology.add_edge("p2", "p3", ChannelID::from("c"));
+        topology.add_edge("p3", "p1", ChannelID::from("d"));
+        assert_eq!(
+            topology.find_cycle(),
+            Some(vec![
+                ChannelID::from("b"),
+                ChannelID::from("c"),
+                ChannelID::from("d")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_topology_is_rendered_as_dot() {
+        let mut topology = Topology::default();
+        topology.add_node("source", NodeKind::Source);
+        topology.add_node("terminal", NodeKind::Terminal);
+        topology.add_edge("source", "terminal", ChannelID::from("frames"));
+        assert_eq!(
+            topology.to_dot(),
+            "digraph {\n    \"source\" [label=\"source\\nSource\"];\n    \"terminal\" [label=\"terminal\\nTerminal\"];\n    \"source\" -> \"terminal\" [label=\"frames\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_relink_replaces_the_edge_to_the_input() {
+        let mut topology = Topology::default();
+        topology.add_edge("p1", "p2", ChannelID::from("a"));
+        topology.add_edge("p2", "p1", ChannelID::from("b"));
+        topology.relink("p3", "p1", ChannelID::from("b"));
+        assert_eq!(topology.find_cycle(), None);
+        assert_eq!(topology.edges().len(), 2);
+    }
+
+    #[test]
+    fn test_self_loop_is_a_cycle() {
+        let mut topology = Topology::default();
+        topology.add_edge("p1", "p1", ChannelID::from("a"));
+        assert_eq!(topology.find_cycle(), Some(vec![ChannelID::from("a")]));
+    }
+}
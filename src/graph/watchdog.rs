@@ -0,0 +1,114 @@
+//! Watchdog detecting graphs that stopped making progress while data is still
+//! waiting in the buffers, for instance because a synchronization set never assembles.
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use atomic::{Atomic, Ordering};
+
+use crate::channels::read_channel::ChannelBuffer;
+use crate::channels::ChannelID;
+
+use super::build::{GraphStatus, WorkerStatus};
+
+/// Called with the channels holding data when the graph is stuck.
+pub type StallCallback = Arc<dyn Fn(&[StalledChannel]) + Send + Sync>;
+
+/// Configuration of the watchdog started with `Graph::start_watchdog`.
+#[derive(Clone)]
+pub struct WatchdogConfig {
+    /// How long no worker may run while the buffers hold the same data
+    /// before the graph is reported as stuck.
+    pub stall_timeout: Duration,
+    /// Optional callback invoked once per stall, after logging.
+    pub on_stall: Option<StallCallback>,
+}
+
+/// A channel holding data while the graph is stuck.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StalledChannel {
+    pub node_id: String,
+    pub channel_id: ChannelID,
+    /// Number of packets waiting in the buffer of the channel.
+    pub occupancy: usize,
+}
+
+/// Reads the occupancy of the buffers of a node from another thread.
+pub(super) trait BufferProbe: Send + Sync {
+    fn occupancy(&self) -> HashMap<ChannelID, usize>;
+}
+
+pub(super) struct ChannelsProbe<T>(pub Arc<RwLock<T>>);
+
+impl<T: ChannelBuffer + Send + Sync> BufferProbe for ChannelsProbe<T> {
+    fn occupancy(&self) -> HashMap<ChannelID, usize> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .occupancy()
+    }
+}
+
+fn stalled_channels(probes: &HashMap<String, Arc<dyn BufferProbe>>) -> Vec<StalledChannel> {
+    let mut stalled: Vec<_> = probes
+        .iter()
+        .flat_map(|(node_id, probe)| {
+            probe
+                .occupancy()
+                .into_iter()
+                .filter(|(_, occupancy)| *occupancy > 0)
+                .map(|(channel_id, occupancy)| StalledChannel {
+                    node_id: node_id.clone(),
+                    channel_id,
+                    occupancy,
+                })
+        })
+        .collect();
+    stalled.sort();
+    stalled
+}
+
+pub(super) fn watch(
+    running: Arc<Atomic<GraphStatus>>,
+    workers: Vec<Arc<Atomic<WorkerStatus>>>,
+    probes: HashMap<String, Arc<dyn BufferProbe>>,
+    config: WatchdogConfig,
+    poll_interval: Duration,
+) {
+    let mut last_stalled = vec![];
+    let mut stalled_since = Instant::now();
+    let mut reported = false;
+    while running.load(Ordering::Relaxed) != GraphStatus::Terminating {
+        thread::sleep(poll_interval);
+
+        let stalled = stalled_channels(&probes);
+        let progressing = running.load(Ordering::Relaxed) == GraphStatus::Paused
+            || workers
+                .iter()
+                .any(|status| status.load(Ordering::Relaxed) == WorkerStatus::Running);
+        if progressing || stalled.is_empty() || stalled != last_stalled {
+            last_stalled = stalled;
+            stalled_since = Instant::now();
+            reported = false;
+            continue;
+        }
+
+        if !reported && stalled_since.elapsed() >= config.stall_timeout {
+            for channel in stalled.iter() {
+                tracing::warn!(
+                    node_id = %channel.node_id,
+                    channel_id = %channel.channel_id,
+                    occupancy = channel.occupancy,
+                    "Graph is not progressing while channel holds data"
+                );
+            }
+            if let Some(on_stall) = config.on_stall.as_ref() {
+                on_stall(&stalled);
+            }
+            reported = true;
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub use packet::DataVersion;
 pub use packet::PacketError;
 
 use channels::ChannelError;
+use graph::build::GraphError;
 use thiserror::Error;
 
 /// Possible inference error
@@ -16,12 +17,16 @@ pub enum RustedPipeError {
     PacketError(#[from] PacketError),
     #[error(transparent)]
     ChannelError(#[from] ChannelError),
+    #[error(transparent)]
+    GraphError(#[from] GraphError),
     #[error("Cannot find node {0:?}, have you added the node to the graph?")]
     MissingNodeError(String),
     #[error("Error while executing processor: {0:?}")]
     ProcessorError(String),
     #[error("No more packets to send")]
     EndOfStream(),
+    #[error("Processors failed while running: {0:?}")]
+    WorkerErrors(Vec<(String, RustedPipeError)>),
 }
 
 #[macro_export]
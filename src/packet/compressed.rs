@@ -0,0 +1,105 @@
+//! Compressed packets, to send large payloads such as images over the network or
+//! to persist them. Only the data is compressed: the version stays readable so that
+//! buffers can order and deduplicate the packets without decompressing them.
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{DataVersion, Packet, PacketError};
+use std::collections::HashMap;
+
+/// Compresses packets with zstd.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketCompressor {
+    level: i32,
+}
+
+impl Default for PacketCompressor {
+    fn default() -> Self {
+        Self {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl PacketCompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the zstd compression level, from 1 (fastest) to 22 (smallest).
+    pub fn with_level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Serializes and compresses the data of the packet.
+    pub fn compress<T: Serialize>(
+        &self,
+        packet: &Packet<T>,
+    ) -> Result<CompressedPacket, PacketError> {
+        let data = bincode::serialize(&packet.data).map_err(compression_error)?;
+        let payload = zstd::encode_all(data.as_slice(), self.level).map_err(compression_error)?;
+        Ok(CompressedPacket {
+            version: packet.version,
+            end: packet.end,
+            gap: packet.gap,
+            metadata: packet.metadata.clone(),
+            payload,
+        })
+    }
+}
+
+/// A packet whose data is serialized and compressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedPacket {
+    pub version: DataVersion,
+    pub end: Option<DataVersion>,
+    pub gap: bool,
+    pub metadata: HashMap<String, String>,
+    payload: Vec<u8>,
+}
+
+impl CompressedPacket {
+    /// Decompresses and deserializes the data into a packet of type `T`.
+    pub fn decompress<T: DeserializeOwned>(&self) -> Result<Packet<T>, PacketError> {
+        let data = zstd::decode_all(self.payload.as_slice()).map_err(compression_error)?;
+        let data = bincode::deserialize(&data).map_err(compression_error)?;
+        Ok(Packet {
+            data,
+            version: self.version,
+            end: self.end,
+            gap: self.gap,
+            metadata: self.metadata.clone(),
+        })
+    }
+
+    /// Size of the compressed data in bytes.
+    pub fn compressed_len(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+fn compression_error(err: impl ToString) -> PacketError {
+    PacketError::CompressionError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_packet_round_trip() {
+        let image = vec![7_u8; 64 * 1024];
+        let packet =
+            Packet::new(image.clone(), DataVersion::new(10)).with_metadata("source", "camera");
+        let compressed = PacketCompressor::new()
+            .with_level(3)
+            .compress(&packet)
+            .unwrap();
+        assert!(compressed.compressed_len() < image.len());
+        assert_eq!(compressed.version, DataVersion::new(10));
+
+        let restored = compressed.decompress::<Vec<u8>>().unwrap();
+        assert_eq!(restored.data, image);
+        assert_eq!(restored.get_metadata("source"), Some("camera"));
+    }
+}
@@ -1,72 +1,345 @@
+#[cfg(feature = "compression")]
+pub mod compressed;
 pub mod typed;
 pub mod work_queue;
-use std::any::{Any, TypeId};
+use std::any::{type_name, Any};
+use std::collections::HashMap;
 use std::marker::Copy;
+use std::sync::Arc;
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use thiserror::Error;
 
 /// Possible inference error
 #[derive(Debug, Error, PartialEq, Clone)]
 pub enum PacketError {
-    #[error("Received data of unexpected type, was expecting {0:?}")]
-    UnexpectedDataType(TypeId),
-    #[error("Trying to use a channel which does not exist, channel id {0:?}")]
+    #[error("Received data of unexpected type {found}, was expecting {expected}")]
+    UnexpectedDataType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("Trying to use a channel which does not exist, channel id {0}")]
     MissingChannel(ChannelID),
     #[error("Trying to use a channel index which does not exist, channel index {0:?}")]
     MissingChannelIndex(usize),
     #[error("Channel has no data {0:?}")]
     MissingChannelData(usize),
+    #[error("Data of type {0} is shared by other packets and cannot be moved out")]
+    SharedData(&'static str),
+    #[error("Cannot compress or decompress packet: {0}")]
+    CompressionError(String),
 }
 
-#[derive(Debug, Copy, Clone, Eq, Ord, PartialOrd)]
+/// Version of a packet. Versions are ordered by timestamp first and by
+/// sequence number for packets sharing the same timestamp.
+/// Timestamps are always in nanoseconds, usually since the UNIX epoch.
+/// Struct literals can leave the sequence number to `..Default::default()`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct DataVersion {
+    /// Timestamp in nanoseconds.
     pub timestamp_ns: u128,
+    /// Optional tiebreaker between packets produced at the same timestamp, 0 if unused.
+    pub sequence: u64,
 }
 
 impl DataVersion {
-    pub fn from_now() -> Self {
+    /// Creates a version for a timestamp without sequence number.
+    pub fn new(timestamp_ns: u128) -> Self {
         DataVersion {
-            timestamp_ns: SystemTime::now()
+            timestamp_ns,
+            sequence: 0,
+        }
+    }
+
+    /// Sets the sequence number used to order versions with the same timestamp.
+    pub fn with_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn from_nanos(timestamp_ns: u128) -> Self {
+        DataVersion::new(timestamp_ns)
+    }
+
+    pub fn from_millis(timestamp_ms: u64) -> Self {
+        DataVersion::new(timestamp_ms as u128 * 1_000_000)
+    }
+
+    pub fn from_now() -> Self {
+        DataVersion::new(
+            SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .expect("Cannot calculate epoch")
                 .as_nanos(),
-        }
+        )
+    }
+
+    /// Creates a version for the time of `instant` since the UNIX epoch, measured
+    /// from the current system time.
+    pub fn from_instant(instant: Instant) -> Self {
+        let now = DataVersion::from_now().timestamp_ns;
+        let now_instant = Instant::now();
+        let timestamp_ns = if instant <= now_instant {
+            now.saturating_sub((now_instant - instant).as_nanos())
+        } else {
+            now + (instant - now_instant).as_nanos()
+        };
+        DataVersion::new(timestamp_ns)
+    }
+
+    /// Time elapsed between the timestamp of `earlier` and this one, zero if
+    /// `earlier` is more recent.
+    pub fn duration_since(&self, earlier: &DataVersion) -> Duration {
+        nanos_to_duration(self.timestamp_ns.saturating_sub(earlier.timestamp_ns))
+    }
+
+    /// Time between the timestamps of the two versions, whichever is the most recent.
+    pub fn abs_diff(&self, other: &DataVersion) -> Duration {
+        nanos_to_duration(self.timestamp_ns.abs_diff(other.timestamp_ns))
+    }
+
+    /// True if the timestamps of the two versions are at most `tolerance` apart.
+    pub fn is_within(&self, other: &DataVersion, tolerance: Duration) -> bool {
+        self.abs_diff(other) <= tolerance
+    }
+
+    /// True if the timestamp of this version is older than the one of `other`.
+    /// Unlike `<`, the sequence numbers are ignored.
+    pub fn is_before(&self, other: &DataVersion) -> bool {
+        self.timestamp_ns < other.timestamp_ns
+    }
+
+    /// True if the timestamp of this version is more recent than the one of `other`.
+    /// Unlike `>`, the sequence numbers are ignored.
+    pub fn is_after(&self, other: &DataVersion) -> bool {
+        self.timestamp_ns > other.timestamp_ns
     }
 }
 
-impl PartialEq for DataVersion {
-    fn eq(&self, other: &Self) -> bool {
-        self.timestamp_ns == other.timestamp_ns
+/// Prints the timestamp in the most readable unit, followed by the sequence
+/// number if there is one, e.g. `1.5s` or `250ms#2`.
+impl std::fmt::Display for DataVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", nanos_to_duration(self.timestamp_ns))?;
+        if self.sequence != 0 {
+            write!(f, "#{}", self.sequence)?;
+        }
+        Ok(())
     }
 }
-#[derive(Debug, Copy, Clone)]
+
+fn nanos_to_duration(nanos: u128) -> Duration {
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+/// Typed packets can be serialized if their data can.
+/// Untyped packets cannot since their data type is unknown.
+#[derive(Debug, Clone)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Packet<T> {
     pub data: T,
     pub version: DataVersion,
+    /// For multi version packets, the last version the data is valid for, see
+    /// `Packet::multi_version`.
+    pub end: Option<DataVersion>,
+    /// True for the packets created with `Packet::gap`, which carry no data.
+    pub gap: bool,
+    /// Provenance of the packet, e.g. the source node or a trace id. It is kept
+    /// when the packet is untyped and cast back.
+    pub metadata: HashMap<String, String>,
+}
+
+/// Data of any type, which remembers the name of its type for error messages.
+pub trait UntypedData: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn type_name(&self) -> &'static str;
 }
 
-pub type Untyped = dyn Any;
-pub type UntypedPacket = Packet<Box<Untyped>>;
+impl<T: Any> UntypedData for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+}
+
+pub type Untyped = dyn UntypedData;
+/// A packet with dynamically typed data. The data is reference counted, so cloning
+/// the packet, for instance to fan it out to multiple buffers, does not copy it.
+pub type UntypedPacket = Packet<Arc<Untyped>>;
 
 pub trait UntypedPacketCast: 'static {
+    /// Casts the packet and moves its data out.
+    ///
+    /// * Returns
+    /// `PacketError::UnexpectedDataType` if the data is not a `T`, or
+    /// `PacketError::SharedData` if clones of the packet are still alive.
     fn deref_owned<T: 'static>(self) -> Result<Packet<Box<T>>, PacketError>;
+    /// Casts the packet and moves its data out, copying it only if clones of
+    /// the packet are still alive.
+    fn deref_cloned<T: Clone + 'static>(self) -> Result<Packet<T>, PacketError>;
+    /// Casts a packet created with `Packet::from_arc` back to its shared data.
+    fn deref_arc<T: 'static>(self) -> Result<Packet<Arc<T>>, PacketError>;
+}
+
+fn downcast_arc<T: 'static>(data: Arc<Untyped>) -> Result<Arc<T>, PacketError> {
+    // Deref first, the Arc itself also implements UntypedData.
+    if !(*data).as_any().is::<T>() {
+        return Err(PacketError::UnexpectedDataType {
+            expected: type_name::<T>(),
+            found: (*data).type_name(),
+        });
+    }
+    // Safety: the data was checked to be a `T`, like `Rc::downcast` does.
+    Ok(unsafe { Arc::from_raw(Arc::into_raw(data) as *const T) })
+}
+
+impl UntypedPacketCast for UntypedPacket {
+    fn deref_owned<T: 'static>(self) -> Result<Packet<Box<T>>, PacketError> {
+        match Arc::try_unwrap(downcast_arc::<T>(self.data)?) {
+            Ok(data) => Ok(Packet {
+                data: Box::new(data),
+                version: self.version,
+                end: self.end,
+                gap: self.gap,
+                metadata: self.metadata,
+            }),
+            Err(_) => Err(PacketError::SharedData(type_name::<T>())),
+        }
+    }
+
+    fn deref_cloned<T: Clone + 'static>(self) -> Result<Packet<T>, PacketError> {
+        let data = downcast_arc::<T>(self.data)?;
+        let data = Arc::try_unwrap(data).unwrap_or_else(|shared| (*shared).clone());
+        Ok(Packet {
+            data,
+            version: self.version,
+            end: self.end,
+            gap: self.gap,
+            metadata: self.metadata,
+        })
+    }
+
+    fn deref_arc<T: 'static>(self) -> Result<Packet<Arc<T>>, PacketError> {
+        let data = downcast_arc::<Arc<T>>(self.data)?;
+        Ok(Packet {
+            data: (*data).clone(),
+            version: self.version,
+            end: self.end,
+            gap: self.gap,
+            metadata: self.metadata,
+        })
+    }
+}
+
+impl<T: 'static> Packet<Arc<T>> {
+    /// Creates a packet sharing its data. Cloning the packet, for instance when
+    /// writing it to multiple channels, only clones the reference and not the data.
+    pub fn from_arc(data: Arc<T>, version: DataVersion) -> Self {
+        Packet::new(data, version)
+    }
+}
+
+impl<T: Clone> Packet<T> {
+    /// Clones the packet without untyping it, for instance to write it to
+    /// multiple buffers. Data shared with `Packet::from_arc` is not copied.
+    pub fn clone_shallow(&self) -> Packet<T> {
+        self.clone()
+    }
+}
+
+impl<T> Packet<T> {
+    pub fn new(data: T, version: DataVersion) -> Self {
+        Packet::<T> {
+            data,
+            version,
+            end: None,
+            gap: false,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Creates a packet valid for all the versions from `start` to `end` included,
+    /// e.g. a segment of a trajectory that can be interpolated at any time. Buffers
+    /// indexed by version, like `FixedSizeBTree`, match it for any version in the range.
+    ///
+    /// Panics if `end` is older than `start`.
+    pub fn multi_version(data: T, start: DataVersion, end: DataVersion) -> Self {
+        if end < start {
+            panic!("Packet range ends at {end:?} before its start {start:?}");
+        }
+        Packet {
+            end: Some(end),
+            ..Packet::new(data, start)
+        }
+    }
+
+    /// True if the packet has `version` or, for multi version packets, if `version`
+    /// is within its range.
+    pub fn covers(&self, version: &DataVersion) -> bool {
+        match self.end {
+            Some(end) => self.version <= *version && *version <= end,
+            None => self.version == *version,
+        }
+    }
+
+    /// Adds the entry `key` to the metadata of the packet, replacing any previous value.
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get_metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Converts the data of the packet, keeping its versions and metadata.
+    pub fn map<O>(self, f: impl FnOnce(T) -> O) -> Packet<O> {
+        Packet {
+            data: f(self.data),
+            version: self.version,
+            end: self.end,
+            gap: self.gap,
+            metadata: self.metadata,
+        }
+    }
+
+    /// Creates a packet signaling that there is no data at `version`, for instance
+    /// because a sensor dropped a frame. It advances the buffers like any packet, so
+    /// the synchronizers can match the other channels, but it is never handed to a
+    /// processor: its channel has no data in the matched set. `placeholder` fills the
+    /// data of the packet and is never read.
+    pub fn gap(placeholder: T, version: DataVersion) -> Self {
+        Packet {
+            gap: true,
+            ..Packet::new(placeholder, version)
+        }
+    }
 }
 
 impl<T: 'static> Packet<T> {
     pub fn to_untyped(self) -> UntypedPacket {
         UntypedPacket {
-            data: Box::new(self.data) as Box<Untyped>,
+            data: Arc::new(self.data) as Arc<Untyped>,
             version: self.version,
+            end: self.end,
+            gap: self.gap,
+            metadata: self.metadata,
         }
     }
-
-    pub fn new(data: T, version: DataVersion) -> Self {
-        Packet::<T> { data, version }
-    }
 }
 
+/// Separates the segments of hierarchical channel ids, e.g. `camera/front/image`.
+pub const CHANNEL_SEPARATOR: char = '/';
+
+/// Id of a channel. Ids are compared, hashed and ordered by their name.
 #[derive(Eq, Hash, Debug, Clone, PartialEq, PartialOrd, Ord)]
 pub struct ChannelID {
     pub id: String,
@@ -78,9 +351,22 @@ impl std::fmt::Display for ChannelID {
     }
 }
 
+/// Channel ids are serialized as their name.
+impl serde::Serialize for ChannelID {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.id)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ChannelID {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer).map(ChannelID::new)
+    }
+}
+
 impl From<&&ChannelID> for ChannelID {
     fn from(f: &&Self) -> Self {
-        Self { id: f.id.clone() }
+        (*f).clone()
     }
 }
 
@@ -94,16 +380,186 @@ impl ChannelID {
     pub fn new(id: String) -> Self {
         ChannelID { id }
     }
+
+    /// The name of the channel.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The id of a channel nested in this one, e.g. `camera/front` is the child
+    /// `front` of `camera`.
+    pub fn child(&self, segment: &str) -> ChannelID {
+        ChannelID::new(format!("{}{CHANNEL_SEPARATOR}{segment}", self.id))
+    }
+
+    /// The id this one is nested in, None for a top level channel.
+    pub fn parent(&self) -> Option<ChannelID> {
+        self.id
+            .rsplit_once(CHANNEL_SEPARATOR)
+            .map(|(parent, _)| ChannelID::from(parent))
+    }
+
+    /// The segments of the id, from the outermost one.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.id.split(CHANNEL_SEPARATOR)
+    }
+
+    /// True if the id is `prefix` or is nested in it. Segments are compared
+    /// whole, so `camera/front` starts with `camera` but not with `cam`.
+    pub fn starts_with(&self, prefix: &ChannelID) -> bool {
+        let mut segments = self.segments();
+        prefix
+            .segments()
+            .all(|segment| segments.next() == Some(segment))
+    }
 }
 
 impl From<&str> for ChannelID {
     fn from(id: &str) -> Self {
-        ChannelID { id: id.to_string() }
+        ChannelID::new(id.to_string())
     }
 }
 
 impl From<String> for ChannelID {
     fn from(id: String) -> Self {
-        ChannelID { id }
+        ChannelID::new(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arc_packet_shares_data_across_clones() {
+        let data = Arc::new(vec![0_u8; 16]);
+        let packet = Packet::from_arc(data.clone(), DataVersion::new(1));
+        let fan_out = packet.clone();
+        assert!(Arc::ptr_eq(&packet.data, &fan_out.data));
+
+        let shared = fan_out.to_untyped().deref_arc::<Vec<u8>>().unwrap();
+        assert!(Arc::ptr_eq(&shared.data, &data));
+        assert_eq!(shared.version, DataVersion::new(1));
+        assert!(packet.to_untyped().deref_arc::<String>().is_err());
+    }
+
+    #[test]
+    fn test_untyped_packet_clone_shares_data() {
+        let packet = Packet::new("TestData".to_string(), DataVersion::new(1)).to_untyped();
+        let fan_out = packet.clone();
+        assert!(Arc::ptr_eq(&packet.data, &fan_out.data));
+
+        assert_eq!(
+            fan_out.deref_owned::<String>().unwrap_err(),
+            PacketError::SharedData(type_name::<String>())
+        );
+        let fan_out = packet.clone().deref_cloned::<String>().unwrap();
+        assert_eq!(fan_out.data, "TestData".to_string());
+        assert_eq!(*packet.deref_owned::<String>().unwrap().data, "TestData".to_string());
+    }
+
+    #[test]
+    fn test_metadata_survives_untyped_round_trip() {
+        let packet = Packet::new("TestData".to_string(), DataVersion::new(1))
+            .with_metadata("trace_id", "abc")
+            .with_metadata("source", "camera");
+        let packet = packet.to_untyped().deref_owned::<String>().unwrap();
+        assert_eq!(packet.get_metadata("trace_id"), Some("abc"));
+        assert_eq!(packet.get_metadata("source"), Some("camera"));
+        assert_eq!(packet.get_metadata("frame"), None);
+    }
+
+    #[test]
+    fn test_packet_serialization_round_trip() {
+        let packet = Packet::new(vec![1_u32, 2, 3], DataVersion::new(10).with_sequence(2))
+            .with_metadata("trace_id", "abc");
+        let bytes = bincode::serialize(&packet).unwrap();
+        let restored: Packet<Vec<u32>> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.data, packet.data);
+        assert_eq!(restored.version, packet.version);
+        assert_eq!(restored.metadata, packet.metadata);
+
+        let channel = ChannelID::from("frames");
+        let bytes = bincode::serialize(&channel).unwrap();
+        assert_eq!(bincode::deserialize::<ChannelID>(&bytes).unwrap(), channel);
+    }
+
+    #[test]
+    fn test_data_version_durations_are_in_nanoseconds() {
+        let first = DataVersion::from_millis(1_000);
+        let second = DataVersion::from_nanos(1_250_000_000).with_sequence(3);
+        assert_eq!(second.duration_since(&first), Duration::from_millis(250));
+        assert_eq!(first.duration_since(&second), Duration::ZERO);
+        assert_eq!(first.abs_diff(&second), Duration::from_millis(250));
+        assert!(first.is_within(&second, Duration::from_millis(250)));
+        assert!(!first.is_within(&second, Duration::from_millis(249)));
+        assert!(first.is_before(&second));
+        assert!(!second.with_sequence(0).is_after(&second));
+
+        let instant = Instant::now();
+        let version = DataVersion::from_instant(instant - Duration::from_secs(1));
+        assert!(DataVersion::from_now().duration_since(&version) >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_data_version_orders_by_timestamp_then_sequence() {
+        let first = DataVersion::new(1).with_sequence(0);
+        let second = DataVersion::new(1).with_sequence(1);
+        let third = DataVersion::new(2);
+        assert!(first < second);
+        assert!(second < third);
+        assert_ne!(first, second);
+        assert_eq!(DataVersion::new(1), first);
+        assert_eq!(
+            DataVersion {
+                timestamp_ns: 1,
+                ..Default::default()
+            },
+            first
+        );
+    }
+
+    #[test]
+    fn test_data_version_is_displayed_in_readable_unit() {
+        assert_eq!(DataVersion::from_millis(1500).to_string(), "1.5s");
+        assert_eq!(
+            DataVersion::new(250).with_sequence(2).to_string(),
+            "250ns#2"
+        );
+        assert_eq!(ChannelID::from("camera").to_string(), "camera");
+    }
+
+    #[test]
+    fn test_channel_id_is_compared_by_name() {
+        let first = ChannelID::from("channel");
+        let second = ChannelID::new("channel".to_string());
+        let other = ChannelID::from("other_channel");
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(first.id(), "channel");
+        assert_eq!(first.id, "channel".to_string());
+    }
+
+    #[test]
+    fn test_channel_id_is_ordered_by_name() {
+        let channels = ["c3", "c1", "c2"].map(ChannelID::from);
+        let mut sorted = channels.to_vec();
+        sorted.sort();
+        assert_eq!(sorted.iter().map(|c| c.id()).collect::<Vec<_>>(), ["c1", "c2", "c3"]);
+    }
+
+    #[test]
+    fn test_channel_id_hierarchy() {
+        let camera = ChannelID::from("camera");
+        let image = camera.child("front").child("image");
+        assert_eq!(image, ChannelID::from("camera/front/image"));
+        assert_eq!(image.segments().collect::<Vec<_>>(), ["camera", "front", "image"]);
+        assert_eq!(image.parent(), Some(ChannelID::from("camera/front")));
+        assert_eq!(camera.parent(), None);
+
+        assert!(image.starts_with(&camera));
+        assert!(image.starts_with(&image));
+        assert!(!image.starts_with(&ChannelID::from("cam")));
+        assert!(!camera.starts_with(&image));
     }
 }
@@ -1,11 +1,24 @@
-use crate::packet::Packet;
+use crate::packet::{DataVersion, Packet};
 use paste::item;
 
-pub trait PacketSetTrait {}
+/// A set of packets matched by a ReadChannel. Sets must be `Clone`: with a
+/// `RestartPolicy` retrying the processor, the worker keeps a copy of the set
+/// to hand it again to the processor after an error.
+pub trait PacketSetTrait: Clone {
+    /// Fills the channels without data with the packets of `previous`.
+    /// Does nothing by default.
+    fn hold(&mut self, _previous: &Self) {}
+    /// The version of each packet in the set. Empty by default, for sets which
+    /// cannot tell.
+    fn versions(&self) -> Vec<DataVersion> {
+        vec![]
+    }
+}
 
 macro_rules! typed_packet {
     ($struct_name:ident, $($T:ident),+) => {
         #[allow(non_camel_case_types)]
+        #[derive(Clone)]
         pub struct $struct_name<$($T: Clone),+> {
             $(
                 $T : Option<Packet<$T>>,
@@ -13,7 +26,21 @@ macro_rules! typed_packet {
         }
 
         #[allow(non_camel_case_types)]
-        impl<$($T: Clone),+> PacketSetTrait for $struct_name<$($T),+>  {}
+        impl<$($T: Clone),+> PacketSetTrait for $struct_name<$($T),+>  {
+            fn hold(&mut self, previous: &Self) {
+                $(
+                    if self.$T.is_none() {
+                        self.$T = previous.$T.clone();
+                    }
+                )+
+            }
+
+            fn versions(&self) -> Vec<DataVersion> {
+                [$(
+                    self.$T.as_ref().map(|packet| packet.version),
+                )+].into_iter().flatten().collect()
+            }
+        }
 
         #[allow(non_camel_case_types)]
         unsafe impl<$($T: Clone),+> Send for $struct_name<$($T),+>  {}
@@ -99,4 +126,44 @@ typed_packet!(ReadChannel4PacketSet, c1, c2, c3, c4);
 typed_packet!(ReadChannel5PacketSet, c1, c2, c3, c4, c5);
 typed_packet!(ReadChannel6PacketSet, c1, c2, c3, c4, c5, c6);
 typed_packet!(ReadChannel7PacketSet, c1, c2, c3, c4, c5, c6, c7);
-typed_packet!(ReadChannel8PacketSet, c1, c2, c3, c4, c5, c6, c7, c8);
\ No newline at end of file
+typed_packet!(ReadChannel8PacketSet, c1, c2, c3, c4, c5, c6, c7, c8);
+
+/// The packets matched by a MergeReadChannel, in the order of its channels.
+#[derive(Clone)]
+pub struct MergePacketSet<T: Clone> {
+    packets: Vec<Option<Packet<T>>>,
+}
+
+impl<T: Clone> PacketSetTrait for MergePacketSet<T> {
+    fn hold(&mut self, previous: &Self) {
+        for (packet, previous) in self.packets.iter_mut().zip(previous.packets.iter()) {
+            if packet.is_none() {
+                *packet = previous.clone();
+            }
+        }
+    }
+
+    fn versions(&self) -> Vec<DataVersion> {
+        self.packets
+            .iter()
+            .flatten()
+            .map(|packet| packet.version)
+            .collect()
+    }
+}
+
+impl<T: Clone> MergePacketSet<T> {
+    pub fn new(packets: Vec<Option<Packet<T>>>) -> Self {
+        Self { packets }
+    }
+
+    /// The packet of each channel, None if the channel had no data for the set.
+    pub fn values(&self) -> &[Option<Packet<T>>] {
+        &self.packets
+    }
+
+    /// The packets of the channels with data, in channel order.
+    pub fn into_packets(self) -> Vec<Packet<T>> {
+        self.packets.into_iter().flatten().collect()
+    }
+}
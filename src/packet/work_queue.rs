@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
 
@@ -6,6 +6,8 @@ use crate::{channels::ChannelError, buffers::single_buffers::LenTrait, graph::me
 
 pub struct ReadEvent<T> {
     pub packet_data: T,
+    /// When the packet set was pushed into the queue.
+    pub enqueued_at: Instant,
 }
 
 pub struct WorkQueue<T> {
@@ -57,19 +59,27 @@ impl<T> WorkQueue<T> {
         }
     }
 
-    pub fn push(&mut self, packet_set: T) {
+    /// Queues a packet set, dropping the oldest ones if the queue is full.
+    ///
+    /// * Returns
+    /// The number of packet sets dropped.
+    pub fn push(&mut self, packet_set: T) -> usize {
+        let mut dropped = 0;
         while self.queue.len() >= self.max_in_queue {
             self.queue
                 .recv()
                 .expect("Something is wrong, the work queue is closed.");
             self.monitor.dec();
+            dropped += 1;
         }
         self.notifier
             .send(ReadEvent {
                 packet_data: packet_set,
+                enqueued_at: Instant::now(),
             })
             .expect("Something is wrong, the work queue is closed.");
         self.monitor.inc();
+        dropped
     }
 
     pub fn get(&mut self, timeout: Option<Duration>) -> Result<ReadEvent<T>, ChannelError> {